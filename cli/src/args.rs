@@ -1,5 +1,19 @@
+use crate::interpreter::Interpreter;
+
 #[derive(clap::Parser)]
 pub struct Args {
+    /// Print command results as text or as JSON records
+    #[arg(long, value_enum, default_value = "console", global = true)]
+    pub interpreter: Interpreter,
+
+    /// Execute commands from the file, may be repeated
+    #[arg(short = 'x', long = "command", value_name = "FILE", global = true)]
+    pub scripts: Vec<String>,
+
+    /// Exit after running the command files with the exit status of the program
+    #[arg(long, global = true)]
+    pub batch: bool,
+
     #[command(subcommand)]
     pub commands: Commands,
 }
@@ -11,6 +25,30 @@ pub enum Commands {
         /// Executable file to run
         #[arg(value_name = "FILE")]
         program_path: String,
+
+        /// Arguments passed to the program
+        #[arg(value_name = "ARGS", last = true)]
+        args: Vec<String>,
+
+        /// Environment variable to set for the program
+        #[arg(long = "env", value_name = "KEY=VAL", value_parser = parse_env_var)]
+        env: Vec<(String, String)>,
+
+        /// Working directory of the program
+        #[arg(long, value_name = "DIR")]
+        cwd: Option<String>,
+
+        /// File to redirect program stdin from
+        #[arg(long, value_name = "FILE")]
+        stdin: Option<String>,
+
+        /// File to redirect program stdout to
+        #[arg(long, value_name = "FILE")]
+        stdout: Option<String>,
+
+        /// File to redirect program stderr to
+        #[arg(long, value_name = "FILE")]
+        stderr: Option<String>,
     },
     #[command(about = "attach to process")]
     Attach {
@@ -22,4 +60,48 @@ pub enum Commands {
         #[arg(value_name = "PID")]
         pid: i32,
     },
+    #[command(about = "run program under gdb remote protocol server")]
+    Serve {
+        /// Executable file to run
+        #[arg(value_name = "FILE")]
+        program_path: String,
+
+        /// Arguments passed to the program
+        #[arg(value_name = "ARGS", last = true)]
+        args: Vec<String>,
+
+        /// TCP port on localhost to wait for gdb on
+        #[arg(long, required_unless_present = "socket", conflicts_with = "socket")]
+        port: Option<u16>,
+
+        /// Unix socket to wait for gdb on
+        #[arg(long, value_name = "PATH")]
+        socket: Option<String>,
+    },
+    #[command(about = "debug program through gdb remote protocol stub")]
+    Remote {
+        /// Executable file the stub runs
+        #[arg(value_name = "FILE")]
+        program_path: String,
+
+        /// Address of the stub, like localhost:1234
+        #[arg(value_name = "HOST:PORT")]
+        address: String,
+    },
+    #[command(about = "debug core file dumped by program")]
+    Core {
+        /// Executable file which dumped the core
+        #[arg(value_name = "EXE")]
+        program_path: String,
+
+        /// Core file
+        #[arg(value_name = "CORE")]
+        core_path: String,
+    },
+}
+
+fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    var.split_once('=')
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .ok_or(format!("invalid KEY=VAL: no `=` found in `{}`", var))
 }
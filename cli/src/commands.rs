@@ -1,11 +1,69 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use nix::sys::signal::Signal;
 use rustyline::history::DefaultHistory;
+use serde_json::{json, Value};
+
+use crate::{
+    examine, find, fpview, helper,
+    interpreter::{self, Interpreter},
+    interrupt, regdump,
+};
+
+// scripts sourcing each other stop at this depth
+const MAX_SCRIPT_DEPTH: usize = 8;
+
+/// State the commands share between each other.
+pub struct Session<'l> {
+    launcher: Option<&'l mdbg_rs::Launcher>,
+    out: Interpreter,
+    registers: regdump::RegisterHistory,
+    // status of the last exited program, 128 + signal if it was killed by a signal or stopped by one
+    exit_status: Option<i32>,
+    // commands run when the program stops at the breakpoint with the id
+    breakpoint_commands: HashMap<usize, Vec<String>>,
+    // ids of breakpoints the program stopped at whose commands haven't run yet
+    hit_breakpoints: Vec<usize>,
+    // resolved paths of the scripts being run, the innermost one last
+    scripts: Vec<PathBuf>,
+}
+
+impl<'l> Session<'l> {
+    pub fn new(
+        debugger: &mdbg_rs::Debugger,
+        launcher: Option<&'l mdbg_rs::Launcher>,
+        out: Interpreter,
+    ) -> Session<'l> {
+        let mut registers = regdump::RegisterHistory::default();
+        registers.record_stop(debugger);
 
-use crate::helper;
+        Session {
+            launcher,
+            out,
+            registers,
+            exit_status: None,
+            breakpoint_commands: HashMap::new(),
+            hit_breakpoints: Vec::new(),
+            scripts: Vec::new(),
+        }
+    }
+
+    pub fn exit_status(&self) -> Option<i32> {
+        self.exit_status
+    }
+}
 
 pub fn run_command_loop(
     editor: &mut rustyline::Editor<helper::CliHelper, DefaultHistory>,
     debugger: &mut mdbg_rs::Debugger,
+    session: &mut Session,
 ) -> Result<(), String> {
+    let out = session.out;
     loop {
         let readline = editor.readline("mdbg> ");
         match readline {
@@ -14,18 +72,31 @@ pub fn run_command_loop(
                     .add_history_entry(line.as_str())
                     .map_err(|e| format!("failed to add history entry: {}", e))?;
 
-                if let Some(status) = handle_command(debugger, line)? {
-                    println!("Process exited with status: {}", status);
-                    break;
+                let mut next_line = || match editor.readline(">") {
+                    Ok(line) => Ok(Some(line)),
+                    Err(rustyline::error::ReadlineError::Eof) => Ok(None),
+                    Err(e) => Err(format!("failed to read line: {}", e)),
+                };
+                if let Err(e) = execute(debugger, session, &line, &mut next_line) {
+                    out.error(command_name(&line), &e);
                 }
+                run_breakpoint_commands(debugger, session);
             }
             Err(rustyline::error::ReadlineError::Interrupted) => {
-                println!("CTRL-C");
-                break;
+                if !out.is_json() {
+                    println!("CTRL-C");
+                }
+                if confirm_quit(editor, debugger)? {
+                    break;
+                }
             }
             Err(rustyline::error::ReadlineError::Eof) => {
-                println!("CTRL-D");
-                break;
+                if !out.is_json() {
+                    println!("CTRL-D");
+                }
+                if confirm_quit(editor, debugger)? {
+                    break;
+                }
             }
             Err(e) => Err(format!("failed to read line: {}", e))?,
         }
@@ -34,71 +105,618 @@ pub fn run_command_loop(
     Ok(())
 }
 
-fn handle_command(debugger: &mut mdbg_rs::Debugger, line: String) -> Result<Option<i32>, String> {
+/// Runs commands from the file, it stops at the first failing command like gdb does.
+/// A script can't source itself, directly or through other scripts.
+pub fn source(
+    debugger: &mut mdbg_rs::Debugger,
+    session: &mut Session,
+    path: &str,
+) -> Result<(), String> {
+    let script = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let resolved =
+        fs::canonicalize(path).map_err(|e| format!("failed to resolve {}: {}", path, e))?;
+    if session.scripts.contains(&resolved) {
+        Err(format!("{} is already being sourced", path))?;
+    }
+    if session.scripts.len() >= MAX_SCRIPT_DEPTH {
+        Err(format!(
+            "scripts can be nested at most {} deep",
+            MAX_SCRIPT_DEPTH
+        ))?;
+    }
+
+    session.scripts.push(resolved);
+    let result = run_script(debugger, session, path, &script);
+    session.scripts.pop();
+
+    result
+}
+
+fn run_script(
+    debugger: &mut mdbg_rs::Debugger,
+    session: &mut Session,
+    path: &str,
+    script: &str,
+) -> Result<(), String> {
+    let mut lines = script.lines().enumerate();
+    while let Some((index, line)) = lines.next() {
+        let mut next_line = || Ok(lines.next().map(|(_, line)| line.to_owned()));
+        execute(debugger, session, line, &mut next_line)
+            .map_err(|e| format!("{}:{}: {}", path, index + 1, e))?;
+        run_breakpoint_commands(debugger, session);
+    }
+
+    Ok(())
+}
+
+/// Runs the command line and prints its result, the error is left to the caller.
+/// `next_line` reads the lines following the command, None means the input ended.
+fn execute(
+    debugger: &mut mdbg_rs::Debugger,
+    session: &mut Session,
+    line: &str,
+    next_line: &mut dyn FnMut() -> Result<Option<String>, String>,
+) -> Result<(), String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(());
+    }
+
+    let result = match command_name(line) {
+        "commands" => define_commands(debugger, session, line, next_line),
+        _ => handle_command(debugger, session, line),
+    };
+    print_activated_breakpoints(debugger, session.out);
+    session.out.result(command_name(line), result?);
+
+    Ok(())
+}
+
+/// Handles `commands ID` and the command lines up to `end` after it, an empty list
+/// removes the commands of the breakpoint.
+fn define_commands(
+    debugger: &mdbg_rs::Debugger,
+    session: &mut Session,
+    line: &str,
+    next_line: &mut dyn FnMut() -> Result<Option<String>, String>,
+) -> Result<Value, String> {
+    let id = line
+        .split(' ')
+        .nth(1)
+        .ok_or("breakpoint id is required")?
+        .parse::<usize>()
+        .map_err(|e| format!("failed to parse breakpoint id: {}", e))?;
+    if !debugger.has_breakpoint(id) {
+        Err(format!("no breakpoint number {}", id))?;
+    }
+
+    let mut commands = Vec::new();
+    loop {
+        let line = next_line()?.ok_or("commands aren't terminated with end")?;
+        match line.trim() {
+            "end" => break,
+            line if line.is_empty() || line.starts_with('#') => (),
+            line => commands.push(line.to_owned()),
+        }
+    }
+
+    let result = json!({ "id": id, "commands": commands });
+    match commands.is_empty() {
+        true => session.breakpoint_commands.remove(&id),
+        false => session.breakpoint_commands.insert(id, commands),
+    };
+    Ok(result)
+}
+
+/// Runs commands of the breakpoints the program stopped at. A `continue` among them ends
+/// the list, commands of the breakpoints it stops at run next.
+fn run_breakpoint_commands(debugger: &mut mdbg_rs::Debugger, session: &mut Session) {
+    loop {
+        let hit = std::mem::take(&mut session.hit_breakpoints);
+        if hit.is_empty() {
+            break;
+        }
+
+        let commands: Vec<String> = hit
+            .iter()
+            .filter_map(|id| session.breakpoint_commands.get(id))
+            .flatten()
+            .filter(|command| command.as_str() != "silent")
+            .cloned()
+            .collect();
+        for command in commands {
+            let mut nested = || Err("commands can't be defined inside commands".to_owned());
+            if let Err(e) = execute(debugger, session, &command, &mut nested) {
+                session.out.error(command_name(&command), &e);
+                break;
+            }
+            if command_name(&command) == "continue" {
+                break;
+            }
+        }
+    }
+}
+
+/// Tells if commands of any breakpoint the program stopped at start with `silent`,
+/// then the stop isn't printed.
+fn is_silent(session: &Session) -> bool {
+    session.hit_breakpoints.iter().any(|id| {
+        session
+            .breakpoint_commands
+            .get(id)
+            .and_then(|commands| commands.first())
+            .is_some_and(|command| command == "silent")
+    })
+}
+
+fn command_name(line: &str) -> &str {
+    line.trim().split([' ', '/']).next().unwrap_or_default()
+}
+
+/// Offers to detach from a still running process, so it isn't left with breakpoints in memory.
+fn confirm_quit(
+    editor: &mut rustyline::Editor<helper::CliHelper, DefaultHistory>,
+    debugger: &mut mdbg_rs::Debugger,
+) -> Result<bool, String> {
+    if !debugger.alive() {
+        return Ok(true);
+    }
+
+    let prompt = format!(
+        "Process {} is running. Detach from it and quit? (y or n) ",
+        debugger.program_pid()
+    );
+    let detach = match editor.readline(&prompt) {
+        Ok(answer) => answer.trim() == "y",
+        Err(rustyline::error::ReadlineError::Interrupted) => false,
+        // nobody to answer, so don't leave breakpoints in the process
+        Err(rustyline::error::ReadlineError::Eof) => true,
+        Err(e) => Err(format!("failed to read line: {}", e))?,
+    };
+
+    if detach {
+        debugger
+            .detach()
+            .map_err(|e| format!("failed to detach: {}", e))?;
+    }
+    Ok(detach)
+}
+
+fn handle_command(
+    debugger: &mut mdbg_rs::Debugger,
+    session: &mut Session,
+    line: &str,
+) -> Result<Value, String> {
+    let out = session.out;
     let args = line.split(" ").collect::<Vec<&str>>();
-    let command = args[0];
+    let arg = |index: usize| args.get(index).copied().ok_or("missing argument");
+    // commands like x/4xg take modifier after slash
+    let (command, modifier) = match args[0].split_once('/') {
+        Some((command, modifier)) => (command, Some(modifier)),
+        None => (args[0], None),
+    };
 
-    let mut exit = None;
-    match command {
+    let result = match command {
         "continue" => {
-            exit = debugger
-                .continue_execution()
-                .map_err(|e| format!("failed to continue execution: {}", e))?
+            let reason = interrupt::forward_to_program(debugger.program_pid(), || {
+                debugger.continue_execution()
+            })
+            .map_err(|e| format!("failed to continue execution: {}", e))?;
+            print_activated_breakpoints(debugger, out);
+            session.exit_status = match reason {
+                mdbg_rs::StopReason::Exited(status) => Some(status),
+                // program stopped by a signal dies of it once it's resumed, like a shell sees it
+                mdbg_rs::StopReason::Signaled(signal) | mdbg_rs::StopReason::Signal(signal) => {
+                    Some(128 + signal)
+                }
+                _ => None,
+            };
+            session.hit_breakpoints = match reason {
+                mdbg_rs::StopReason::Breakpoint(addr) => debugger.breakpoints_at(addr),
+                _ => Vec::new(),
+            };
+            // JSON records are for scripts, so they are kept for silent breakpoints
+            if out.is_json() || !is_silent(session) {
+                print_stop(debugger, out, reason)?;
+            }
+            match debugger.alive() {
+                true => session.registers.record_stop(debugger),
+                false => session.registers.clear(),
+            }
+            json!({})
+        }
+        "run" | "restart" => {
+            let launcher = session
+                .launcher
+                .ok_or("restart is supported only for launched programs")?;
+            debugger
+                .restart(launcher)
+                .map_err(|e| format!("failed to restart program: {}", e))?;
+            if !out.is_json() {
+                println!("Starting debugging process {}.", debugger.program_pid());
+            }
+            session.registers.clear();
+            session.registers.record_stop(debugger);
+            session.exit_status = None;
+            json!({ "pid": debugger.program_pid() })
+        }
+        "detach" => {
+            debugger
+                .detach()
+                .map_err(|e| format!("failed to detach: {}", e))?;
+            if !out.is_json() {
+                println!("Detached from process {}.", debugger.program_pid());
+            }
+            json!({ "pid": debugger.program_pid() })
+        }
+        "kill" => {
+            debugger
+                .kill()
+                .map_err(|e| format!("failed to kill program: {}", e))?;
+            if !out.is_json() {
+                println!("Process {} killed.", debugger.program_pid());
+            }
+            json!({ "pid": debugger.program_pid() })
+        }
+        "info" => match args.get(1).copied() {
+            Some("sharedlibrary") => {
+                let mut libraries = Vec::new();
+                for (path, load_bias) in debugger.shared_libraries() {
+                    match out {
+                        Interpreter::Console => println!("{:#018X} {}", load_bias, path),
+                        Interpreter::Json => libraries.push(
+                            json!({ "path": path, "load_bias": interpreter::hex(load_bias) }),
+                        ),
+                    }
+                }
+                json!({ "libraries": libraries })
+            }
+            Some("proc") if args.get(2) == Some(&"mappings") => print_mappings(debugger, out)?,
+            Some("threads") => {
+                let current = debugger.current_thread();
+                let threads = debugger.threads();
+                if !out.is_json() {
+                    for &tid in &threads {
+                        let marker = if tid == current { '*' } else { ' ' };
+                        println!("{} {}", marker, tid);
+                    }
+                }
+                json!({ "current": current, "threads": threads })
+            }
+            _ => Err("unknown info command")?,
+        },
+        "thread" => {
+            if let Some(tid) = args.get(1) {
+                let tid = tid
+                    .parse::<i32>()
+                    .map_err(|e| format!("failed to parse thread id: {}", e))?;
+                debugger.select_thread(tid)?;
+                session.registers.clear();
+                session.registers.record_stop(debugger);
+            }
+            if !out.is_json() {
+                println!("Current thread is {}.", debugger.current_thread());
+            }
+            json!({ "current": debugger.current_thread() })
+        }
+        "gcore" => {
+            let path = match args.get(1) {
+                Some(path) => path.to_string(),
+                None => format!("core.{}", debugger.program_pid()),
+            };
+            debugger
+                .generate_core(&path)
+                .map_err(|e| format!("failed to generate core file: {}", e))?;
+            if !out.is_json() {
+                println!("Saved corefile {}.", path);
+            }
+            json!({ "path": path })
         }
         "break" => {
-            let line = args[2]
+            let line = arg(2)?
                 .parse::<u64>()
                 .map_err(|e| format!("failed to parse source line number: {}", e))?;
-            debugger
+            let id = debugger
                 .set_breakpoint(mdbg_rs::BreakpointRef::Line {
-                    filename: args[1].to_owned(),
+                    filename: arg(1)?.to_owned(),
                     line,
                 })
                 .map_err(|e| format!("failed to set breakpoint: {}", e))?;
+            let addr = debugger.breakpoint_addr(id);
+            if !out.is_json() {
+                match addr {
+                    Some(addr) => println!("Breakpoint {} at {:#X}.", id, addr),
+                    None => println!("Breakpoint {} pending.", id),
+                }
+            }
+            json!({ "id": id, "addr": addr.map(interpreter::hex) })
         }
-        "register" => {
-            match args[1] {
-                "dump" => {
-                    for (reg, val) in debugger
-                        .dump_registers()
-                        .map_err(|e| format!("failed to dump registers: {}", e))?
-                    {
-                        println!("{}: {:#X}", reg, val)
+        "register" => match arg(1)? {
+            "dump" if args.get(2) == Some(&"fp") => {
+                let mut records = Vec::new();
+                for (reg, bytes) in debugger
+                    .dump_fp_registers()
+                    .map_err(|e| format!("failed to dump fp registers: {}", e))?
+                {
+                    match out {
+                        Interpreter::Console => fpview::print_fp_register(&reg, &bytes, None)?,
+                        Interpreter::Json => {
+                            records.push(fpview::fp_register_record(&reg, &bytes, None)?)
+                        }
                     }
                 }
-                "read" => println!(
-                    "{}: {:#X}",
-                    args[2],
-                    debugger
-                        .get_register_value(&mdbg_rs::RegSelector::Name(args[2]))
-                        .map_err(|e| format!("failed to get register value: {}", e))?
-                ),
-                "write" => {
-                    let value = u64::from_str_radix(args[3], 16)
-                        .map_err(|e| format!("failed to parse hex value: {}", e))?;
-                    debugger
-                        .set_register_value(&mdbg_rs::RegSelector::Name(args[2]), value)
-                        .map_err(|e| format!("failed to set value to register: {}", e))?
+                json!({ "registers": records })
+            }
+            "read" if mdbg_rs::RegSelector::Name(arg(2)?).is_fp() => {
+                let bytes = debugger
+                    .get_register_bytes(&mdbg_rs::RegSelector::Name(args[2]))
+                    .map_err(|e| format!("failed to get register value: {}", e))?;
+                match out {
+                    Interpreter::Console => {
+                        fpview::print_fp_register(args[2], &bytes, args.get(3).copied())?;
+                        json!({})
+                    }
+                    Interpreter::Json => {
+                        fpview::fp_register_record(args[2], &bytes, args.get(3).copied())?
+                    }
                 }
-                _ => panic!("wrong command"),
-            };
+            }
+            "write" if mdbg_rs::RegSelector::Name(arg(2)?).is_fp() => {
+                let reg = mdbg_rs::RegSelector::Name(args[2]);
+                let len = debugger
+                    .get_register_bytes(&reg)
+                    .map_err(|e| format!("failed to get register value: {}", e))?
+                    .len();
+                let value = fpview::parse_fp_value(args[2], len, &args[3..])?;
+                debugger
+                    .set_register_bytes(&reg, &value)
+                    .map_err(|e| format!("failed to set value to register: {}", e))?;
+                json!({})
+            }
+            "dump" => regdump::print_registers(debugger, &session.registers, out)?,
+            "read" => {
+                let name = arg(2)?;
+                let value = debugger
+                    .get_register_value(&mdbg_rs::RegSelector::Name(name))
+                    .map_err(|e| format!("failed to get register value: {}", e))?;
+                let mut record = json!({ "name": name, "value": interpreter::hex(value) });
+                if name == debugger.arch().flags_register() {
+                    // single flags are written as one bit registers like zf
+                    let flags = debugger.arch().decode_flags(value);
+                    if !out.is_json() {
+                        println!("{}: {:#X} [ {} ]", name, value, flags.join(" "));
+                    }
+                    record["flags"] = json!(flags);
+                } else if !out.is_json() {
+                    println!("{}: {:#X}", name, value);
+                }
+                record
+            }
+            "write" => {
+                let value = u64::from_str_radix(arg(3)?, 16)
+                    .map_err(|e| format!("failed to parse hex value: {}", e))?;
+                debugger
+                    .set_register_value(&mdbg_rs::RegSelector::Name(arg(2)?), value)
+                    .map_err(|e| format!("failed to set value to register: {}", e))?;
+                json!({})
+            }
+            _ => Err("unknown register command")?,
+        },
+        "source" => {
+            source(debugger, session, arg(1)?)?;
+            json!({})
         }
+        "x" => examine::examine(debugger, out, modifier, args.get(1).copied())?,
+        "find" => find::find(debugger, out, modifier, &args[1..])?,
         "memory" => {
-            let addr = u64::from_str_radix(args[2], 16)
+            let addr = u64::from_str_radix(arg(2)?, 16)
                 .map_err(|e| format!("failed to parse memory address: {}", e))?;
 
-            match args[1] {
-                "read" => println!("{:#X}", debugger.read_memory(addr)?),
+            match arg(1)? {
+                "read" => {
+                    let value = debugger.read_memory(addr)?;
+                    if !out.is_json() {
+                        println!("{:#X}", value);
+                    }
+                    json!({ "addr": interpreter::hex(addr), "value": interpreter::hex(value as u64) })
+                }
                 "write" => {
-                    let value = i64::from_str_radix(args[3], 16)
+                    let value = i64::from_str_radix(arg(3)?, 16)
                         .map_err(|e| format!("failed to parse hex value: {}", e))?;
                     debugger.write_memory(addr, value)?;
+                    json!({})
                 }
-                _ => panic!("wrong command"),
+                _ => Err("unknown memory command")?,
+            }
+        }
+        _ => Err(format!("unknown command `{}`", command))?,
+    };
+
+    Ok(result)
+}
+
+fn print_mappings(debugger: &mdbg_rs::Debugger, out: Interpreter) -> Result<Value, String> {
+    let regions = debugger
+        .memory_regions()
+        .map_err(|e| format!("failed to read memory map: {}", e))?;
+
+    let flag = |set, c| if set { c } else { '-' };
+    let perms = |perms: &mdbg_rs::linux_maps::Permissions| {
+        format!(
+            "{}{}{}{}",
+            flag(perms.read, 'r'),
+            flag(perms.write, 'w'),
+            flag(perms.exec, 'x'),
+            if perms.shared { 's' } else { 'p' }
+        )
+    };
+    if out.is_json() {
+        let records: Vec<Value> = regions
+            .iter()
+            .map(|region| {
+                json!({
+                    "start": interpreter::hex(region.start),
+                    "end": interpreter::hex(region.end),
+                    "offset": interpreter::hex(region.offset),
+                    "perms": perms(&region.perms),
+                    "path": region.pathname,
+                })
+            })
+            .collect();
+        return Ok(json!({ "mappings": records }));
+    }
+
+    println!(
+        "{:>18} {:>18} {:>10} {:>10} Perms  Path",
+        "Start", "End", "Size", "Offset"
+    );
+    for region in regions {
+        println!(
+            "{:#018x} {:#018x} {:#10x} {:#10x} {}   {}",
+            region.start,
+            region.end,
+            region.end - region.start,
+            region.offset,
+            perms(&region.perms),
+            region.pathname.unwrap_or_default()
+        );
+    }
+
+    Ok(json!({}))
+}
+
+fn print_activated_breakpoints(debugger: &mut mdbg_rs::Debugger, out: Interpreter) {
+    for id in debugger.take_activated_breakpoints() {
+        if let Some(addr) = debugger.breakpoint_addr(id) {
+            match out {
+                Interpreter::Console => println!("Breakpoint {} resolved at {:#X}.", id, addr),
+                Interpreter::Json => out.event(
+                    "breakpoint-resolved",
+                    json!({ "id": id, "addr": interpreter::hex(addr) }),
+                ),
+            }
+        }
+    }
+}
+
+/// Describes the dumped process.
+pub fn print_core(core: &mdbg_rs::CoreFile, out: Interpreter) {
+    let signal = Signal::try_from(core.signal()).ok();
+    if out.is_json() {
+        out.event(
+            "core",
+            json!({
+                "command_line": core.command_line(),
+                "signal": core.signal(),
+                "signal_name": signal.map(Signal::as_str),
+            }),
+        );
+        return;
+    }
+
+    if let Some(command_line) = core.command_line() {
+        println!("Core was generated by `{}`.", command_line);
+    }
+    match signal {
+        Some(signal) => println!("Program terminated with signal {}.", signal),
+        None => println!("Program was dumped without a signal."),
+    }
+}
+
+fn print_stop(
+    debugger: &mdbg_rs::Debugger,
+    out: Interpreter,
+    reason: mdbg_rs::StopReason,
+) -> Result<(), String> {
+    let output = debugger.take_program_output();
+    if !output.is_empty() {
+        match out {
+            Interpreter::Console => {
+                let mut stdout = io::stdout();
+                let _ = stdout.write_all(&output).and_then(|_| stdout.flush());
+            }
+            Interpreter::Json => out.event(
+                "output",
+                json!({ "output": String::from_utf8_lossy(&output) }),
+            ),
+        }
+    }
+
+    let (description, record) = match reason {
+        mdbg_rs::StopReason::Exited(status) => {
+            match out {
+                Interpreter::Console => println!("Process exited with status: {}", status),
+                Interpreter::Json => out.event("exited", json!({ "status": status })),
+            }
+            return Ok(());
+        }
+        mdbg_rs::StopReason::Signaled(signal) => {
+            match (out, Signal::try_from(signal)) {
+                (Interpreter::Json, name) => out.event(
+                    "signaled",
+                    json!({ "signal": signal, "signal_name": name.ok().map(Signal::as_str) }),
+                ),
+                (_, Ok(Signal::SIGSEGV)) => println!("Segfault occured."),
+                (_, Ok(signal)) => println!("Program terminated with signal {}.", signal),
+                (_, Err(_)) => println!("Program terminated with signal {}.", signal),
             }
+            return Ok(());
+        }
+        mdbg_rs::StopReason::Breakpoint(addr) => (
+            "Breakpoint hit".to_owned(),
+            json!({ "reason": "breakpoint", "breakpoints": debugger.breakpoints_at(addr) }),
+        ),
+        mdbg_rs::StopReason::Interrupted => (
+            "Program interrupted".to_owned(),
+            json!({ "reason": "interrupted" }),
+        ),
+        mdbg_rs::StopReason::Step => ("Program stopped".to_owned(), json!({ "reason": "step" })),
+        mdbg_rs::StopReason::Signal(signal) => {
+            let name = Signal::try_from(signal).ok().map(Signal::as_str);
+            (
+                format!("Program received signal {}", name.unwrap_or("unknown")),
+                json!({ "reason": "signal", "signal": signal, "signal_name": name }),
+            )
         }
-        _ => panic!("wrong command"),
     };
 
-    Ok(exit)
+    print_location(debugger, out, &description, record)
+}
+
+/// Prints where the program is stopped, in JSON mode `record` becomes a `stopped` event.
+pub fn print_location(
+    debugger: &mdbg_rs::Debugger,
+    out: Interpreter,
+    description: &str,
+    mut record: Value,
+) -> Result<(), String> {
+    let rip = debugger
+        .get_pc()
+        .map_err(|e| format!("failed to get PC register value: {}", e))?;
+    let symbol = debugger.symbolize(rip);
+    let location = debugger
+        .get_source_location(rip)
+        .map_err(|e| format!("failed to get source location: {}", e))?;
+
+    if out.is_json() {
+        record["pc"] = json!(interpreter::hex(rip));
+        record["symbol"] = json!(symbol);
+        record["file"] = json!(location.as_ref().map(|l| &l.filename));
+        record["line"] = json!(location.as_ref().map(|l| l.line));
+        out.event("stopped", record);
+        return Ok(());
+    }
+
+    let symbol = symbol
+        .map(|symbol| format!(" in {}", symbol))
+        .unwrap_or_default();
+    match location {
+        Some(location) => println!(
+            "{} at {:#X}{} ({}:{}).",
+            description, rip, symbol, location.filename, location.line
+        ),
+        None => println!("{} at {:#X}{}.", description, rip, symbol),
+    }
+
+    Ok(())
 }
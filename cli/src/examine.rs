@@ -0,0 +1,382 @@
+use serde_json::{json, Value};
+
+use crate::interpreter::{self, Interpreter};
+
+const ROW_BYTES: usize = 16;
+const MAX_STRING_LEN: usize = 200;
+// gdb-like limit, larger dumps are easier to get from `dump memory`
+const MAX_COUNT: usize = 0x10000;
+const READ_CHUNK_LEN: usize = 0x1000;
+
+struct ExamineFormat {
+    count: usize,
+    format: char,
+    unit: usize,
+}
+
+/// Handles `x/Nfu ADDR`: dumps N units of size u starting from the address in the format f.
+pub fn examine(
+    debugger: &mdbg_rs::Debugger,
+    out: Interpreter,
+    modifier: Option<&str>,
+    addr: Option<&str>,
+) -> Result<Value, String> {
+    let format = parse_format(modifier.unwrap_or_default())?;
+    let addr = parse_address(debugger, addr.ok_or("address is required")?)?;
+
+    match format.format {
+        's' => print_strings(debugger, out, addr, format.count),
+        'i' => print_instructions(debugger, out, addr, format.count),
+        _ => print_units(debugger, out, addr, &format),
+    }
+}
+
+fn parse_format(modifier: &str) -> Result<ExamineFormat, String> {
+    let digits_end = modifier
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(modifier.len());
+    let count = match &modifier[..digits_end] {
+        "" => 1,
+        digits => digits
+            .parse()
+            .map_err(|e| format!("failed to parse count: {}", e))?,
+    };
+    if count > MAX_COUNT {
+        Err(format!("count must be at most {}", MAX_COUNT))?;
+    }
+
+    let mut format = None;
+    let mut unit = None;
+    for c in modifier[digits_end..].chars() {
+        match c {
+            'x' | 'd' | 'u' | 'o' | 'c' | 's' | 'f' | 'i' => format = Some(c),
+            'b' => unit = Some(1),
+            'h' => unit = Some(2),
+            'w' => unit = Some(4),
+            'g' => unit = Some(8),
+            _ => Err(format!("unknown format letter `{}`", c))?,
+        }
+    }
+
+    let format = format.unwrap_or('x');
+    let unit = match (format, unit) {
+        ('c', _) => 1,
+        ('f', None) => 8,
+        ('f', Some(unit)) if unit < 4 => Err("float format requires w or g unit size")?,
+        (_, unit) => unit.unwrap_or(4),
+    };
+
+    Ok(ExamineFormat {
+        count,
+        format,
+        unit,
+    })
+}
+
+/// Parses hex or decimal number, `$register` or a symbol name.
+pub fn parse_address(debugger: &mdbg_rs::Debugger, expr: &str) -> Result<u64, String> {
+    if let Some(reg) = expr.strip_prefix('$') {
+        return debugger
+            .get_register_value(&mdbg_rs::RegSelector::Name(reg))
+            .map_err(|e| format!("failed to get register value: {}", e));
+    }
+    if let Some(hex) = expr.strip_prefix("0x").or(expr.strip_prefix("0X")) {
+        return u64::from_str_radix(hex, 16).map_err(|e| format!("failed to parse address: {}", e));
+    }
+    if let Ok(addr) = expr.parse() {
+        return Ok(addr);
+    }
+
+    debugger
+        .find_symbol_addr(expr)
+        .ok_or(format!("no symbol `{}` in loaded objects", expr))
+}
+
+fn addr_label(debugger: &mdbg_rs::Debugger, addr: u64) -> String {
+    match debugger.symbolize(addr) {
+        Some(symbol) => format!("{:#018x} <{}>", addr, symbol),
+        None => format!("{:#018x}", addr),
+    }
+}
+
+fn print_units(
+    debugger: &mdbg_rs::Debugger,
+    out: Interpreter,
+    addr: u64,
+    format: &ExamineFormat,
+) -> Result<Value, String> {
+    let len = format
+        .count
+        .checked_mul(format.unit)
+        .ok_or("count is too large")?;
+    let row_bytes = if format.format == 'c' { 8 } else { ROW_BYTES };
+    let mut records = Vec::new();
+    let mut offset = 0;
+    // memory is read in chunks of whole rows, printing stops at the first unreadable one
+    while offset < len {
+        let chunk_len = READ_CHUNK_LEN.min(len - offset);
+        let chunk_addr = addr + offset as u64;
+        let bytes = debugger
+            .read_bytes(chunk_addr, chunk_len)
+            .map_err(|e| format!("failed to read memory: {}", e))?;
+
+        print_rows(
+            debugger,
+            out,
+            chunk_addr,
+            &bytes,
+            row_bytes,
+            format,
+            &mut records,
+        );
+        offset += bytes.len();
+        if bytes.len() < chunk_len {
+            break;
+        }
+    }
+
+    if offset < len {
+        Err(format!(
+            "cannot access memory at {:#x}",
+            addr + offset as u64
+        ))?;
+    }
+
+    Ok(json!({ "values": records }))
+}
+
+fn print_rows(
+    debugger: &mdbg_rs::Debugger,
+    out: Interpreter,
+    addr: u64,
+    bytes: &[u8],
+    row_bytes: usize,
+    format: &ExamineFormat,
+    records: &mut Vec<Value>,
+) {
+    for (row, chunk) in bytes.chunks(row_bytes).enumerate() {
+        let row_addr = addr + (row * row_bytes) as u64;
+        let values: Vec<String> = chunk
+            .chunks_exact(format.unit)
+            .map(|unit| format_unit(unit, format.format))
+            .collect();
+
+        if out.is_json() {
+            records.extend(values.iter().enumerate().map(|(index, value)| {
+                let unit_addr = row_addr + (index * format.unit) as u64;
+                json!({ "addr": interpreter::hex(unit_addr), "value": value })
+            }));
+            continue;
+        }
+        if format.format == 'c' {
+            println!("{}:\t{}", addr_label(debugger, row_addr), values.join("\t"));
+            continue;
+        }
+
+        let width = values.iter().map(|v| v.len()).max().unwrap_or(0);
+        let row_units = row_bytes / format.unit;
+        let padding = (row_units - values.len()) * (width + 1);
+        let values: Vec<String> = values
+            .iter()
+            .map(|v| format!("{:>width$}", v, width = width))
+            .collect();
+        println!(
+            "{}:\t{}{:padding$}  |{}|",
+            addr_label(debugger, row_addr),
+            values.join(" "),
+            "",
+            ascii(chunk),
+            padding = padding
+        );
+    }
+}
+
+fn format_unit(bytes: &[u8], format: char) -> String {
+    let mut raw = [0u8; 8];
+    raw[..bytes.len()].copy_from_slice(bytes);
+    let value = u64::from_le_bytes(raw);
+    let bits = bytes.len() * 8;
+    let signed = ((value << (64 - bits)) as i64) >> (64 - bits);
+
+    match format {
+        'x' => format!("0x{:0width$x}", value, width = bytes.len() * 2),
+        'd' => signed.to_string(),
+        'u' => value.to_string(),
+        'o' => format!("0{:o}", value),
+        'f' if bytes.len() == 4 => f32::from_bits(value as u32).to_string(),
+        'f' => f64::from_bits(value).to_string(),
+        'c' => format!("{} '{}'", signed, escape(&[bytes[0]])),
+        _ => unreachable!("format is validated by parser"),
+    }
+}
+
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|&b| std::ascii::escape_default(b))
+        .map(|b| b as char)
+        .collect()
+}
+
+fn print_strings(
+    debugger: &mdbg_rs::Debugger,
+    out: Interpreter,
+    mut addr: u64,
+    count: usize,
+) -> Result<Value, String> {
+    let mut records = Vec::new();
+    for _ in 0..count {
+        let bytes = debugger
+            .read_bytes(addr, MAX_STRING_LEN + 1)
+            .map_err(|e| format!("failed to read memory: {}", e))?;
+        if bytes.is_empty() {
+            Err(format!("cannot access memory at {:#x}", addr))?;
+        }
+
+        let (string, len, terminated) = match bytes.iter().position(|&b| b == 0) {
+            Some(end) => (&bytes[..end], end + 1, true),
+            None => (
+                &bytes[..bytes.len().min(MAX_STRING_LEN)],
+                MAX_STRING_LEN,
+                false,
+            ),
+        };
+        if out.is_json() {
+            records.push(json!({
+                "addr": interpreter::hex(addr),
+                "value": String::from_utf8_lossy(string),
+                "truncated": !terminated,
+            }));
+        } else {
+            println!(
+                "{}:\t\"{}\"{}",
+                addr_label(debugger, addr),
+                escape(string),
+                if terminated { "" } else { "..." }
+            );
+        }
+        addr += len as u64;
+    }
+
+    Ok(json!({ "strings": records }))
+}
+
+fn print_instructions(
+    debugger: &mdbg_rs::Debugger,
+    out: Interpreter,
+    addr: u64,
+    count: usize,
+) -> Result<Value, String> {
+    let pc = debugger.get_pc().ok();
+    let instructions = debugger
+        .disassemble(addr, count)
+        .map_err(|e| format!("failed to disassemble: {}", e))?;
+
+    let mut records = Vec::new();
+    for instruction in &instructions {
+        if out.is_json() {
+            let bytes: String = instruction
+                .bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            records.push(json!({
+                "addr": interpreter::hex(instruction.addr),
+                "symbol": debugger.symbolize(instruction.addr),
+                "bytes": bytes,
+                "text": instruction.text,
+                "current": Some(instruction.addr) == pc,
+            }));
+            continue;
+        }
+
+        let bytes: Vec<String> = instruction
+            .bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        println!(
+            "{} {}:\t{:<30}{}",
+            if Some(instruction.addr) == pc {
+                "=>"
+            } else {
+                "  "
+            },
+            addr_label(debugger, instruction.addr),
+            bytes.join(" "),
+            instruction.text
+        );
+    }
+
+    if instructions.len() < count {
+        let end = instructions
+            .last()
+            .map(|i| i.addr + i.bytes.len() as u64)
+            .unwrap_or(addr);
+        Err(format!("cannot decode instruction at {:#x}", end))?;
+    }
+
+    Ok(json!({ "instructions": records }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(modifier: &str) -> (usize, char, usize) {
+        let format = parse_format(modifier).unwrap();
+        (format.count, format.format, format.unit)
+    }
+
+    #[test]
+    fn defaults_to_one_hex_word() {
+        assert_eq!(parse(""), (1, 'x', 4));
+        assert_eq!(parse("8"), (8, 'x', 4));
+    }
+
+    #[test]
+    fn letters_go_in_any_order() {
+        assert_eq!(parse("4xg"), (4, 'x', 8));
+        assert_eq!(parse("4gx"), (4, 'x', 8));
+        assert_eq!(parse("2db"), (2, 'd', 1));
+        assert_eq!(parse("hu"), (1, 'u', 2));
+    }
+
+    #[test]
+    fn format_picks_its_unit() {
+        assert_eq!(parse("3c"), (3, 'c', 1));
+        assert_eq!(parse("cg"), (1, 'c', 1));
+        assert_eq!(parse("f"), (1, 'f', 8));
+        assert_eq!(parse("fw"), (1, 'f', 4));
+        assert_eq!(parse("10s"), (10, 's', 4));
+        assert_eq!(parse("5i"), (5, 'i', 4));
+    }
+
+    #[test]
+    fn rejects_bad_modifiers() {
+        assert!(parse_format("q").is_err());
+        assert!(parse_format("4x-").is_err());
+        assert!(parse_format("fb").is_err());
+        assert!(parse_format("fh").is_err());
+        assert!(parse_format("99999999999999999999x").is_err());
+    }
+
+    #[test]
+    fn limits_count() {
+        assert_eq!(parse("65536xg"), (MAX_COUNT, 'x', 8));
+        assert!(parse_format("65537xb").is_err());
+    }
+}
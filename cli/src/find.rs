@@ -0,0 +1,156 @@
+use mdbg_rs::{SearchPattern, SearchScope};
+use serde_json::{json, Value};
+
+use crate::{
+    examine,
+    interpreter::{self, Interpreter},
+};
+
+/// Handles `find/FMT [in REGION|START-END] PATTERN`, the pattern is a string by default,
+/// `S` searches UTF-16 string, `x` hex bytes, `b`/`h`/`w`/`g` integer of that size
+/// and `E` makes the integer big-endian.
+pub fn find(
+    debugger: &mdbg_rs::Debugger,
+    out: Interpreter,
+    modifier: Option<&str>,
+    args: &[&str],
+) -> Result<Value, String> {
+    let (scope, args) = match args {
+        ["in", scope, rest @ ..] => (parse_scope(debugger, scope)?, rest),
+        _ => (SearchScope::All, args),
+    };
+    if args.is_empty() {
+        Err("pattern is required")?;
+    }
+    let pattern = parse_pattern(debugger, modifier.unwrap_or_default(), args)?;
+
+    let matches = debugger
+        .search_memory(&scope, &pattern)
+        .map_err(|e| format!("failed to search memory: {}", e))?;
+
+    if out.is_json() {
+        let records: Vec<Value> = matches
+            .iter()
+            .map(|m| {
+                json!({
+                    "addr": interpreter::hex(m.addr),
+                    "symbol": debugger.symbolize(m.addr),
+                    "region": m.region,
+                })
+            })
+            .collect();
+        return Ok(json!({ "matches": records }));
+    }
+
+    for m in &matches {
+        let label = match debugger.symbolize(m.addr) {
+            Some(symbol) => format!("{:#018x} <{}>", m.addr, symbol),
+            None => format!("{:#018x}", m.addr),
+        };
+        println!("{}\t{}", label, m.region.as_deref().unwrap_or("[anon]"));
+    }
+    match matches.len() {
+        0 => println!("Pattern not found."),
+        1 => println!("1 pattern found."),
+        n => println!("{} patterns found.", n),
+    }
+
+    Ok(json!({}))
+}
+
+fn parse_scope(debugger: &mdbg_rs::Debugger, scope: &str) -> Result<SearchScope, String> {
+    match scope.split_once('-') {
+        Some((start, end)) if !scope.starts_with('[') => Ok(SearchScope::Range {
+            start: examine::parse_address(debugger, start)?,
+            end: examine::parse_address(debugger, end)?,
+        }),
+        _ => Ok(SearchScope::Region(scope.to_owned())),
+    }
+}
+
+fn parse_pattern(
+    debugger: &mdbg_rs::Debugger,
+    modifier: &str,
+    args: &[&str],
+) -> Result<SearchPattern, String> {
+    let mut kind = 's';
+    let mut big_endian = false;
+    for c in modifier.chars() {
+        match c {
+            's' | 'S' | 'x' | 'b' | 'h' | 'w' | 'g' => kind = c,
+            'E' => big_endian = true,
+            _ => Err(format!("unknown format letter `{}`", c))?,
+        }
+    }
+
+    let text = args.join(" ");
+    // quotes allow leading and trailing spaces in strings
+    let string = text
+        .strip_prefix('"')
+        .and_then(|t| t.strip_suffix('"'))
+        .unwrap_or(&text)
+        .to_owned();
+
+    let width = match kind {
+        's' => return Ok(SearchPattern::Utf8(string)),
+        'S' => return Ok(SearchPattern::Utf16(string)),
+        'x' => return parse_bytes(&text).map(SearchPattern::Bytes),
+        'b' => 1,
+        'h' => 2,
+        'w' => 4,
+        _ => 8,
+    };
+
+    let value = match text.strip_prefix('-') {
+        Some(abs) => examine::parse_address(debugger, abs)?.wrapping_neg(),
+        None => examine::parse_address(debugger, &text)?,
+    };
+    if width < 8 && value >> (width * 8) != 0 && (value as i64) >> (width * 8 - 1) != -1 {
+        Err(format!("value {} doesn't fit in {} bytes", text, width))?;
+    }
+
+    Ok(SearchPattern::Int {
+        value,
+        width,
+        big_endian,
+    })
+}
+
+/// Parses bytes like `de ad be ef` or `deadbeef`.
+fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.split_whitespace().collect();
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        Err(format!("invalid hex bytes `{}`", text))?;
+    }
+    if !digits.len().is_multiple_of(2) {
+        Err("hex bytes must have even number of digits")?;
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .map_err(|e| format!("failed to parse hex byte: {}", e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_bytes() {
+        assert_eq!(parse_bytes("de ad be ef"), Ok(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(parse_bytes("DEad"), Ok(vec![0xde, 0xad]));
+        assert!(parse_bytes("abc").is_err());
+    }
+
+    #[test]
+    fn rejects_non_hex_bytes() {
+        assert!(parse_bytes("aéb").is_err());
+        assert!(parse_bytes("éé").is_err());
+        assert!(parse_bytes("+1").is_err());
+        assert!(parse_bytes("0x12").is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use serde_json::{json, Value};
+
+// lane views shown when no view is given for vector registers
+const DEFAULT_VIEWS: [&str; 3] = ["f32", "f64", "i32"];
+
+/// Prints the register value in the view like `f32x4` or `i8`, all default views otherwise.
+pub fn print_fp_register(name: &str, bytes: &[u8], view: Option<&str>) -> Result<(), String> {
+    if let Some(view) = view {
+        println!("{}: {}", name, format_view(bytes, view)?);
+        return Ok(());
+    }
+
+    if name.starts_with("st") {
+        println!("{}: {:?} (raw {})", name, f80_to_f64(bytes), hex(bytes));
+    } else if bytes.len() < 16 {
+        println!("{}: {}", name, hex(bytes));
+    } else {
+        println!("{}: {}", name, hex(bytes));
+        for view in DEFAULT_VIEWS {
+            println!(
+                "  {:<8} {}",
+                lanes_name(bytes, view)?,
+                format_view(bytes, view)?
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Describes the register like `print_fp_register` does, lanes of every view are strings.
+pub fn fp_register_record(name: &str, bytes: &[u8], view: Option<&str>) -> Result<Value, String> {
+    let mut record = json!({ "name": name, "raw": hex(bytes) });
+    if name.starts_with("st") && view.is_none() {
+        // NaN and infinity have no JSON number and become null
+        record["value"] = json!(f80_to_f64(bytes));
+    }
+
+    let views = match view {
+        Some(view) => vec![view],
+        None if bytes.len() >= 16 => DEFAULT_VIEWS.to_vec(),
+        None => Vec::new(),
+    };
+    for view in views {
+        record["views"][lanes_name(bytes, view)?] = json!(lanes(bytes, view)?);
+    }
+
+    Ok(record)
+}
+
+/// Parses `[VIEW] VALUE...` into register bytes, a single value without view is
+/// a hex number for vector registers and a float for st registers.
+pub fn parse_fp_value(name: &str, len: usize, args: &[&str]) -> Result<Vec<u8>, String> {
+    match args {
+        [] => Err("value is required")?,
+        [value] if name.starts_with("st") && !value.starts_with("0x") => {
+            let value = value
+                .parse::<f64>()
+                .map_err(|e| format!("failed to parse float value: {}", e))?;
+            Ok(f64_to_f80(value).to_vec())
+        }
+        [value] => {
+            let digits = value.strip_prefix("0x").unwrap_or(value);
+            if digits.is_empty() || digits.len() > len * 2 {
+                Err(format!("hex value must have 1 to {} digits", len * 2))?;
+            }
+            // little-endian bytes from the lowest digits
+            let padded = format!("{:0>width$}", digits, width = len * 2);
+            (0..len)
+                .rev()
+                .map(|i| {
+                    u8::from_str_radix(&padded[i * 2..i * 2 + 2], 16)
+                        .map_err(|e| format!("failed to parse hex value: {}", e))
+                })
+                .collect()
+        }
+        [view, lanes @ ..] => {
+            let (kind, size) = parse_view(view, len)?;
+            if lanes.len() * size != len {
+                Err(format!("{} lanes are required for {}", len / size, name))?;
+            }
+            lanes
+                .iter()
+                .map(|lane| parse_lane(lane, kind, size))
+                .collect::<Result<Vec<_>, _>>()
+                .map(|lanes| lanes.concat())
+        }
+    }
+}
+
+fn format_view(bytes: &[u8], view: &str) -> Result<String, String> {
+    Ok(format!("{{{}}}", lanes(bytes, view)?.join(", ")))
+}
+
+fn lanes(bytes: &[u8], view: &str) -> Result<Vec<String>, String> {
+    let (kind, size) = parse_view(view, bytes.len())?;
+    Ok(bytes
+        .chunks_exact(size)
+        .map(|lane| {
+            let mut raw = [0u8; 8];
+            raw[..size].copy_from_slice(lane);
+            let value = u64::from_le_bytes(raw);
+            let bits = size * 8;
+            match kind {
+                // debug format switches to exponent for tiny and huge values
+                'f' if size == 4 => format!("{:?}", f32::from_bits(value as u32)),
+                'f' => format!("{:?}", f64::from_bits(value)),
+                'i' => (((value << (64 - bits)) as i64) >> (64 - bits)).to_string(),
+                _ => value.to_string(),
+            }
+        })
+        .collect())
+}
+
+fn lanes_name(bytes: &[u8], view: &str) -> Result<String, String> {
+    let (_, size) = parse_view(view, bytes.len())?;
+    Ok(format!("{}x{}", view, bytes.len() / size))
+}
+
+/// Parses view like `f32x4` into element kind and size, the lane count is optional.
+fn parse_view(view: &str, len: usize) -> Result<(char, usize), String> {
+    let (element, count) = match view.split_once('x') {
+        Some((element, count)) => (element, Some(count)),
+        None => (view, None),
+    };
+
+    let (kind, size) = match element {
+        "i8" | "u8" => (element.as_bytes()[0] as char, 1),
+        "i16" | "u16" => (element.as_bytes()[0] as char, 2),
+        "i32" | "u32" | "f32" => (element.as_bytes()[0] as char, 4),
+        "i64" | "u64" | "f64" => (element.as_bytes()[0] as char, 8),
+        _ => Err(format!("unknown view `{}`", view))?,
+    };
+    if !len.is_multiple_of(size) {
+        Err(format!(
+            "view `{}` doesn't fit {} bytes register",
+            view, len
+        ))?;
+    }
+    if let Some(count) = count {
+        let count: usize = count
+            .parse()
+            .map_err(|e| format!("failed to parse lane count: {}", e))?;
+        if count * size != len {
+            Err(format!("register has {} {} lanes", len / size, element))?;
+        }
+    }
+
+    Ok((kind, size))
+}
+
+fn parse_lane(lane: &str, kind: char, size: usize) -> Result<Vec<u8>, String> {
+    let value = match (kind, lane.strip_prefix("0x")) {
+        ('f', _) if size == 4 => lane.parse::<f32>().map(|v| v.to_bits() as u64).ok(),
+        ('f', _) => lane.parse::<f64>().map(|v| v.to_bits()).ok(),
+        (_, Some(hex)) => u64::from_str_radix(hex, 16).ok(),
+        ('i', None) => lane.parse::<i64>().map(|v| v as u64).ok(),
+        (_, None) => lane.parse::<u64>().ok(),
+    };
+
+    value
+        .map(|v| v.to_le_bytes()[..size].to_vec())
+        .ok_or(format!("failed to parse lane `{}`", lane))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let digits: String = bytes.iter().rev().map(|b| format!("{:02x}", b)).collect();
+    format!("0x{}", digits)
+}
+
+/// Converts x87 80-bit extended precision value, precision beyond f64 is lost.
+fn f80_to_f64(bytes: &[u8]) -> f64 {
+    let mut raw = [0u8; 8];
+    raw.copy_from_slice(&bytes[..8]);
+    let mantissa = u64::from_le_bytes(raw);
+    let sign_exp = u16::from_le_bytes([bytes[8], bytes[9]]);
+    let sign = if sign_exp & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = (sign_exp & 0x7fff) as i32;
+
+    let value = match exp {
+        0x7fff if mantissa << 1 == 0 => f64::INFINITY,
+        0x7fff => f64::NAN,
+        // the integer bit is explicit, so denormals need no special handling,
+        // the scale is applied in two halves, so that f64 denormals don't underflow
+        _ => {
+            let scale = exp - 16383;
+            mantissa as f64 * 2f64.powi(-63) * 2f64.powi(scale / 2) * 2f64.powi(scale - scale / 2)
+        }
+    };
+    sign * value
+}
+
+fn f64_to_f80(value: f64) -> [u8; 10] {
+    let bits = value.to_bits();
+    let sign = ((bits >> 63) as u16) << 15;
+    let exp = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = bits & ((1 << 52) - 1);
+
+    let (exp, mantissa) = match exp {
+        0 if fraction == 0 => (0, 0),
+        0 => {
+            // normalize denormal value
+            let shift = fraction.leading_zeros() as i32;
+            (16383 + 63 - shift - 1074, fraction << shift)
+        }
+        0x7ff => (0x7fff, 1 << 63 | fraction << 11),
+        _ => (exp - 1023 + 16383, 1 << 63 | fraction << 11),
+    };
+
+    let mut bytes = [0u8; 10];
+    bytes[..8].copy_from_slice(&mantissa.to_le_bytes());
+    bytes[8..].copy_from_slice(&(sign | exp as u16).to_le_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: f64) -> f64 {
+        f80_to_f64(&f64_to_f80(value))
+    }
+
+    #[test]
+    fn encodes_one() {
+        assert_eq!(f64_to_f80(1.0), [0, 0, 0, 0, 0, 0, 0, 0x80, 0xff, 0x3f]);
+        assert_eq!(f64_to_f80(-2.0), [0, 0, 0, 0, 0, 0, 0, 0x80, 0x00, 0xc0]);
+    }
+
+    #[test]
+    fn round_trips_finite_values() {
+        for value in [
+            0.0,
+            1.0,
+            -2.5,
+            std::f64::consts::PI,
+            1e300,
+            -1e-300,
+            f64::MAX,
+            f64::MIN_POSITIVE,
+        ] {
+            assert_eq!(round_trip(value), value);
+        }
+        assert!(round_trip(-0.0).is_sign_negative());
+    }
+
+    #[test]
+    fn round_trips_denormals() {
+        let smallest = f64::from_bits(1);
+        assert_eq!(round_trip(smallest), smallest);
+        assert_eq!(round_trip(-smallest * 12345.0), -smallest * 12345.0);
+        // normalized into an f80 with explicit integer bit
+        assert_eq!(f64_to_f80(smallest)[7], 0x80);
+    }
+
+    #[test]
+    fn keeps_special_values() {
+        assert_eq!(round_trip(f64::INFINITY), f64::INFINITY);
+        assert_eq!(round_trip(f64::NEG_INFINITY), f64::NEG_INFINITY);
+        assert!(round_trip(f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn saturates_values_out_of_f64_range() {
+        // largest and smallest normal f80 values
+        let max = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe, 0x7f];
+        let min = [0, 0, 0, 0, 0, 0, 0, 0x80, 0x01, 0x80];
+
+        assert_eq!(f80_to_f64(&max), f64::INFINITY);
+        assert_eq!(f80_to_f64(&min), -0.0);
+        assert!(f80_to_f64(&min).is_sign_negative());
+    }
+}
@@ -0,0 +1,664 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    os::unix::{fs::FileExt, net::UnixStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use mdbg_rs::{
+    rsp::{self, Connection, Packet},
+    BreakpointRef, RegSelector, StopReason,
+};
+use nix::{
+    errno::Errno,
+    sys::signal::{self, Signal},
+    unistd::Pid,
+};
+
+// largest packet gdb may send, memory writes are split to fit
+const PACKET_SIZE: usize = 0x4000;
+
+const TARGET_XML_HEADER: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <architecture>i386:x86-64</architecture>
+"#;
+
+const VEC128_TYPE: &str = r#"    <vector id="v4f" type="ieee_single" count="4"/>
+    <vector id="v2d" type="ieee_double" count="2"/>
+    <vector id="v16i8" type="int8" count="16"/>
+    <vector id="v8i16" type="int16" count="8"/>
+    <vector id="v4i32" type="int32" count="4"/>
+    <vector id="v2i64" type="int64" count="2"/>
+    <union id="vec128">
+      <field name="v4_float" type="v4f"/>
+      <field name="v2_double" type="v2d"/>
+      <field name="v16_int8" type="v16i8"/>
+      <field name="v8_int16" type="v8i16"/>
+      <field name="v4_int32" type="v4i32"/>
+      <field name="v2_int64" type="v2i64"/>
+      <field name="uint128" type="uint128"/>
+    </union>
+"#;
+
+/// Socket gdb is connected through, a clone of it watches for interrupts while the program runs.
+pub trait Socket: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> std::io::Result<Self>;
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+}
+
+impl Socket for TcpStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Socket for UnixStream {
+    fn try_clone(&self) -> std::io::Result<Self> {
+        UnixStream::try_clone(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+enum Source {
+    General(&'static str),
+    // value is shorter than the register in the target description
+    Fp(usize),
+    // abridged FXSAVE tag word is expanded to the full x87 one
+    Tag,
+    // debugger has no access to the register
+    Constant(u64),
+}
+
+struct Register {
+    name: String,
+    bits: usize,
+    reg_type: &'static str,
+    feature: &'static str,
+    source: Source,
+}
+
+/// Registers of the x86-64 target description in `g` packet order.
+fn registers() -> Vec<Register> {
+    let reg = |name: &str, bits, reg_type, feature, source| Register {
+        name: name.to_owned(),
+        bits,
+        reg_type,
+        feature,
+        source,
+    };
+    let core = "org.gnu.gdb.i386.core";
+    let sse = "org.gnu.gdb.i386.sse";
+
+    let mut registers = Vec::new();
+    for name in [
+        "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15",
+    ] {
+        let reg_type = match name {
+            "rbp" | "rsp" => "data_ptr",
+            _ => "int64",
+        };
+        registers.push(reg(name, 64, reg_type, core, Source::General(name)));
+    }
+    registers.push(reg("rip", 64, "code_ptr", core, Source::General("rip")));
+    registers.push(reg("eflags", 32, "int32", core, Source::General("eflags")));
+    for name in ["cs", "ss", "ds", "es", "fs", "gs"] {
+        registers.push(reg(name, 32, "int32", core, Source::General(name)));
+    }
+    for i in 0..8 {
+        registers.push(reg(
+            &format!("st{}", i),
+            80,
+            "i387_ext",
+            core,
+            Source::Fp(10),
+        ));
+    }
+    registers.push(reg("fctrl", 32, "int", core, Source::Fp(2)));
+    registers.push(reg("fstat", 32, "int", core, Source::Fp(2)));
+    registers.push(reg("ftag", 32, "int", core, Source::Tag));
+    for name in ["fiseg", "fioff", "foseg", "fooff"] {
+        registers.push(reg(name, 32, "int", core, Source::Constant(0)));
+    }
+    registers.push(reg("fop", 32, "int", core, Source::Fp(2)));
+    for i in 0..16 {
+        registers.push(reg(
+            &format!("xmm{}", i),
+            128,
+            "vec128",
+            sse,
+            Source::Fp(16),
+        ));
+    }
+    registers.push(reg("mxcsr", 32, "int", sse, Source::Fp(4)));
+    // -1 tells gdb the program isn't inside a system call
+    registers.push(reg(
+        "orig_rax",
+        64,
+        "int",
+        "org.gnu.gdb.i386.linux",
+        Source::Constant(u64::MAX),
+    ));
+    registers.push(reg(
+        "fs_base",
+        64,
+        "int",
+        "org.gnu.gdb.i386.segments",
+        Source::General("fsbase"),
+    ));
+    registers.push(reg(
+        "gs_base",
+        64,
+        "int",
+        "org.gnu.gdb.i386.segments",
+        Source::General("gsbase"),
+    ));
+
+    registers
+}
+
+fn target_xml(registers: &[Register]) -> String {
+    let mut xml = TARGET_XML_HEADER.to_owned();
+    let mut feature = "";
+    for (regnum, register) in registers.iter().enumerate() {
+        if register.feature != feature {
+            if !feature.is_empty() {
+                xml.push_str("  </feature>\n");
+            }
+            feature = register.feature;
+            xml.push_str(&format!("  <feature name=\"{}\">\n", feature));
+            if register.reg_type == "vec128" {
+                xml.push_str(VEC128_TYPE);
+            }
+        }
+        xml.push_str(&format!(
+            "    <reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>\n",
+            register.name, register.bits, register.reg_type, regnum
+        ));
+    }
+    xml.push_str("  </feature>\n</target>\n");
+
+    xml
+}
+
+struct Server<'d, 'a, S> {
+    debugger: &'d mut mdbg_rs::Debugger<'a>,
+    connection: Connection<S>,
+    registers: Vec<Register>,
+    target_xml: String,
+    // ids of debugger breakpoints inserted by gdb
+    breakpoints: HashMap<u64, usize>,
+    last_stop: String,
+    // files opened with vFile:open by descriptor
+    files: HashMap<u64, File>,
+    closed: bool,
+}
+
+/// Serves GDB Remote Serial Protocol requests on the connection until gdb kills the program,
+/// detaches or disconnects.
+pub fn serve<S: Socket>(debugger: &mut mdbg_rs::Debugger, socket: S) -> Result<(), String> {
+    if debugger.arch().name() != "x86_64" {
+        Err(format!(
+            "gdb server supports x86_64 programs only, not {}",
+            debugger.arch().name()
+        ))?;
+    }
+
+    let registers = registers();
+    let mut server = Server {
+        debugger,
+        connection: Connection::new(socket),
+        target_xml: target_xml(&registers),
+        registers,
+        breakpoints: HashMap::new(),
+        last_stop: "S05".to_owned(),
+        files: HashMap::new(),
+        closed: false,
+    };
+
+    while !server.closed {
+        let packet = match server.connection.receive()? {
+            Some(Packet::Data(data)) => String::from_utf8_lossy(&data).into_owned(),
+            // program is already stopped
+            Some(Packet::Interrupt) => continue,
+            None => break,
+        };
+
+        let reply = server.handle(&packet).unwrap_or_else(|e| {
+            println!("{}: {}", packet.split(':').next().unwrap_or_default(), e);
+            b"E01".to_vec()
+        });
+        // gdb doesn't wait for a reply to kill
+        if packet != "k" {
+            server.connection.send(&reply)?;
+        }
+        if packet == "QStartNoAckMode" {
+            server.connection.set_no_ack();
+        }
+    }
+
+    Ok(())
+}
+
+impl<S: Socket> Server<'_, '_, S> {
+    fn handle(&mut self, packet: &str) -> Result<Vec<u8>, String> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        Ok(match command {
+            "?" => self.last_stop.clone(),
+            "g" => self.read_registers()?,
+            "G" => {
+                self.write_registers(args)?;
+                "OK".to_owned()
+            }
+            "p" => self.read_register(parse_regnum(args)?)?,
+            "P" => {
+                let (regnum, value) = args.split_once('=').ok_or("missing register value")?;
+                self.write_register(parse_regnum(regnum)?, &rsp::decode_hex(value)?)?;
+                "OK".to_owned()
+            }
+            "m" => {
+                let (addr, len) = parse_range(args)?;
+                // hex encoded reply must fit in a packet, gdb asks for the rest again
+                let len = (len as usize).min(PACKET_SIZE / 2);
+                let data = self.debugger.read_bytes(addr, len)?;
+                if data.is_empty() && len > 0 {
+                    Err(format!("cannot access memory at {:#x}", addr))?;
+                }
+                rsp::encode_hex(&data)
+            }
+            "M" => {
+                let (range, data) = args.split_once(':').ok_or("missing memory data")?;
+                let (addr, len) = parse_range(range)?;
+                let data = rsp::decode_hex(data)?;
+                if data.len() as u64 != len || self.debugger.write_bytes(addr, &data)? < data.len()
+                {
+                    Err(format!("cannot write memory at {:#x}", addr))?;
+                }
+                "OK".to_owned()
+            }
+            "Z" | "z" => self.switch_breakpoint(command == "Z", args)?,
+            "c" | "s" | "C" | "S" => {
+                // `Csig;addr` has the signal before the resume address
+                let (action, addr) = match command {
+                    "c" | "s" => (command, args),
+                    _ => packet.split_once(';').unwrap_or((packet, "")),
+                };
+                let (step, signal) = parse_action(action)?;
+                if !addr.is_empty() {
+                    let pc = RegSelector::Name(self.debugger.arch().pc_register());
+                    self.debugger
+                        .set_register_value(&pc, rsp::parse_hex(addr)?)?;
+                }
+                self.resume(step, signal)?
+            }
+            "v" => return self.handle_v(packet),
+            "q" | "Q" => return self.handle_query(packet),
+            // all threads are the same for a single threaded program
+            "H" | "T" => "OK".to_owned(),
+            "k" => self.kill()?,
+            "D" => {
+                self.debugger.detach()?;
+                self.closed = true;
+                "OK".to_owned()
+            }
+            // empty reply tells gdb the packet isn't supported
+            _ => String::new(),
+        }
+        .into_bytes())
+    }
+
+    fn kill(&mut self) -> Result<String, String> {
+        if self.debugger.alive() {
+            self.debugger.kill()?;
+        }
+        self.closed = true;
+        Ok("OK".to_owned())
+    }
+
+    fn handle_v(&mut self, packet: &str) -> Result<Vec<u8>, String> {
+        if packet == "vCont?" {
+            return Ok(b"vCont;c;C;s;S".to_vec());
+        }
+        if packet.starts_with("vKill") {
+            return Ok(self.kill()?.into_bytes());
+        }
+        if let Some(request) = packet.strip_prefix("vFile:") {
+            return Ok(self.handle_file(request));
+        }
+
+        let reply = match packet.strip_prefix("vCont;") {
+            // actions for other threads can't apply to a single threaded program
+            Some(actions) => {
+                let action = actions.split([';', ':']).next().unwrap_or_default();
+                let (step, signal) = parse_action(action)?;
+                self.resume(step, signal)
+            }
+            None => Ok(String::new()),
+        };
+        reply.map(String::into_bytes)
+    }
+
+    /// Serves host I/O requests, the client reads files like `/proc/<pid>/maps` with them.
+    fn handle_file(&mut self, request: &str) -> Vec<u8> {
+        let (operation, args) = request.split_once(':').unwrap_or((request, ""));
+        let args: Vec<&str> = args.split(',').collect();
+
+        let result = match (operation, args.as_slice()) {
+            ("open", [path, ..]) => rsp::decode_hex(path)
+                .map_err(|_| ErrorKind::InvalidInput.into())
+                .and_then(|path| File::open(String::from_utf8_lossy(&path).as_ref()))
+                .map(|file| {
+                    let fd = (0..).find(|fd| !self.files.contains_key(fd)).unwrap();
+                    self.files.insert(fd, file);
+                    (fd, Vec::new())
+                }),
+            ("pread", [fd, len, offset]) => self.read_file(fd, len, offset),
+            ("close", [fd]) => rsp::parse_hex(fd)
+                .ok()
+                .and_then(|fd| self.files.remove(&fd))
+                .map(|_| (0, Vec::new()))
+                .ok_or(ErrorKind::NotFound.into()),
+            _ => return Vec::new(),
+        };
+
+        match result {
+            Ok((value, data)) => {
+                let mut reply = format!("F{:x};", value).into_bytes();
+                reply.extend(rsp::escape(&data));
+                reply
+            }
+            // errno of the failed call goes after the result
+            Err(e) => {
+                format!("F-1,{:x}", e.raw_os_error().unwrap_or(Errno::EIO as i32)).into_bytes()
+            }
+        }
+    }
+
+    fn read_file(&self, fd: &str, len: &str, offset: &str) -> std::io::Result<(u64, Vec<u8>)> {
+        let parse = |value| rsp::parse_hex(value).map_err(|_| ErrorKind::InvalidInput);
+        let file = self.files.get(&parse(fd)?).ok_or(ErrorKind::NotFound)?;
+
+        let mut data = vec![0; (parse(len)? as usize).min(PACKET_SIZE / 2)];
+        let read = file.read_at(&mut data, parse(offset)?)?;
+        data.truncate(read);
+        Ok((read as u64, data))
+    }
+
+    fn handle_query(&mut self, packet: &str) -> Result<Vec<u8>, String> {
+        let pid = self.debugger.program_pid();
+
+        Ok(match packet.split(':').next().unwrap_or_default() {
+            "qSupported" => format!(
+                "PacketSize={:x};qXfer:features:read+;qXfer:auxv:read+;QStartNoAckMode+;swbreak+;vContSupported+",
+                PACKET_SIZE
+            ),
+            "QStartNoAckMode" => "OK".to_owned(),
+            "qXfer" => {
+                let (object, range) = if let Some(range) =
+                    packet.strip_prefix("qXfer:features:read:target.xml:")
+                {
+                    (self.target_xml.clone().into_bytes(), range)
+                } else if let Some(range) = packet.strip_prefix("qXfer:auxv:read::") {
+                    (self.debugger.auxv()?, range)
+                } else {
+                    Err(format!("unsupported transfer {}", packet))?
+                };
+                let (offset, len) = parse_range(range)?;
+                let start = (offset as usize).min(object.len());
+                let end = start.saturating_add(len as usize).min(object.len());
+                let marker = if end == object.len() { b'l' } else { b'm' };
+
+                let mut reply = vec![marker];
+                reply.extend(rsp::escape(&object[start..end]));
+                return Ok(reply);
+            }
+            // the program was launched by the server, so gdb kills it on quit
+            "qAttached" => "0".to_owned(),
+            "qC" => format!("QC{:x}", pid),
+            "qfThreadInfo" => format!("m{:x}", pid),
+            "qsThreadInfo" => "l".to_owned(),
+            _ => String::new(),
+        }
+        .into_bytes())
+    }
+
+    /// Resumes the program with the signal, the one it stopped with is discarded like gdbserver does.
+    fn resume(&mut self, step: bool, signal: Option<i32>) -> Result<String, String> {
+        self.debugger.set_pending_signal(signal)?;
+        let socket = self
+            .connection
+            .stream()
+            .try_clone()
+            .map_err(|e| format!("failed to clone socket: {}", e))?;
+        let pid = self.debugger.program_pid();
+        let stopped = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let stopped = stopped.clone();
+            thread::spawn(move || watch_interrupt(socket, pid, &stopped))
+        };
+
+        let result = match step {
+            true => self.debugger.step_instruction(),
+            false => self.debugger.continue_execution(),
+        };
+        stopped.store(true, Ordering::SeqCst);
+        watcher
+            .join()
+            .map_err(|_| "interrupt watcher panicked".to_owned())?;
+
+        self.last_stop = match result? {
+            StopReason::Breakpoint(_) => "T05swbreak:;".to_owned(),
+            StopReason::Step => "S05".to_owned(),
+            StopReason::Interrupted => "S02".to_owned(),
+            StopReason::Exited(status) => format!("W{:02x}", status & 0xff),
+            StopReason::Signaled(signal) => format!("X{:02x}", rsp::gdb_signal(signal)),
+            StopReason::Signal(signal) => format!("S{:02x}", rsp::gdb_signal(signal)),
+        };
+        Ok(self.last_stop.clone())
+    }
+
+    fn switch_breakpoint(&mut self, insert: bool, args: &str) -> Result<String, String> {
+        let mut fields = args.split(',');
+        // only software breakpoints are supported
+        if fields.next() != Some("0") {
+            return Ok(String::new());
+        }
+        let addr = rsp::parse_hex(fields.next().ok_or("missing breakpoint address")?)?;
+
+        match (insert, self.breakpoints.get(&addr).copied()) {
+            (true, None) => {
+                let id = self.debugger.set_breakpoint(BreakpointRef::Addr(addr))?;
+                self.breakpoints.insert(addr, id);
+            }
+            (false, Some(id)) => {
+                self.debugger.delete_breakpoint(id)?;
+                self.breakpoints.remove(&addr);
+            }
+            // gdb may repeat the request after reconnecting
+            _ => (),
+        }
+
+        Ok("OK".to_owned())
+    }
+
+    fn read_registers(&self) -> Result<String, String> {
+        (0..self.registers.len())
+            .map(|regnum| self.read_register(regnum))
+            .collect()
+    }
+
+    fn write_registers(&mut self, hex: &str) -> Result<(), String> {
+        let data = rsp::decode_hex(hex)?;
+        let mut offset = 0;
+        for regnum in 0..self.registers.len() {
+            let len = self.registers[regnum].bits / 8;
+            // gdb may send fewer registers than it knows about
+            let value = match data.get(offset..offset + len) {
+                Some(value) => value,
+                None => break,
+            };
+            self.write_register(regnum, value)?;
+            offset += len;
+        }
+
+        Ok(())
+    }
+
+    fn read_register(&self, regnum: usize) -> Result<String, String> {
+        let register = self
+            .registers
+            .get(regnum)
+            .ok_or(format!("no register {}", regnum))?;
+        let len = register.bits / 8;
+
+        let mut value = match register.source {
+            Source::General(name) => self
+                .debugger
+                .get_register_value(&RegSelector::Name(name))?
+                .to_le_bytes()
+                .to_vec(),
+            Source::Fp(_) => self
+                .debugger
+                .get_register_bytes(&RegSelector::Name(&register.name))?,
+            Source::Tag => {
+                let abridged = self
+                    .debugger
+                    .get_register_bytes(&RegSelector::Name("ftag"))?[0];
+                // empty registers are tagged 0b11, others are reported as valid
+                let tag = (0..8)
+                    .filter(|i| abridged & 1 << i == 0)
+                    .fold(0u16, |tag, i| tag | 0b11 << (i * 2));
+                tag.to_le_bytes().to_vec()
+            }
+            Source::Constant(value) => value.to_le_bytes().to_vec(),
+        };
+        value.resize(len, 0);
+
+        Ok(rsp::encode_hex(&value))
+    }
+
+    fn write_register(&mut self, regnum: usize, value: &[u8]) -> Result<(), String> {
+        let register = self
+            .registers
+            .get(regnum)
+            .ok_or(format!("no register {}", regnum))?;
+
+        match register.source {
+            Source::General(name) => {
+                let mut raw = [0u8; 8];
+                let len = value.len().min(raw.len());
+                raw[..len].copy_from_slice(&value[..len]);
+                self.debugger
+                    .set_register_value(&RegSelector::Name(name), u64::from_le_bytes(raw))
+            }
+            Source::Fp(len) => {
+                let value = value.get(..len).ok_or("register value is too short")?;
+                let name = register.name.clone();
+                self.debugger
+                    .set_register_bytes(&RegSelector::Name(&name), value)
+            }
+            Source::Tag => {
+                let tag = u16::from_le_bytes([value[0], *value.get(1).unwrap_or(&0)]);
+                let abridged = (0..8)
+                    .filter(|i| (tag >> (i * 2)) & 0b11 != 0b11)
+                    .fold(0u8, |abridged, i| abridged | 1 << i);
+                self.debugger
+                    .set_register_bytes(&RegSelector::Name("ftag"), &[abridged])
+            }
+            // gdb writes back everything it read, values it can't change are ignored
+            Source::Constant(_) => Ok(()),
+        }
+    }
+}
+
+/// Stops the program with SIGINT when gdb sends an interrupt, until the program stops by itself.
+fn watch_interrupt<S: Socket>(mut socket: S, pid: i32, stopped: &AtomicBool) {
+    // short timeout lets the thread notice the stop
+    if socket
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .is_err()
+    {
+        return;
+    }
+
+    let mut byte = [0u8; 1];
+    while !stopped.load(Ordering::SeqCst) {
+        match socket.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) if byte[0] == rsp::INTERRUPT => {
+                let _ = signal::kill(Pid::from_raw(pid), Signal::SIGINT);
+            }
+            Ok(_) => (),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => (),
+            Err(_) => break,
+        }
+    }
+
+    // the timeout is shared with the connection socket
+    let _ = socket.set_read_timeout(None);
+}
+
+/// Parses `c`, `s`, `Csig` or `Ssig` action into the step flag and the Linux signal to deliver.
+fn parse_action(action: &str) -> Result<(bool, Option<i32>), String> {
+    let (kind, signal) = action.split_at(action.chars().next().map_or(0, char::len_utf8));
+    let signal = match kind {
+        "c" | "s" if signal.is_empty() => None,
+        "C" | "S" => {
+            let gdb = rsp::parse_hex(signal)?;
+            let linux = u8::try_from(gdb).ok().and_then(rsp::linux_signal);
+            Some(linux.ok_or(format!("unknown signal {}", gdb))?)
+        }
+        _ => Err(format!("unsupported action {}", action))?,
+    };
+
+    Ok((kind.eq_ignore_ascii_case("s"), signal))
+}
+
+fn parse_regnum(value: &str) -> Result<usize, String> {
+    Ok(rsp::parse_hex(value)? as usize)
+}
+
+/// Parses `addr,length` pair of hex numbers.
+fn parse_range(value: &str) -> Result<(u64, u64), String> {
+    let (addr, len) = value
+        .split_once(',')
+        .ok_or(format!("invalid range {}", value))?;
+    Ok((rsp::parse_hex(addr)?, rsp::parse_hex(len)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_resume_actions() {
+        assert_eq!(parse_action("c").unwrap(), (false, None));
+        assert_eq!(parse_action("s").unwrap(), (true, None));
+        assert_eq!(parse_action("C0b").unwrap(), (false, Some(11)));
+        // GDB numbers differ from Linux ones, SIGSTOP is 17 in GDB and 19 in Linux
+        assert_eq!(parse_action("S11").unwrap(), (true, Some(19)));
+
+        assert!(parse_action("C").is_err());
+        assert!(parse_action("c0b").is_err());
+        assert!(parse_action("Cfff").is_err());
+        assert!(parse_action("t").is_err());
+        assert!(parse_action("").is_err());
+    }
+}
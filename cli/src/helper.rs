@@ -39,7 +39,7 @@ impl Completer for CliHelper {
         let prefix =
             rustyline::completion::longest_common_prefix(&candidates).map(|s| s.to_owned());
         if let Some(prefix) = prefix {
-            if prefix != "" {
+            if !prefix.is_empty() {
                 candidates.clear();
                 candidates.push(Pair {
                     display: prefix.clone(),
@@ -0,0 +1,50 @@
+use serde_json::{json, Value};
+
+/// How the command loop talks to its user.
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Interpreter {
+    /// Text meant for people
+    Console,
+    /// One JSON record per line meant for scripts
+    Json,
+}
+
+impl Interpreter {
+    pub fn is_json(self) -> bool {
+        self == Interpreter::Json
+    }
+
+    /// Reports the result of a finished command, commands print console text themselves.
+    pub fn result(self, command: &str, result: Value) {
+        if self.is_json() {
+            print_record(json!({ "type": "result", "command": command, "result": result }));
+        }
+    }
+
+    pub fn error(self, command: &str, message: &str) {
+        match self {
+            Interpreter::Console => println!("{}", message),
+            Interpreter::Json => {
+                print_record(json!({ "type": "error", "command": command, "message": message }))
+            }
+        }
+    }
+
+    /// Reports something that happened to the program, like a stop or a resolved breakpoint.
+    pub fn event(self, event: &str, mut body: Value) {
+        if self.is_json() {
+            body["type"] = json!("event");
+            body["event"] = json!(event);
+            print_record(body);
+        }
+    }
+}
+
+/// Addresses and register values are hex strings, JSON numbers lose precision above 2^53.
+pub fn hex(value: u64) -> String {
+    format!("{:#x}", value)
+}
+
+fn print_record(record: Value) {
+    println!("{}", record);
+}
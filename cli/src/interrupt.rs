@@ -0,0 +1,83 @@
+use std::{
+    io::IsTerminal,
+    os::fd::AsRawFd,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use nix::{
+    sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, SigmaskHow, Signal},
+    unistd::{self, Pid},
+};
+
+static INFERIOR_PID: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn handle_sigint(_: i32) {
+    let pid = INFERIOR_PID.load(Ordering::SeqCst);
+    if pid > 0 {
+        // stopped program is reported by waitpid, so the debugger gets control back
+        let _ = signal::kill(Pid::from_raw(pid), Signal::SIGSTOP);
+    }
+}
+
+pub fn install_handler() -> Result<(), String> {
+    // restart waitpid after handler returns, program stop is reported by it
+    let action = SigAction::new(
+        SigHandler::Handler(handle_sigint),
+        SaFlags::SA_RESTART,
+        SigSet::empty(),
+    );
+
+    // SAFETY: handler only reads an atomic and calls kill, both are async-signal-safe.
+    unsafe {
+        signal::sigaction(Signal::SIGINT, &action)
+            .map_err(|e| format!("failed to set SIGINT handler: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Turns Ctrl-C into a stop of the program with the given pid while `action` runs.
+/// The program owns the terminal meanwhile, so it can read from it and Ctrl-C reaches it
+/// directly, the tracer sees the SIGINT as a stop.
+pub fn forward_to_program<T, F: FnOnce() -> T>(pid: i32, action: F) -> T {
+    INFERIOR_PID.store(pid, Ordering::SeqCst);
+    let debugger_group = hand_terminal_to(pid);
+    let result = action();
+    if let Some(group) = debugger_group {
+        take_terminal_back(group);
+    }
+    INFERIOR_PID.store(0, Ordering::SeqCst);
+
+    result
+}
+
+/// Makes the process group of the program the foreground one, returns the previous group.
+/// Nothing changes if stdin isn't a terminal or the program is in another session.
+fn hand_terminal_to(pid: i32) -> Option<Pid> {
+    let stdin = std::io::stdin();
+    if !stdin.is_terminal() {
+        return None;
+    }
+
+    let fd = stdin.as_raw_fd();
+    let debugger_group = unistd::tcgetpgrp(fd).ok()?;
+    let program_group = unistd::getpgid(Some(Pid::from_raw(pid))).ok()?;
+    if program_group == debugger_group {
+        return None;
+    }
+    unistd::tcsetpgrp(fd, program_group).ok()?;
+
+    Some(debugger_group)
+}
+
+fn take_terminal_back(group: Pid) {
+    // background process changing the foreground group gets SIGTTOU unless it's blocked
+    let mut ttou = SigSet::empty();
+    ttou.add(Signal::SIGTTOU);
+    let mut previous = SigSet::empty();
+    if signal::sigprocmask(SigmaskHow::SIG_BLOCK, Some(&ttou), Some(&mut previous)).is_err() {
+        return;
+    }
+    let _ = unistd::tcsetpgrp(std::io::stdin().as_raw_fd(), group);
+    let _ = signal::sigprocmask(SigmaskHow::SIG_SETMASK, Some(&previous), None);
+}
@@ -1,67 +1,300 @@
 mod args;
 mod commands;
+mod examine;
+mod find;
+mod fpview;
+mod gdbserver;
 mod helper;
+mod interpreter;
+mod interrupt;
+mod regdump;
 
-use std::{env::var, ffi::CString, fs::create_dir_all, path::Path, ptr};
+use std::{
+    env::var,
+    fs::{self, create_dir_all},
+    net::TcpListener,
+    os::unix::net::UnixListener,
+    path::{Path, PathBuf},
+    process::exit,
+};
 
 use args::{Args, Commands};
 use clap::Parser;
-use nix::{
-    libc::execl,
-    sys::ptrace,
-    unistd::{fork, ForkResult, Pid},
-};
+use interpreter::Interpreter;
+use nix::{sys::ptrace, unistd::Pid};
 use rustyline::history::DefaultHistory;
+use serde_json::{json, Value};
 
 use crate::commands::run_command_loop;
 
+/// Options of the command loop, they apply to every way of starting it.
+struct Frontend {
+    out: Interpreter,
+    scripts: Vec<String>,
+    batch: bool,
+}
+
 fn main() {
     parse_args().unwrap();
 }
 
 fn parse_args() -> Result<(), String> {
     let args = Args::parse();
+    let frontend = Frontend {
+        out: args.interpreter,
+        scripts: args.scripts,
+        batch: args.batch,
+    };
     match args.commands {
-        Commands::Run { program_path } => run(program_path),
-        Commands::Attach { program_path, pid } => attach(program_path, pid),
-    }
-}
-
-fn run(program_path: String) -> Result<(), String> {
-    // SAFETY: Call in single thread.
-    match unsafe { fork() } {
-        Ok(ForkResult::Parent { child }) => init_debugger(program_path, child.as_raw())?,
-        Ok(ForkResult::Child) => {
-            ptrace::traceme().expect("failed to run traceme");
-            let c_path = CString::new(program_path.clone()).unwrap();
-            let c_path_arg = CString::new(program_path).unwrap();
-
-            // SAFETY: there isn't aliased pointers.
-            unsafe {
-                execl(c_path.as_ptr(), c_path_arg.as_ptr(), ptr::null::<i8>());
+        Commands::Run {
+            program_path,
+            args,
+            env,
+            cwd,
+            stdin,
+            stdout,
+            stderr,
+        } => {
+            let mut launcher = mdbg_rs::Launcher::new(&program_path).args(args);
+            for (key, value) in env {
+                launcher = launcher.env(&key, &value);
             }
+            if let Some(cwd) = cwd {
+                launcher = launcher.cwd(&cwd);
+            }
+            if let Some(stdin) = stdin {
+                launcher = launcher.stdin(&stdin);
+            }
+            if let Some(stdout) = stdout {
+                launcher = launcher.stdout(&stdout);
+            }
+            if let Some(stderr) = stderr {
+                launcher = launcher.stderr(&stderr);
+            }
+
+            run(launcher, &frontend)
         }
-        Err(e) => Err(format!("failed to fork process: {}", e))?,
+        Commands::Attach { program_path, pid } => attach(program_path, pid, &frontend),
+        Commands::Serve {
+            program_path,
+            args,
+            port,
+            socket,
+        } => serve(
+            mdbg_rs::Launcher::new(&program_path).args(args),
+            port,
+            socket,
+            frontend.out,
+        ),
+        Commands::Remote {
+            program_path,
+            address,
+        } => remote(program_path, address, &frontend),
+        Commands::Core {
+            program_path,
+            core_path,
+        } => core(program_path, core_path, &frontend),
     }
+}
 
-    Ok(())
+fn run(launcher: mdbg_rs::Launcher, frontend: &Frontend) -> Result<(), String> {
+    let pid = launcher
+        .launch()
+        .map_err(|e| format!("failed to launch program: {}", e))?;
+    init_debugger(
+        launcher.program_path().to_owned(),
+        pid,
+        Some(&launcher),
+        frontend,
+    )
 }
 
-fn attach(program_path: String, pid: i32) -> Result<(), String> {
+fn attach(program_path: String, pid: i32, frontend: &Frontend) -> Result<(), String> {
     ptrace::attach(Pid::from_raw(pid)).map_err(|e| format!("failed to attach to process {}", e))?;
-    init_debugger(program_path, pid)
+    init_debugger(program_path, pid, None, frontend)
+}
+
+fn init_debugger(
+    program_path: String,
+    pid: i32,
+    launcher: Option<&mdbg_rs::Launcher>,
+    frontend: &Frontend,
+) -> Result<(), String> {
+    let mut debugger = load_process(&program_path, pid, frontend.out)?;
+
+    interrupt::install_handler()?;
+    start_editor(&mut debugger, launcher, frontend)
 }
 
-fn init_debugger(program_path: String, pid: i32) -> Result<(), String> {
-    let mut debugger = mdbg_rs::load_in_memory(pid, &program_path)?;
+/// Waits for the traced process to stop and loads the executable and shared libraries.
+fn load_process(
+    program_path: &str,
+    pid: i32,
+    out: Interpreter,
+) -> Result<mdbg_rs::Debugger<'static>, String> {
+    let mut debugger = mdbg_rs::load_in_memory(pid, program_path)?;
 
     debugger
         .wait_attach()
         .map_err(|e| format!("failed to wait trap: {}", e))?;
-    println!("Starting debugging process {}.", pid);
-    let load_addr = mdbg_rs::linux_maps::get_load_addr(pid, &program_path)
+    match out {
+        Interpreter::Console => println!("Starting debugging process {}.", pid),
+        Interpreter::Json => out.event("started", json!({ "pid": pid })),
+    }
+    let load_addr = mdbg_rs::linux_maps::get_load_addr(pid, program_path)
+        .map_err(|e| format!("failed to get load addr: {}", e))?;
+    debugger
+        .set_load_addr(load_addr)
+        .map_err(|e| format!("failed to set load addr: {}", e))?;
+    debugger
+        .track_shared_libraries()
+        .map_err(|e| format!("failed to track shared libraries: {}", e))?;
+
+    Ok(debugger)
+}
+
+/// Launches the program and lets a single gdb connection control it.
+fn serve(
+    launcher: mdbg_rs::Launcher,
+    port: Option<u16>,
+    socket: Option<String>,
+    out: Interpreter,
+) -> Result<(), String> {
+    let pid = launcher
+        .launch()
+        .map_err(|e| format!("failed to launch program: {}", e))?;
+    let mut debugger = load_process(launcher.program_path(), pid, out)?;
+
+    let result = match (port, socket) {
+        (Some(port), _) => TcpListener::bind(("127.0.0.1", port))
+            .map_err(|e| format!("failed to listen on port {}: {}", port, e))
+            .and_then(|listener| {
+                announce(
+                    out,
+                    &format!("Listening on port {}.", port),
+                    "listening",
+                    json!({ "port": port }),
+                );
+                let (stream, addr) = listener
+                    .accept()
+                    .map_err(|e| format!("failed to accept connection: {}", e))?;
+                announce(
+                    out,
+                    &format!("Remote debugging from {}.", addr),
+                    "connected",
+                    json!({ "address": addr.to_string() }),
+                );
+                gdbserver::serve(&mut debugger, stream)
+            }),
+        (None, Some(path)) => {
+            let result = UnixListener::bind(&path)
+                .map_err(|e| format!("failed to listen on {}: {}", path, e))
+                .and_then(|listener| {
+                    announce(
+                        out,
+                        &format!("Listening on {}.", path),
+                        "listening",
+                        json!({ "socket": path }),
+                    );
+                    let (stream, _) = listener
+                        .accept()
+                        .map_err(|e| format!("failed to accept connection: {}", e))?;
+                    gdbserver::serve(&mut debugger, stream)
+                });
+            let _ = fs::remove_file(&path);
+            result
+        }
+        (None, None) => Err("either port or socket is required".to_owned()),
+    };
+
+    // program isn't left stopped with breakpoints after gdb disconnects
+    if debugger.alive() {
+        debugger
+            .kill()
+            .map_err(|e| format!("failed to kill program: {}", e))?;
+    }
+    result
+}
+
+/// Prints the message for people or reports the event in JSON mode.
+fn announce(out: Interpreter, message: &str, event: &str, body: Value) {
+    match out {
+        Interpreter::Console => println!("{}", message),
+        Interpreter::Json => out.event(event, body),
+    }
+}
+
+fn remote(program_path: String, address: String, frontend: &Frontend) -> Result<(), String> {
+    let mut debugger = mdbg_rs::load_remote(&program_path, &address)
+        .map_err(|e| format!("failed to connect to remote stub: {}", e))?;
+    announce(
+        frontend.out,
+        &format!("Remote debugging using {}.", address),
+        "started",
+        json!({ "pid": debugger.program_pid(), "address": address }),
+    );
+    let load_addr = debugger
+        .executable_load_addr()
         .map_err(|e| format!("failed to get load addr: {}", e))?;
-    debugger.set_load_addr(load_addr);
+    debugger
+        .set_load_addr(load_addr)
+        .map_err(|e| format!("failed to set load addr: {}", e))?;
+    debugger
+        .track_shared_libraries()
+        .map_err(|e| format!("failed to track shared libraries: {}", e))?;
+
+    // Ctrl-C isn't forwarded, the program may run on another machine
+    start_editor(&mut debugger, None, frontend)
+}
+
+fn core(program_path: String, core_path: String, frontend: &Frontend) -> Result<(), String> {
+    let core = mdbg_rs::CoreFile::load(&core_path)
+        .map_err(|e| format!("failed to load core file: {}", e))?;
+    commands::print_core(&core, frontend.out);
+    let mut debugger = mdbg_rs::load_core(&program_path, core)
+        .map_err(|e| format!("failed to load core file: {}", e))?;
+    commands::print_location(
+        &debugger,
+        frontend.out,
+        "Thread stopped",
+        json!({ "reason": "core" }),
+    )?;
+
+    start_editor(&mut debugger, None, frontend)
+}
+
+fn start_editor(
+    debugger: &mut mdbg_rs::Debugger,
+    launcher: Option<&mdbg_rs::Launcher>,
+    frontend: &Frontend,
+) -> Result<(), String> {
+    let mut session = commands::Session::new(debugger, launcher, frontend.out);
+    let mut failed = false;
+    for path in startup_scripts()
+        .into_iter()
+        .chain(frontend.scripts.clone())
+    {
+        if let Err(e) = commands::source(debugger, &mut session, &path) {
+            frontend.out.error("source", &e);
+            failed = true;
+        }
+    }
+
+    if frontend.batch {
+        // attached and remote programs are left running, launched ones end with the debugger
+        if debugger.alive() {
+            match launcher {
+                Some(_) => debugger.kill(),
+                None => debugger.detach(),
+            }
+            .map_err(|e| format!("failed to release program: {}", e))?;
+        }
+        // failed script makes the status non-zero even if the program succeeded
+        match session.exit_status() {
+            Some(status) if status != 0 => exit(status),
+            _ => exit(failed as i32),
+        }
+    }
 
     let mut editor = rustyline::Editor::<helper::CliHelper, DefaultHistory>::new()
         .map_err(|e| format!("failed to create editor: {}", e))?;
@@ -69,12 +302,26 @@ fn init_debugger(program_path: String, pid: i32) -> Result<(), String> {
     editor.set_helper(Some(helper::CliHelper::new(
         vec![
             "continue",
+            "run",
+            "restart",
+            "detach",
+            "kill",
+            "info sharedlibrary",
+            "info proc mappings",
+            "info threads",
+            "thread",
+            "gcore",
             "break",
             "register dump",
+            "register dump fp",
             "register read",
             "register write",
             "memory read",
             "memory write",
+            "x",
+            "find",
+            "source",
+            "commands",
         ]
         .into_iter()
         .map(|c| c.to_owned())
@@ -89,16 +336,29 @@ fn init_debugger(program_path: String, pid: i32) -> Result<(), String> {
     });
     if let Some(history_path) = history_path {
         let _ = editor.load_history(&history_path);
-        run_command_loop(&mut editor, &mut debugger)?;
+        run_command_loop(&mut editor, debugger, &mut session)?;
         let parent = history_path.parent().unwrap();
-        create_dir_all(&parent)
+        create_dir_all(parent)
             .map_err(|e| format!("failed to create directory to save command history: {}", e))?;
         editor
             .save_history(&history_path)
             .map_err(|e| format!("failed to save history: {}", e))?;
     } else {
-        run_command_loop(&mut editor, &mut debugger)?;
+        run_command_loop(&mut editor, debugger, &mut session)?;
     }
 
     Ok(())
 }
+
+/// Returns `~/.mdbgrc` and `./.mdbginit` which exist, they run before `-x` scripts.
+fn startup_scripts() -> Vec<String> {
+    let home_script = var("HOME")
+        .ok()
+        .map(|home| Path::new(&home).join(".mdbgrc"));
+    home_script
+        .into_iter()
+        .chain([PathBuf::from(".mdbginit")])
+        .filter(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect()
+}
@@ -0,0 +1,97 @@
+use std::io::IsTerminal;
+
+use serde_json::{json, Value};
+
+use crate::interpreter::{self, Interpreter};
+
+const CHANGED_COLOR: &str = "\x1b[1;31m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// Register values at the last two stops, used to highlight changes in `register dump`.
+#[derive(Default)]
+pub struct RegisterHistory {
+    previous: Vec<(String, u64)>,
+    current: Vec<(String, u64)>,
+}
+
+impl RegisterHistory {
+    pub fn record_stop(&mut self, debugger: &mdbg_rs::Debugger) {
+        self.previous = std::mem::take(&mut self.current);
+        self.current = debugger.dump_registers().unwrap_or_default();
+    }
+
+    /// Forgets values of a process which no longer exists.
+    pub fn clear(&mut self) {
+        self.previous.clear();
+        self.current.clear();
+    }
+
+    fn changed(&self, name: &str, value: u64) -> bool {
+        self.previous
+            .iter()
+            .find(|(n, _)| n == name)
+            .is_some_and(|(_, previous)| *previous != value)
+    }
+}
+
+/// Prints general purpose registers one per line with symbols or mappings for pointers,
+/// registers changed since the previous stop are marked.
+pub fn print_registers(
+    debugger: &mdbg_rs::Debugger,
+    history: &RegisterHistory,
+    out: Interpreter,
+) -> Result<Value, String> {
+    let regs = debugger
+        .dump_registers()
+        .map_err(|e| format!("failed to dump registers: {}", e))?;
+    // annotation is best effort, so missing maps only hide it
+    let regions = debugger.memory_regions().unwrap_or_default();
+    let color = std::io::stdout().is_terminal();
+
+    // 0x prefix and two digits per byte
+    let width = 2 + debugger.arch().pointer_size() * 2;
+    let mut records = Vec::new();
+    for (name, value) in regs {
+        let annotation = annotation(debugger, &regions, &name, value);
+        let changed = history.changed(&name, value);
+        if out.is_json() {
+            records.push(json!({
+                "name": name,
+                "value": interpreter::hex(value),
+                "annotation": annotation,
+                "changed": changed,
+            }));
+            continue;
+        }
+
+        let hex = format!("{:#0width$x}", value, width = width);
+        let (name, hex) = match changed {
+            true if color => (name, format!("{}{}{}", CHANGED_COLOR, hex, RESET_COLOR)),
+            // without colors the name is marked
+            true => (format!("{}*", name), hex),
+            false => (name, hex),
+        };
+        println!("{:<8}{}  {}", name, hex, annotation);
+    }
+
+    Ok(json!({ "registers": records }))
+}
+
+fn annotation(
+    debugger: &mdbg_rs::Debugger,
+    regions: &[mdbg_rs::linux_maps::MemoryRegion],
+    name: &str,
+    value: u64,
+) -> String {
+    if name == debugger.arch().flags_register() {
+        return format!("[ {} ]", debugger.arch().decode_flags(value).join(" "));
+    }
+    if let Some(symbol) = debugger.symbolize(value) {
+        return format!("<{}>", symbol);
+    }
+
+    match regions.iter().find(|region| region.contains(value)) {
+        Some(region) => region.pathname.clone().unwrap_or("[anon]".to_owned()),
+        None => (value as i64).to_string(),
+    }
+}
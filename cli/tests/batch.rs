@@ -0,0 +1,40 @@
+use std::{env, fs, process::Command};
+
+/// Runs `sh -c SHELL_COMMAND` under the debugger with the script in batch mode
+/// and returns the exit status of the debugger.
+fn run_batch(name: &str, script: &str, shell_command: &str) -> i32 {
+    let path = env::temp_dir().join(format!("mdbg-batch-{}-{}", std::process::id(), name));
+    fs::write(&path, script).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .arg("--batch")
+        .arg("-x")
+        .arg(&path)
+        .args(["run", "/bin/sh", "--", "-c", shell_command])
+        .output()
+        .unwrap()
+        .status;
+    fs::remove_file(&path).unwrap();
+
+    status.code().unwrap()
+}
+
+#[test]
+fn exits_with_program_status() {
+    assert_eq!(run_batch("exit", "continue\n", "exit 3"), 3);
+}
+
+#[test]
+fn exits_with_status_of_crashed_program() {
+    // the first continue stops at the signal, the second one delivers it
+    assert_eq!(run_batch("crash", "continue\n", "kill -SEGV $$"), 128 + 11);
+    assert_eq!(
+        run_batch("crash-resumed", "continue\ncontinue\n", "kill -SEGV $$"),
+        128 + 11
+    );
+}
+
+#[test]
+fn failed_script_makes_status_non_zero() {
+    assert_eq!(run_batch("failed", "continue\nbogus\n", "exit 0"), 1);
+}
@@ -1,10 +1,11 @@
 mod context;
 
-use std::{ffi::CStr, ptr};
+use std::{collections::HashMap, ffi::CStr, ptr};
 
 use context::Context;
 
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn load(program_pid: i32, program_path: *const libc::c_char) -> *const libc::c_void {
     // SAFETY: The caller must guarantee that pointer is valid.
     let path = match unsafe { CStr::from_ptr(program_path).to_str() } {
@@ -20,6 +21,88 @@ pub extern "C" fn load(program_pid: i32, program_path: *const libc::c_char) -> *
     Context::store(debugger) as *const libc::c_void
 }
 
+#[repr(C)]
+pub struct LaunchOptions {
+    /// Null-terminated array of program arguments, may be null.
+    args: *const *const libc::c_char,
+    /// Null-terminated array of `KEY=VAL` environment entries, may be null.
+    env: *const *const libc::c_char,
+    cwd: *const libc::c_char,
+    stdin: *const libc::c_char,
+    stdout: *const libc::c_char,
+    stderr: *const libc::c_char,
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn launch(program_path: *const libc::c_char, options: *const LaunchOptions) -> i32 {
+    // SAFETY: The caller must guarantee that pointers are valid.
+    let launcher = unsafe { build_launcher(program_path, options.as_ref()) };
+
+    launcher
+        .ok()
+        .and_then(|launcher| launcher.launch().ok())
+        .unwrap_or(-1)
+}
+
+unsafe fn build_launcher(
+    program_path: *const libc::c_char,
+    options: Option<&LaunchOptions>,
+) -> Result<mdbg_rs::Launcher, ()> {
+    let mut launcher = mdbg_rs::Launcher::new(c_str(program_path)?.ok_or(())?);
+
+    let options = match options {
+        Some(options) => options,
+        None => return Ok(launcher),
+    };
+
+    launcher = launcher.args(
+        c_str_array(options.args)?
+            .into_iter()
+            .map(|arg| arg.to_owned()),
+    );
+    for var in c_str_array(options.env)? {
+        let (key, value) = var.split_once('=').ok_or(())?;
+        launcher = launcher.env(key, value);
+    }
+    if let Some(cwd) = c_str(options.cwd)? {
+        launcher = launcher.cwd(cwd);
+    }
+    if let Some(stdin) = c_str(options.stdin)? {
+        launcher = launcher.stdin(stdin);
+    }
+    if let Some(stdout) = c_str(options.stdout)? {
+        launcher = launcher.stdout(stdout);
+    }
+    if let Some(stderr) = c_str(options.stderr)? {
+        launcher = launcher.stderr(stderr);
+    }
+
+    Ok(launcher)
+}
+
+unsafe fn c_str<'a>(ptr: *const libc::c_char) -> Result<Option<&'a str>, ()> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+
+    CStr::from_ptr(ptr).to_str().map(Some).or(Err(()))
+}
+
+unsafe fn c_str_array<'a>(mut ptr: *const *const libc::c_char) -> Result<Vec<&'a str>, ()> {
+    let mut result = Vec::new();
+    if ptr.is_null() {
+        return Ok(result);
+    }
+
+    while !(*ptr).is_null() {
+        result.push(c_str(*ptr)?.ok_or(())?);
+        ptr = ptr.add(1);
+    }
+
+    Ok(result)
+}
+
 #[no_mangle]
 pub extern "C" fn wait_attach(ctx: *const libc::c_void) -> i64 {
     Context::from(ctx as u64)
@@ -29,6 +112,7 @@ pub extern "C" fn wait_attach(ctx: *const libc::c_void) -> i64 {
 }
 
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn get_load_addr(program_pid: i32, program_path: *const libc::c_char) -> u64 {
     // SAFETY: The caller must guarantee that pointer is valid.
     let path = match unsafe { CStr::from_ptr(program_path).to_str() } {
@@ -36,16 +120,33 @@ pub extern "C" fn get_load_addr(program_pid: i32, program_path: *const libc::c_c
         Err(_) => return 0,
     };
 
-    match mdbg_rs::linux_maps::get_load_addr(program_pid, path) {
-        Ok(addr) => addr,
-        Err(_) => 0,
-    }
+    mdbg_rs::linux_maps::get_load_addr(program_pid, path).unwrap_or_default()
+}
+
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn get_load_bias(program_pid: i32, program_path: *const libc::c_char) -> u64 {
+    // SAFETY: The caller must guarantee that pointer is valid.
+    let path = match unsafe { CStr::from_ptr(program_path).to_str() } {
+        Ok(v) => v,
+        Err(_) => return 0,
+    };
+
+    mdbg_rs::get_load_bias(program_pid, path).unwrap_or_default()
 }
 
 #[no_mangle]
 pub extern "C" fn set_load_addr(ctx: *const libc::c_void, addr: u64) -> i64 {
     Context::from(ctx as u64)
-        .and_then(|mut ctx| ctx.with_debugger(|d| Ok(d.set_load_addr(addr))))
+        .and_then(|mut ctx| ctx.with_debugger(|d| d.set_load_addr(addr).or(Err(()))))
+        .and(Ok(0))
+        .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub extern "C" fn track_shared_libraries(ctx: *const libc::c_void) -> i64 {
+    Context::from(ctx as u64)
+        .and_then(|mut ctx| ctx.with_debugger(|d| d.track_shared_libraries().or(Err(()))))
         .and(Ok(0))
         .unwrap_or(-1)
 }
@@ -62,7 +163,10 @@ pub extern "C" fn continue_execution(ctx: *const libc::c_void) -> StatusResult {
     Context::from(ctx as u64)
         .and_then(|mut ctx| {
             ctx.with_debugger(|d| {
-                let status = d.continue_execution().or(Err(()))?;
+                let status = match d.continue_execution().or(Err(()))? {
+                    mdbg_rs::StopReason::Exited(status) => Some(status),
+                    _ => None,
+                };
                 Ok(StatusResult {
                     exited: status.is_some(),
                     status: status.unwrap_or(0),
@@ -78,6 +182,7 @@ pub extern "C" fn continue_execution(ctx: *const libc::c_void) -> StatusResult {
 }
 
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn set_breakpoint(
     ctx: *const libc::c_void,
     filename: *const libc::c_char,
@@ -91,12 +196,15 @@ pub extern "C" fn set_breakpoint(
 
     let breakpoint_ref = mdbg_rs::BreakpointRef::Line { filename, line };
     Context::from(ctx as u64)
-        .and_then(|mut ctx| ctx.with_debugger(|d| Ok(d.set_breakpoint(breakpoint_ref.clone()))))
-        .and(Ok(0))
+        .and_then(|mut ctx| {
+            ctx.with_debugger(|d| d.set_breakpoint(breakpoint_ref.clone()).or(Err(())))
+        })
+        .map(|id| id as i64)
         .unwrap_or(-1)
 }
 
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn get_register_value(
     ctx: *const libc::c_void,
     register: *const libc::c_char,
@@ -121,6 +229,7 @@ pub extern "C" fn get_register_value(
 }
 
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn set_register_value(
     ctx: *const libc::c_void,
     register: *const libc::c_char,
@@ -172,10 +281,12 @@ pub struct RegistersDump {
 }
 
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn dump_registers(ctx: *const libc::c_void, dump: *mut RegistersDump) -> i64 {
     Context::from(ctx as u64)
         .and_then(|mut ctx| ctx.with_debugger(|d| d.dump_registers().or(Err(()))))
         .map(|regs| {
+            let regs: HashMap<String, u64> = regs.into_iter().collect();
             unsafe {
                 // SAFETY: The caller must guarantee that pointer is valid.
                 *dump = RegistersDump {
@@ -187,8 +298,8 @@ pub extern "C" fn dump_registers(ctx: *const libc::c_void, dump: *mut RegistersD
                     rbx: *regs.get("rbx").unwrap_or(&0u64),
                     r11: *regs.get("r11").unwrap_or(&0u64),
                     r10: *regs.get("r10").unwrap_or(&0u64),
-                    r9: *regs.get("r19").unwrap_or(&0u64),
-                    r8: *regs.get("r18").unwrap_or(&0u64),
+                    r9: *regs.get("r9").unwrap_or(&0u64),
+                    r8: *regs.get("r8").unwrap_or(&0u64),
                     rax: *regs.get("rax").unwrap_or(&0u64),
                     rcx: *regs.get("rcx").unwrap_or(&0u64),
                     rdx: *regs.get("rdx").unwrap_or(&0u64),
@@ -214,6 +325,7 @@ pub extern "C" fn dump_registers(ctx: *const libc::c_void, dump: *mut RegistersD
 }
 
 #[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "C" fn read_memory(ctx: *const libc::c_void, addr: u64, value: *mut i64) -> i64 {
     Context::from(ctx as u64)
         .and_then(|mut ctx| ctx.with_debugger(|d| d.read_memory(addr).or(Err(()))))
@@ -232,3 +344,34 @@ pub extern "C" fn write_memory(ctx: *const libc::c_void, addr: u64, value: i64)
         .and(Ok(0))
         .unwrap_or(-1)
 }
+
+/// Reads up to `len` bytes into the caller buffer, returns number of bytes read or -1.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn read_bytes(ctx: *const libc::c_void, addr: u64, buf: *mut u8, len: usize) -> i64 {
+    Context::from(ctx as u64)
+        .and_then(|mut ctx| ctx.with_debugger(|d| d.read_bytes(addr, len).or(Err(()))))
+        .map(|bytes| {
+            // SAFETY: The caller must guarantee that buffer is valid and has at least len bytes.
+            unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), buf, bytes.len()) };
+            bytes.len() as i64
+        })
+        .unwrap_or(-1)
+}
+
+/// Writes `len` bytes from the caller buffer, returns number of bytes written or -1.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "C" fn write_bytes(
+    ctx: *const libc::c_void,
+    addr: u64,
+    data: *const u8,
+    len: usize,
+) -> i64 {
+    // SAFETY: The caller must guarantee that buffer is valid and has at least len bytes.
+    let data = unsafe { std::slice::from_raw_parts(data, len) };
+    Context::from(ctx as u64)
+        .and_then(|mut ctx| ctx.with_debugger(|d| d.write_bytes(addr, data).or(Err(()))))
+        .map(|written| written as i64)
+        .unwrap_or(-1)
+}
@@ -0,0 +1,77 @@
+mod protocol;
+mod session;
+
+use std::{
+    io,
+    sync::mpsc::{self, Sender as RequestSender},
+    thread,
+};
+
+use protocol::Sender;
+use serde_json::{json, Value};
+use session::{RunningProgram, Session};
+
+/// Debug Adapter Protocol server, editors like VS Code start it and talk to it over stdio.
+fn main() {
+    run().unwrap();
+}
+
+fn run() -> Result<(), String> {
+    let sender = Sender::new(io::stdout());
+    let running = RunningProgram::default();
+    let (requests_tx, requests) = mpsc::channel();
+    {
+        let sender = sender.clone();
+        let running = running.clone();
+        thread::spawn(move || read_requests(requests_tx, sender, running));
+    }
+
+    // ptrace requests must come from the thread which attached, so the session stays here
+    let mut session = Session::new(sender, running);
+    while !session.finished() {
+        let request = match requests.recv() {
+            Ok(request) => request?,
+            Err(_) => break,
+        };
+        session.handle(&request)?;
+    }
+
+    Ok(())
+}
+
+/// Passes requests to the session, `pause` and `disconnect` interrupt the running
+/// program first, because the session doesn't handle requests until it stops.
+fn read_requests(
+    requests: RequestSender<Result<Value, String>>,
+    sender: Sender,
+    running: RunningProgram,
+) {
+    let mut input = io::stdin().lock();
+    loop {
+        let message = match protocol::read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(e) => {
+                let _ = requests.send(Err(e));
+                return;
+            }
+        };
+        if message["type"] != "request" {
+            continue;
+        }
+
+        match message["command"].as_str() {
+            Some("pause") if running.interrupt() => {
+                let _ = sender.response(&message, json!({}));
+                continue;
+            }
+            Some("disconnect") => {
+                running.interrupt();
+            }
+            _ => (),
+        }
+        if requests.send(Ok(message)).is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    io::{BufRead, Write},
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{json, Value};
+
+/// Reads the next `Content-Length` framed message, None when the client closed the stream.
+pub fn read_message<R: BufRead>(reader: &mut R) -> Result<Option<Value>, String> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| format!("failed to read message header: {}", e))?;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        // empty line ends the headers
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .map_err(|e| format!("failed to parse content length: {}", e))?,
+            );
+        }
+    }
+
+    let mut body = vec![0; length.ok_or("message without Content-Length")?];
+    reader
+        .read_exact(&mut body)
+        .map_err(|e| format!("failed to read message body: {}", e))?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| format!("failed to parse message: {}", e))
+}
+
+/// Sends messages to the client. Clones share the sequence number, so the output
+/// forwarding thread and the request loop can both send events.
+#[derive(Clone)]
+pub struct Sender {
+    inner: Arc<Mutex<SenderState>>,
+}
+
+struct SenderState {
+    output: Box<dyn Write + Send>,
+    seq: u64,
+}
+
+impl Sender {
+    pub fn new<W: Write + Send + 'static>(output: W) -> Sender {
+        Sender {
+            inner: Arc::new(Mutex::new(SenderState {
+                output: Box::new(output),
+                seq: 1,
+            })),
+        }
+    }
+
+    pub fn response(&self, request: &Value, body: Value) -> Result<(), String> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    pub fn error(&self, request: &Value, message: &str) -> Result<(), String> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    pub fn event(&self, event: &str, body: Value) -> Result<(), String> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+
+    /// Sends text the client shows in its debug console.
+    pub fn output(&self, category: &str, output: &str) -> Result<(), String> {
+        self.event("output", json!({ "category": category, "output": output }))
+    }
+
+    fn send(&self, mut message: Value) -> Result<(), String> {
+        let mut state = self.inner.lock().map_err(|_| "sender lock is poisoned")?;
+        message["seq"] = json!(state.seq);
+        state.seq += 1;
+
+        let body = message.to_string();
+        write!(
+            state.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .and_then(|_| state.output.flush())
+        .map_err(|e| format!("failed to send message: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn reads_framed_messages() {
+        let input = frame(r#"{"seq":1,"command":"initialize"}"#) + &frame(r#"{"seq":2}"#);
+        let mut reader = input.as_bytes();
+
+        let first = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(first["command"], "initialize");
+        let second = read_message(&mut reader).unwrap().unwrap();
+        assert_eq!(second["seq"], 2);
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn skips_other_headers() {
+        let input = "Content-Type: application/vscode-jsonrpc\r\nContent-Length: 2\r\n\r\n{}";
+        let message = read_message(&mut input.as_bytes()).unwrap().unwrap();
+        assert_eq!(message, json!({}));
+
+        // body length is counted in bytes
+        let body = r#"{"text":"ünï"}"#;
+        let message = read_message(&mut frame(body).as_bytes()).unwrap().unwrap();
+        assert_eq!(message["text"], "ünï");
+    }
+
+    #[test]
+    fn rejects_broken_messages() {
+        assert!(read_message(&mut "\r\n{}".as_bytes()).is_err());
+        assert!(read_message(&mut "Content-Length: x\r\n\r\n".as_bytes()).is_err());
+        // body is shorter than the header says
+        assert!(read_message(&mut "Content-Length: 10\r\n\r\n{}".as_bytes()).is_err());
+        assert!(read_message(&mut frame("{").as_bytes()).is_err());
+        assert_eq!(read_message(&mut "".as_bytes()).unwrap(), None);
+    }
+}
@@ -0,0 +1,534 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    os::unix::io::FromRawFd,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+use mdbg_rs::{BreakpointRef, Debugger, RegSelector, StopReason};
+use nix::{
+    fcntl::OFlag,
+    sys::{
+        ptrace,
+        signal::{self, Signal},
+    },
+    unistd::{self, Pid},
+};
+use serde_json::{json, Value};
+
+use crate::protocol::Sender;
+
+// variablesReference values of the scopes, 0 means the variable has no children
+const REGISTERS_REF: u64 = 1;
+const FP_REGISTERS_REF: u64 = 2;
+
+/// Pid of the program while a run request waits for it to stop. The thread reading
+/// requests uses it to interrupt the program for `pause` and `disconnect`.
+#[derive(Clone, Default)]
+pub struct RunningProgram {
+    pid: Arc<Mutex<Option<i32>>>,
+}
+
+impl RunningProgram {
+    fn set(&self, pid: Option<i32>) {
+        if let Ok(mut running) = self.pid.lock() {
+            *running = pid;
+        }
+    }
+
+    /// Stops the running program, returns false if it is already stopped.
+    pub fn interrupt(&self) -> bool {
+        match self.pid.lock().as_deref() {
+            // stop is reported by waitpid like the one after Ctrl-C in the CLI
+            Ok(Some(pid)) => signal::kill(Pid::from_raw(*pid), Signal::SIGSTOP).is_ok(),
+            _ => false,
+        }
+    }
+}
+
+/// State of one debugging session from `initialize` to `disconnect`.
+pub struct Session {
+    sender: Sender,
+    running: RunningProgram,
+    debugger: Option<Debugger<'static>>,
+    // launched program is killed on disconnect, attached one is detached
+    launched: bool,
+    stop_on_entry: bool,
+    // ids of breakpoints set for every source path
+    breakpoints: HashMap<String, Vec<usize>>,
+    // threads forwarding program stdout and stderr as output events
+    output: Vec<JoinHandle<()>>,
+    finished: bool,
+}
+
+impl Session {
+    pub fn new(sender: Sender, running: RunningProgram) -> Session {
+        Session {
+            sender,
+            running,
+            debugger: None,
+            launched: false,
+            stop_on_entry: false,
+            breakpoints: HashMap::new(),
+            output: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Handles the request, a failed one gets an error response.
+    pub fn handle(&mut self, request: &Value) -> Result<(), String> {
+        let command = request["command"].as_str().unwrap_or_default().to_owned();
+        let args = &request["arguments"];
+
+        let result = match command.as_str() {
+            "initialize" => self.sender.response(
+                request,
+                json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsTerminateRequest": false,
+                }),
+            ),
+            "launch" => self.launch(args).and_then(|_| self.loaded(request)),
+            "attach" => self.attach(args).and_then(|_| self.loaded(request)),
+            "setBreakpoints" => self
+                .set_breakpoints(args)
+                .and_then(|body| self.sender.response(request, body)),
+            "setExceptionBreakpoints" => self.sender.response(request, json!({})),
+            "configurationDone" => {
+                self.sender.response(request, json!({}))?;
+                if self.stop_on_entry {
+                    self.stopped("entry", &[])
+                } else {
+                    self.resume(Debugger::continue_execution, "breakpoint")
+                }
+            }
+            "threads" => {
+                let threads: Vec<Value> = self
+                    .debugger()?
+                    .threads()
+                    .into_iter()
+                    .map(|tid| json!({ "id": tid, "name": format!("thread {}", tid) }))
+                    .collect();
+                self.sender.response(request, json!({ "threads": threads }))
+            }
+            "continue" => {
+                self.running()?;
+                self.sender
+                    .response(request, json!({ "allThreadsContinued": true }))?;
+                self.resume(Debugger::continue_execution, "breakpoint")
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.running()?;
+                self.sender.response(request, json!({}))?;
+                let step = match command.as_str() {
+                    "next" => Debugger::next_line,
+                    "stepIn" => Debugger::step_line,
+                    _ => Debugger::step_out,
+                };
+                self.resume(step, "step")
+            }
+            // running program is interrupted before the request gets here
+            "pause" => self.sender.response(request, json!({})),
+            "stackTrace" => self
+                .stack_trace()
+                .and_then(|body| self.sender.response(request, body)),
+            "scopes" => {
+                // local variables aren't supported and registers are known for the innermost frame only
+                let scopes = match args["frameId"].as_u64() {
+                    Some(0) => json!([
+                        { "name": "Registers", "presentationHint": "registers", "variablesReference": REGISTERS_REF, "expensive": false },
+                        { "name": "Floating Point Registers", "presentationHint": "registers", "variablesReference": FP_REGISTERS_REF, "expensive": true },
+                    ]),
+                    _ => json!([]),
+                };
+                self.sender.response(request, json!({ "scopes": scopes }))
+            }
+            "variables" => self
+                .variables(args["variablesReference"].as_u64().unwrap_or_default())
+                .and_then(|body| self.sender.response(request, body)),
+            "evaluate" => {
+                let expression = args["expression"].as_str().unwrap_or_default();
+                evaluate(self.debugger()?, expression).and_then(|value| {
+                    self.sender.response(
+                        request,
+                        json!({ "result": format!("{:#x}", value), "variablesReference": 0 }),
+                    )
+                })
+            }
+            "disconnect" => {
+                let terminate = args["terminateDebuggee"].as_bool().unwrap_or(self.launched);
+                self.disconnect(terminate)
+                    .and_then(|_| self.sender.response(request, json!({})))
+            }
+            command => Err(format!("unsupported request {}", command)),
+        };
+
+        result.or_else(|e| self.sender.error(request, &e))
+    }
+
+    fn debugger(&self) -> Result<&Debugger<'static>, String> {
+        self.debugger
+            .as_ref()
+            .ok_or("program is not loaded".to_owned())
+    }
+
+    fn debugger_mut(&mut self) -> Result<&mut Debugger<'static>, String> {
+        self.debugger
+            .as_mut()
+            .ok_or("program is not loaded".to_owned())
+    }
+
+    // run requests are answered before the program runs, so they are rejected up front
+    fn running(&self) -> Result<(), String> {
+        match self.debugger()?.alive() {
+            true => Ok(()),
+            false => Err("program is not running".to_owned()),
+        }
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<(), String> {
+        let program = args["program"].as_str().ok_or("program path is required")?;
+        let mut launcher = mdbg_rs::Launcher::new(program).args(
+            args["args"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|arg| arg.as_str().map(str::to_owned)),
+        );
+        if let Some(cwd) = args["cwd"].as_str() {
+            launcher = launcher.cwd(cwd);
+        }
+        for (key, value) in args["env"].as_object().into_iter().flatten() {
+            launcher = launcher.env(key, value.as_str().unwrap_or_default());
+        }
+
+        // stdout of the adapter carries the protocol, so program output goes through pipes
+        let pipe =
+            || unistd::pipe2(OFlag::O_CLOEXEC).map_err(|e| format!("failed to create pipe: {}", e));
+        let (stdout_read, stdout_write) = pipe()?;
+        let (stderr_read, stderr_write) = pipe()?;
+        // stdin of the adapter carries the protocol as well
+        let launched = launcher
+            .stdin("/dev/null")
+            .stdout(&format!("/proc/self/fd/{}", stdout_write))
+            .stderr(&format!("/proc/self/fd/{}", stderr_write))
+            .launch();
+        // the program holds its own ends, output threads see EOF when it exits
+        let _ = unistd::close(stdout_write);
+        let _ = unistd::close(stderr_write);
+        // SAFETY: read ends of the pipes were just created and nothing else owns them
+        let (stdout, stderr) = unsafe {
+            (
+                File::from_raw_fd(stdout_read),
+                File::from_raw_fd(stderr_read),
+            )
+        };
+        let pid = launched.map_err(|e| format!("failed to launch program: {}", e))?;
+
+        self.output = vec![
+            forward_output(self.sender.clone(), stdout, "stdout"),
+            forward_output(self.sender.clone(), stderr, "stderr"),
+        ];
+        self.launched = true;
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.load(program, pid)
+    }
+
+    fn attach(&mut self, args: &Value) -> Result<(), String> {
+        let program = args["program"].as_str().ok_or("program path is required")?;
+        let pid = args["pid"].as_i64().ok_or("pid is required")? as i32;
+        ptrace::attach(Pid::from_raw(pid))
+            .map_err(|e| format!("failed to attach to process {}", e))?;
+
+        self.launched = false;
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.load(program, pid)
+    }
+
+    fn load(&mut self, program: &str, pid: i32) -> Result<(), String> {
+        let mut debugger = mdbg_rs::load_in_memory(pid, program)?;
+        debugger
+            .wait_attach()
+            .map_err(|e| format!("failed to wait trap: {}", e))?;
+        let load_addr = debugger
+            .executable_load_addr()
+            .map_err(|e| format!("failed to get load addr: {}", e))?;
+        debugger
+            .set_load_addr(load_addr)
+            .map_err(|e| format!("failed to set load addr: {}", e))?;
+        debugger
+            .track_shared_libraries()
+            .map_err(|e| format!("failed to track shared libraries: {}", e))?;
+
+        self.debugger = Some(debugger);
+        Ok(())
+    }
+
+    /// Responds to launch or attach, breakpoints can be set from now on.
+    fn loaded(&mut self, request: &Value) -> Result<(), String> {
+        let pid = self.debugger()?.program_pid();
+        self.sender.response(request, json!({}))?;
+        self.sender.event(
+            "process",
+            json!({ "name": request["arguments"]["program"], "systemProcessId": pid }),
+        )?;
+        self.sender.event("initialized", json!({}))
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["source"]["path"]
+            .as_str()
+            .ok_or("source path is required")?
+            .to_owned();
+        let debugger = self.debugger.as_mut().ok_or("program is not loaded")?;
+
+        // the request replaces every breakpoint of the source
+        for id in self.breakpoints.remove(&path).unwrap_or_default() {
+            debugger.delete_breakpoint(id)?;
+        }
+
+        let mut ids = Vec::new();
+        let mut result = Vec::new();
+        for breakpoint in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = breakpoint["line"]
+                .as_u64()
+                .ok_or("breakpoint line is required")?;
+            let id = debugger.set_breakpoint(BreakpointRef::Line {
+                filename: path.clone(),
+                line,
+            })?;
+            ids.push(id);
+            result.push(json!({
+                "id": id,
+                // pending breakpoint is verified once its library is loaded
+                "verified": debugger.breakpoint_addr(id).is_some(),
+                "line": line,
+                "source": { "path": path },
+            }));
+        }
+        debugger.take_activated_breakpoints();
+        self.breakpoints.insert(path, ids);
+
+        Ok(json!({ "breakpoints": result }))
+    }
+
+    /// Runs the program with the action and reports where it stopped.
+    fn resume(
+        &mut self,
+        action: fn(&mut Debugger<'static>) -> Result<StopReason, String>,
+        reason: &str,
+    ) -> Result<(), String> {
+        let running = self.running.clone();
+        let debugger = self.debugger_mut()?;
+        running.set(Some(debugger.program_pid()));
+        let result = action(debugger);
+        running.set(None);
+        for id in debugger.take_activated_breakpoints() {
+            self.sender.event(
+                "breakpoint",
+                json!({ "reason": "changed", "breakpoint": { "id": id, "verified": true } }),
+            )?;
+        }
+
+        match result {
+            Ok(StopReason::Breakpoint(addr)) => {
+                let debugger = self.debugger()?;
+                let hit: Vec<usize> = self
+                    .breakpoints
+                    .values()
+                    .flatten()
+                    .copied()
+                    .filter(|&id| debugger.breakpoint_addr(id) == Some(addr))
+                    .collect();
+                match hit.is_empty() {
+                    true => self.stopped(reason, &[]),
+                    false => self.stopped("breakpoint", &hit),
+                }
+            }
+            Ok(StopReason::Step) => self.stopped(reason, &[]),
+            Ok(StopReason::Interrupted) => self.stopped("pause", &[]),
+            Ok(StopReason::Exited(status)) => self.exited(status, None),
+            Ok(StopReason::Signaled(signal)) => self.exited(128 + signal, Some(signal)),
+            // the next run request delivers the signal
+            Ok(StopReason::Signal(signal)) => {
+                let name = signal_name(signal);
+                self.sender
+                    .output("console", &format!("Program received signal {}.\n", name))?;
+                self.exception(&name)
+            }
+            // the debugger failed to run the program, it is left where it stopped
+            Err(e) => {
+                self.sender.output("console", &format!("{}\n", e))?;
+                self.exception(&e)
+            }
+        }
+    }
+
+    fn stopped(&self, reason: &str, hit: &[usize]) -> Result<(), String> {
+        let thread = self.debugger()?.current_thread();
+        self.sender.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "threadId": thread,
+                "allThreadsStopped": true,
+                "hitBreakpointIds": hit,
+            }),
+        )
+    }
+
+    fn exception(&self, text: &str) -> Result<(), String> {
+        let thread = self.debugger()?.current_thread();
+        self.sender.event(
+            "stopped",
+            json!({
+                "reason": "exception",
+                "text": text,
+                "threadId": thread,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn exited(&mut self, status: i32, signal: Option<i32>) -> Result<(), String> {
+        // output written before the exit goes first
+        for thread in self.output.drain(..) {
+            let _ = thread.join();
+        }
+        if let Some(signal) = signal {
+            self.sender.output(
+                "console",
+                &format!("Program terminated with signal {}.\n", signal_name(signal)),
+            )?;
+        }
+
+        self.sender.event("exited", json!({ "exitCode": status }))?;
+        self.sender.event("terminated", json!({}))
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let debugger = self.debugger()?;
+        let frames = debugger.backtrace()?;
+
+        let mut result = Vec::new();
+        for (index, frame) in frames.iter().enumerate() {
+            // return address may already belong to the next source line
+            let call_addr = if index == 0 { frame.pc } else { frame.pc - 1 };
+            let name = debugger
+                .symbolize(call_addr)
+                .map(|symbol| symbol.split('+').next().unwrap_or_default().to_owned())
+                .unwrap_or(format!("{:#x}", frame.pc));
+
+            let mut value = json!({
+                "id": index,
+                "name": name,
+                "line": 0,
+                "column": 0,
+                "instructionPointerReference": format!("{:#x}", frame.pc),
+            });
+            if let Some(location) = debugger.get_source_location(call_addr)? {
+                value["line"] = json!(location.line);
+                value["column"] = json!(1);
+                value["source"] = json!({
+                    "name": Path::new(&location.filename).file_name().map(|n| n.to_string_lossy()),
+                    "path": location.filename,
+                });
+            }
+            result.push(value);
+        }
+
+        Ok(json!({ "stackFrames": result, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&self, reference: u64) -> Result<Value, String> {
+        let debugger = self.debugger()?;
+        let variables: Vec<Value> = match reference {
+            REGISTERS_REF => debugger
+                .dump_registers()?
+                .into_iter()
+                .map(|(name, value)| variable(&name, format!("{:#x}", value)))
+                .collect(),
+            FP_REGISTERS_REF => debugger
+                .dump_fp_registers()?
+                .into_iter()
+                .map(|(name, bytes)| {
+                    // little-endian bytes are shown as a single number
+                    let hex: String = bytes.iter().rev().map(|b| format!("{:02x}", b)).collect();
+                    variable(&name, format!("0x{}", hex))
+                })
+                .collect(),
+            reference => Err(format!("unknown variables reference {}", reference))?,
+        };
+
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn disconnect(&mut self, terminate: bool) -> Result<(), String> {
+        self.finished = true;
+        let debugger = match &mut self.debugger {
+            Some(debugger) if debugger.alive() => debugger,
+            _ => return Ok(()),
+        };
+
+        match terminate {
+            true => debugger.kill(),
+            false => debugger.detach(),
+        }
+    }
+}
+
+fn signal_name(signal: i32) -> String {
+    Signal::try_from(signal)
+        .map(|s| s.to_string())
+        .unwrap_or(signal.to_string())
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+/// Evaluates a number, register name with or without `$`, symbol name
+/// or `*expression` which reads a word of memory.
+fn evaluate(debugger: &Debugger, expression: &str) -> Result<u64, String> {
+    let expression = expression.trim();
+    if let Some(pointer) = expression.strip_prefix('*') {
+        let addr = evaluate(debugger, pointer)?;
+        return Ok(debugger.read_memory(addr)? as u64);
+    }
+    if let Some(hex) = expression
+        .strip_prefix("0x")
+        .or(expression.strip_prefix("0X"))
+    {
+        return u64::from_str_radix(hex, 16).map_err(|e| format!("failed to parse number: {}", e));
+    }
+    if let Ok(value) = expression.parse() {
+        return Ok(value);
+    }
+
+    let register = expression.strip_prefix('$').unwrap_or(expression);
+    if let Ok(value) = debugger.get_register_value(&RegSelector::Name(register)) {
+        return Ok(value);
+    }
+    debugger
+        .find_symbol_addr(expression)
+        .ok_or(format!("no register or symbol `{}`", expression))
+}
+
+fn forward_output(sender: Sender, mut pipe: File, category: &'static str) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        while let Ok(read @ 1..) = pipe.read(&mut buf) {
+            let text = String::from_utf8_lossy(&buf[..read]);
+            if sender.output(category, &text).is_err() {
+                break;
+            }
+        }
+    })
+}
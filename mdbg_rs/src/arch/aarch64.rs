@@ -0,0 +1,225 @@
+use object::elf;
+
+use super::{Arch, RegInfo, SubRegInfo, UnwindRegisters, SI_USER, TRAP_BRKPT, TRAP_TRACE};
+
+pub struct AArch64;
+
+// user_pt_regs: x0-x30, sp, pc and pstate
+const REGSET_SIZE: usize = 34 * 8;
+
+const REGISTERS: [RegInfo; 34] = {
+    const NAMES: [&str; 34] = [
+        "x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7", "x8", "x9", "x10", "x11", "x12", "x13",
+        "x14", "x15", "x16", "x17", "x18", "x19", "x20", "x21", "x22", "x23", "x24", "x25", "x26",
+        "x27", "x28", "x29", "x30", "sp", "pc", "pstate",
+    ];
+
+    let mut result = [RegInfo::new("", 0, 8); 34];
+    let mut i = 0;
+    while i < NAMES.len() {
+        result[i] = RegInfo::new(NAMES[i], i * 8, 8);
+        i += 1;
+    }
+    result
+};
+
+const PSTATE: [(&str, u32); 8] = [
+    ("N", 31),
+    ("Z", 30),
+    ("C", 29),
+    ("V", 28),
+    ("D", 9),
+    ("A", 8),
+    ("I", 7),
+    ("F", 6),
+];
+
+impl Arch for AArch64 {
+    fn name(&self) -> &'static str {
+        "aarch64"
+    }
+
+    fn elf_machine(&self) -> u16 {
+        elf::EM_AARCH64
+    }
+
+    fn pointer_size(&self) -> usize {
+        8
+    }
+
+    fn registers(&self) -> &'static [RegInfo] {
+        &REGISTERS
+    }
+
+    fn sub_registers(&self) -> Vec<SubRegInfo> {
+        let mut result: Vec<SubRegInfo> = REGISTERS[..31]
+            .iter()
+            .map(|reg| SubRegInfo {
+                name: reg.name.replace('x', "w"),
+                parent: reg.name,
+                shift: 0,
+                bits: 32,
+                zero_extend: false,
+            })
+            .collect();
+        // x29 and x30 have ABI names
+        result.push(SubRegInfo {
+            name: "fp".to_owned(),
+            parent: "x29",
+            shift: 0,
+            bits: 64,
+            zero_extend: false,
+        });
+        result.push(SubRegInfo {
+            name: "lr".to_owned(),
+            parent: "x30",
+            shift: 0,
+            bits: 64,
+            zero_extend: false,
+        });
+
+        result
+    }
+
+    fn regset_size(&self) -> usize {
+        REGSET_SIZE
+    }
+
+    fn pc_register(&self) -> &'static str {
+        "pc"
+    }
+
+    fn flags_register(&self) -> &'static str {
+        "pstate"
+    }
+
+    fn flags(&self) -> &'static [(&'static str, u32)] {
+        &PSTATE
+    }
+
+    fn breakpoint_instruction(&self) -> &'static [u8] {
+        // BRK #0 is 0xd4200000 in little-endian
+        &[0x00, 0x00, 0x20, 0xd4]
+    }
+
+    fn breakpoint_pc_offset(&self) -> u64 {
+        // PC isn't advanced past BRK
+        0
+    }
+
+    fn breakpoint_trap_codes(&self) -> &'static [i32] {
+        &[TRAP_BRKPT]
+    }
+
+    fn step_trap_codes(&self) -> &'static [i32] {
+        &[SI_USER, TRAP_TRACE]
+    }
+
+    fn unwind_registers(&self) -> UnwindRegisters {
+        UnwindRegisters {
+            stack_pointer: (31, "sp"),
+            frame_pointer: (29, "x29"),
+            link_register: Some((30, "x30")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        breakpoint::{patch_word, word_bytes},
+        reg::{self, RegSelector},
+    };
+
+    fn regset() -> Vec<u8> {
+        (0..34u64)
+            .flat_map(|i| ((i + 1) << 32 | i).to_le_bytes())
+            .collect()
+    }
+
+    fn get(regs: &[u8], name: &str) -> Result<u64, String> {
+        reg::get_register_value(&AArch64, regs, &RegSelector::Name(name))
+    }
+
+    #[test]
+    fn breakpoint_is_brk_0() {
+        let instruction = AArch64.breakpoint_instruction();
+        let mut encoded = [0u8; 4];
+        encoded.copy_from_slice(instruction);
+
+        assert_eq!(u32::from_le_bytes(encoded), 0xd420_0000);
+        assert_eq!(AArch64.breakpoint_pc_offset(), 0);
+    }
+
+    #[test]
+    fn breakpoint_replaces_whole_instruction() {
+        // nop followed by ret
+        let word = i64::from_le_bytes([0x1f, 0x20, 0x03, 0xd5, 0xc0, 0x03, 0x5f, 0xd6]);
+        let instruction = AArch64.breakpoint_instruction();
+
+        let replaced = word_bytes(word, instruction.len());
+        let patched = patch_word(word, instruction);
+
+        assert_eq!(replaced, [0x1f, 0x20, 0x03, 0xd5]);
+        assert_eq!(
+            patched.to_le_bytes(),
+            [0x00, 0x00, 0x20, 0xd4, 0xc0, 0x03, 0x5f, 0xd6]
+        );
+        assert_eq!(patch_word(patched, &replaced), word);
+    }
+
+    #[test]
+    fn registers_follow_user_pt_regs() {
+        let regs = regset();
+        assert_eq!(regs.len(), AArch64.regset_size());
+
+        assert_eq!(get(&regs, "x0").unwrap(), 0x1_0000_0000);
+        assert_eq!(get(&regs, "x30").unwrap(), 0x1f_0000_001e);
+        assert_eq!(get(&regs, "sp").unwrap(), 0x20_0000_001f);
+        assert_eq!(get(&regs, "pc").unwrap(), 0x21_0000_0020);
+        assert_eq!(get(&regs, "lr").unwrap(), get(&regs, "x30").unwrap());
+        assert_eq!(get(&regs, "fp").unwrap(), get(&regs, "x29").unwrap());
+        assert!(get(&regs, "rip").is_err());
+
+        let names: Vec<String> = reg::dump_registers(&AArch64, &regs)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names.len(), 34);
+        assert_eq!(names[..2], ["x0", "x1"]);
+        assert_eq!(names[31..], ["sp", "pc", "pstate"]);
+    }
+
+    #[test]
+    fn w_registers_are_masked() {
+        let mut regs = regset();
+        assert_eq!(get(&regs, "w3").unwrap(), 3);
+
+        reg::set_register_value(&AArch64, &mut regs, &RegSelector::Name("w3"), 0xdead_beef)
+            .unwrap();
+        assert_eq!(get(&regs, "x3").unwrap(), 0x4_dead_beef);
+        assert!(
+            reg::set_register_value(&AArch64, &mut regs, &RegSelector::Name("w3"), 1 << 32)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn pstate_flags() {
+        let mut regs = vec![0u8; AArch64.regset_size()];
+        let pstate = (1 << 30) | (1 << 29) | (1 << 7);
+        reg::set_register_value(&AArch64, &mut regs, &RegSelector::Name("pstate"), pstate).unwrap();
+
+        assert_eq!(AArch64.decode_flags(pstate), ["Z", "C", "I"]);
+        assert_eq!(get(&regs, "z").unwrap(), 1);
+        assert_eq!(get(&regs, "n").unwrap(), 0);
+
+        reg::set_register_value(&AArch64, &mut regs, &RegSelector::Name("n"), 1).unwrap();
+        reg::set_register_value(&AArch64, &mut regs, &RegSelector::Name("z"), 0).unwrap();
+        assert_eq!(
+            get(&regs, "pstate").unwrap(),
+            (1 << 31) | (1 << 29) | (1 << 7)
+        );
+    }
+}
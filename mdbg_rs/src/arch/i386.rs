@@ -0,0 +1,112 @@
+use object::elf;
+
+use super::{
+    Arch, RegInfo, SubRegInfo, UnwindRegisters, SI_KERNEL, SI_USER, TRAP_BRKPT, TRAP_TRACE,
+};
+
+/// 32-bit x86 program running in compatibility mode of x86-64 kernel.
+pub struct I386;
+
+// offsets follow user_regs_struct of i386, which the kernel returns for compat tasks
+const REGISTERS: [RegInfo; 16] = [
+    RegInfo::new("eax", 24, 4),
+    RegInfo::new("ebx", 0, 4),
+    RegInfo::new("ecx", 4, 4),
+    RegInfo::new("edx", 8, 4),
+    RegInfo::new("esi", 12, 4),
+    RegInfo::new("edi", 16, 4),
+    RegInfo::new("ebp", 20, 4),
+    RegInfo::new("esp", 60, 4),
+    RegInfo::new("eip", 48, 4),
+    RegInfo::new("eflags", 56, 4),
+    RegInfo::new("cs", 52, 4),
+    RegInfo::new("ss", 64, 4),
+    RegInfo::new("ds", 28, 4),
+    RegInfo::new("es", 32, 4),
+    RegInfo::new("fs", 36, 4),
+    RegInfo::new("gs", 40, 4),
+];
+
+const REGSET_SIZE: usize = 17 * 4;
+
+impl Arch for I386 {
+    fn name(&self) -> &'static str {
+        "i386"
+    }
+
+    fn elf_machine(&self) -> u16 {
+        elf::EM_386
+    }
+
+    fn pointer_size(&self) -> usize {
+        4
+    }
+
+    fn registers(&self) -> &'static [RegInfo] {
+        &REGISTERS
+    }
+
+    fn sub_registers(&self) -> Vec<SubRegInfo> {
+        let sub = |name: String, parent, shift, bits| SubRegInfo {
+            name,
+            parent,
+            shift,
+            bits,
+            zero_extend: false,
+        };
+        let mut result = Vec::new();
+
+        for (parent, x) in [("eax", "a"), ("ebx", "b"), ("ecx", "c"), ("edx", "d")] {
+            result.push(sub(format!("{}x", x), parent, 0, 16));
+            result.push(sub(format!("{}l", x), parent, 0, 8));
+            result.push(sub(format!("{}h", x), parent, 8, 8));
+        }
+        for (parent, x) in [("esi", "si"), ("edi", "di"), ("ebp", "bp"), ("esp", "sp")] {
+            result.push(sub(x.to_owned(), parent, 0, 16));
+        }
+
+        result
+    }
+
+    fn regset_size(&self) -> usize {
+        REGSET_SIZE
+    }
+
+    fn pc_register(&self) -> &'static str {
+        "eip"
+    }
+
+    fn flags_register(&self) -> &'static str {
+        "eflags"
+    }
+
+    fn flags(&self) -> &'static [(&'static str, u32)] {
+        &super::x86_64::EFLAGS
+    }
+
+    fn breakpoint_instruction(&self) -> &'static [u8] {
+        // int3
+        &[0xCC]
+    }
+
+    fn breakpoint_pc_offset(&self) -> u64 {
+        // EIP points after int3 when the trap is reported
+        1
+    }
+
+    fn breakpoint_trap_codes(&self) -> &'static [i32] {
+        &[SI_KERNEL, TRAP_BRKPT]
+    }
+
+    fn step_trap_codes(&self) -> &'static [i32] {
+        &[SI_USER, TRAP_TRACE]
+    }
+
+    fn unwind_registers(&self) -> UnwindRegisters {
+        UnwindRegisters {
+            stack_pointer: (4, "esp"),
+            frame_pointer: (5, "ebp"),
+            link_register: None,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+mod aarch64;
+mod i386;
+mod x86_64;
+
+use std::{fs::File, io::Read};
+
+pub use aarch64::AArch64;
+pub use i386::I386;
+pub use x86_64::X86_64;
+
+// SIGTRAP si_code values
+pub const SI_USER: i32 = 0x0;
+pub const TRAP_BRKPT: i32 = 0x1;
+pub const TRAP_TRACE: i32 = 0x2;
+pub const SI_KERNEL: i32 = 0x80;
+
+/// Register of the general purpose register set returned by `PTRACE_GETREGSET`
+/// with `NT_PRSTATUS`.
+#[derive(Clone, Copy)]
+pub struct RegInfo {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl RegInfo {
+    pub const fn new(name: &'static str, offset: usize, size: usize) -> RegInfo {
+        RegInfo { name, offset, size }
+    }
+}
+
+/// Registers the unwinder moves from frame to frame, paired with their DWARF numbers.
+pub struct UnwindRegisters {
+    pub stack_pointer: (u16, &'static str),
+    pub frame_pointer: (u16, &'static str),
+    // holds the return address on function entry, None if the call pushes it on the stack
+    pub link_register: Option<(u16, &'static str)>,
+}
+
+/// Bits of a full register which are accessible under own name, like eax or w0.
+pub struct SubRegInfo {
+    pub name: String,
+    pub parent: &'static str,
+    pub shift: u32,
+    pub bits: u32,
+    /// Writes clear the parent bits above the sub-register, like 32-bit writes on x86-64.
+    pub zero_extend: bool,
+}
+
+/// Architecture specific details of registers, breakpoints and traps.
+pub trait Arch: Sync {
+    fn name(&self) -> &'static str;
+
+    /// `e_machine` of ELF files built for the architecture.
+    fn elf_machine(&self) -> u16;
+
+    /// Size of addresses and pointers in the program memory.
+    fn pointer_size(&self) -> usize;
+
+    /// Registers in architectural order.
+    fn registers(&self) -> &'static [RegInfo];
+
+    fn sub_registers(&self) -> Vec<SubRegInfo>;
+
+    /// Size of the `NT_PRSTATUS` register set.
+    fn regset_size(&self) -> usize;
+
+    fn pc_register(&self) -> &'static str;
+
+    fn flags_register(&self) -> &'static str;
+
+    /// Names and bit positions of flags in the flags register.
+    fn flags(&self) -> &'static [(&'static str, u32)];
+
+    /// Instruction which stops the program with SIGTRAP.
+    fn breakpoint_instruction(&self) -> &'static [u8];
+
+    /// Distance from the breakpoint address to PC reported after the trap.
+    fn breakpoint_pc_offset(&self) -> u64;
+
+    /// `si_code` values of SIGTRAP sent after hitting a breakpoint.
+    fn breakpoint_trap_codes(&self) -> &'static [i32];
+
+    /// `si_code` values of SIGTRAP sent after `PTRACE_SINGLESTEP`.
+    fn step_trap_codes(&self) -> &'static [i32];
+
+    fn unwind_registers(&self) -> UnwindRegisters;
+
+    /// Returns names of flags set in the flags register value.
+    fn decode_flags(&self, value: u64) -> Vec<&'static str> {
+        self.flags()
+            .iter()
+            .filter(|(_, bit)| value & 1 << bit != 0)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+}
+
+/// Returns the architecture the debugger is built for.
+#[cfg(target_arch = "x86_64")]
+pub fn host() -> &'static dyn Arch {
+    &X86_64
+}
+
+#[cfg(target_arch = "aarch64")]
+pub fn host() -> &'static dyn Arch {
+    &AArch64
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+compile_error!("mdbg runs only on x86-64 and AArch64 Linux");
+
+/// Returns architecture of the ELF file by its header.
+pub fn for_elf(data: &[u8]) -> Result<&'static dyn Arch, String> {
+    if data.len() < 20 || &data[..4] != b"\x7fELF" {
+        Err("not an ELF file")?;
+    }
+    // all supported architectures are little-endian
+    let machine = u16::from_le_bytes([data[18], data[19]]);
+    let arch = [&I386 as &'static dyn Arch, &X86_64, &AArch64]
+        .into_iter()
+        .find(|arch| arch.elf_machine() == machine)
+        .ok_or(format!("unsupported ELF machine {}", machine))?;
+
+    // x86-64 kernel runs i386 programs in compatibility mode
+    let compat = host().name() == X86_64.name() && arch.name() == I386.name();
+    if arch.name() != host().name() && !compat {
+        Err(format!(
+            "{} programs can't be debugged on {}",
+            arch.name(),
+            host().name()
+        ))?;
+    }
+
+    Ok(arch)
+}
+
+pub fn for_elf_file(path: &str) -> Result<&'static dyn Arch, String> {
+    let mut header = [0u8; 20];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map_err(|e| format!("failed to read ELF header of {}: {}", path, e))?;
+
+    for_elf(&header)
+}
@@ -0,0 +1,250 @@
+use nix::libc;
+use object::elf;
+
+use super::{
+    Arch, RegInfo, SubRegInfo, UnwindRegisters, SI_KERNEL, SI_USER, TRAP_BRKPT, TRAP_TRACE,
+};
+
+pub struct X86_64;
+
+// offsets follow user_regs_struct
+const REGISTERS: [RegInfo; 26] = [
+    RegInfo::new("rax", 80, 8),
+    RegInfo::new("rbx", 40, 8),
+    RegInfo::new("rcx", 88, 8),
+    RegInfo::new("rdx", 96, 8),
+    RegInfo::new("rsi", 104, 8),
+    RegInfo::new("rdi", 112, 8),
+    RegInfo::new("rbp", 32, 8),
+    RegInfo::new("rsp", 152, 8),
+    RegInfo::new("r8", 72, 8),
+    RegInfo::new("r9", 64, 8),
+    RegInfo::new("r10", 56, 8),
+    RegInfo::new("r11", 48, 8),
+    RegInfo::new("r12", 24, 8),
+    RegInfo::new("r13", 16, 8),
+    RegInfo::new("r14", 8, 8),
+    RegInfo::new("r15", 0, 8),
+    RegInfo::new("rip", 128, 8),
+    RegInfo::new("eflags", 144, 8),
+    RegInfo::new("cs", 136, 8),
+    RegInfo::new("ss", 160, 8),
+    RegInfo::new("ds", 184, 8),
+    RegInfo::new("es", 192, 8),
+    RegInfo::new("fs", 200, 8),
+    RegInfo::new("gs", 208, 8),
+    RegInfo::new("fsbase", 168, 8),
+    RegInfo::new("gsbase", 176, 8),
+];
+
+pub(super) const EFLAGS: [(&str, u32); 14] = [
+    ("CF", 0),
+    ("PF", 2),
+    ("AF", 4),
+    ("ZF", 6),
+    ("SF", 7),
+    ("TF", 8),
+    ("IF", 9),
+    ("DF", 10),
+    ("OF", 11),
+    ("NT", 14),
+    ("RF", 16),
+    ("VM", 17),
+    ("AC", 18),
+    ("ID", 21),
+];
+
+impl Arch for X86_64 {
+    fn name(&self) -> &'static str {
+        "x86_64"
+    }
+
+    fn elf_machine(&self) -> u16 {
+        elf::EM_X86_64
+    }
+
+    fn pointer_size(&self) -> usize {
+        8
+    }
+
+    fn registers(&self) -> &'static [RegInfo] {
+        &REGISTERS
+    }
+
+    fn sub_registers(&self) -> Vec<SubRegInfo> {
+        let sub = |name: String, parent, shift, bits| SubRegInfo {
+            name,
+            parent,
+            shift,
+            bits,
+            zero_extend: bits == 32,
+        };
+        let mut result = Vec::new();
+
+        for (parent, x) in [("rax", "a"), ("rbx", "b"), ("rcx", "c"), ("rdx", "d")] {
+            result.push(sub(format!("e{}x", x), parent, 0, 32));
+            result.push(sub(format!("{}x", x), parent, 0, 16));
+            result.push(sub(format!("{}l", x), parent, 0, 8));
+            result.push(sub(format!("{}h", x), parent, 8, 8));
+        }
+        for (parent, x) in [("rsi", "si"), ("rdi", "di"), ("rbp", "bp"), ("rsp", "sp")] {
+            result.push(sub(format!("e{}", x), parent, 0, 32));
+            result.push(sub(x.to_owned(), parent, 0, 16));
+            result.push(sub(format!("{}l", x), parent, 0, 8));
+        }
+        for parent in ["r8", "r9", "r10", "r11", "r12", "r13", "r14", "r15"] {
+            result.push(sub(format!("{}d", parent), parent, 0, 32));
+            result.push(sub(format!("{}w", parent), parent, 0, 16));
+            result.push(sub(format!("{}l", parent), parent, 0, 8));
+        }
+        result.push(sub("eip".to_owned(), "rip", 0, 32));
+
+        result
+    }
+
+    fn regset_size(&self) -> usize {
+        std::mem::size_of::<libc::user_regs_struct>()
+    }
+
+    fn pc_register(&self) -> &'static str {
+        "rip"
+    }
+
+    fn flags_register(&self) -> &'static str {
+        "eflags"
+    }
+
+    fn flags(&self) -> &'static [(&'static str, u32)] {
+        &EFLAGS
+    }
+
+    fn breakpoint_instruction(&self) -> &'static [u8] {
+        // int3
+        &[0xCC]
+    }
+
+    fn breakpoint_pc_offset(&self) -> u64 {
+        // RIP points after int3 when the trap is reported
+        1
+    }
+
+    fn breakpoint_trap_codes(&self) -> &'static [i32] {
+        &[SI_KERNEL, TRAP_BRKPT]
+    }
+
+    fn step_trap_codes(&self) -> &'static [i32] {
+        &[SI_USER, TRAP_TRACE]
+    }
+
+    fn unwind_registers(&self) -> UnwindRegisters {
+        UnwindRegisters {
+            stack_pointer: (7, "rsp"),
+            frame_pointer: (6, "rbp"),
+            link_register: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reg::{self, RegSelector};
+
+    // user_regs_struct has 27 registers, regset_size is of the host
+    fn regset() -> Vec<u8> {
+        (0..27u64)
+            .flat_map(|i| (0x1122_3344_5566_7700 | i).to_le_bytes())
+            .collect()
+    }
+
+    fn get(regs: &[u8], name: &str) -> Result<u64, String> {
+        reg::get_register_value(&X86_64, regs, &RegSelector::Name(name))
+    }
+
+    fn set(regs: &mut [u8], name: &str, value: u64) -> Result<(), String> {
+        reg::set_register_value(&X86_64, regs, &RegSelector::Name(name), value)
+    }
+
+    #[test]
+    fn registers_follow_user_regs_struct() {
+        let regs = regset();
+        assert_eq!(get(&regs, "r15").unwrap(), 0x1122_3344_5566_7700);
+        assert_eq!(get(&regs, "rax").unwrap(), 0x1122_3344_5566_770a);
+        assert_eq!(get(&regs, "rip").unwrap(), 0x1122_3344_5566_7710);
+        assert_eq!(get(&regs, "eflags").unwrap(), 0x1122_3344_5566_7712);
+        assert_eq!(get(&regs, "rsp").unwrap(), 0x1122_3344_5566_7713);
+        assert_eq!(get(&regs, "gs").unwrap(), 0x1122_3344_5566_771a);
+        assert!(get(&regs, "pc").is_err());
+    }
+
+    #[test]
+    fn sub_registers_are_masked_and_shifted() {
+        let regs = regset();
+        assert_eq!(get(&regs, "eax").unwrap(), 0x5566_770a);
+        assert_eq!(get(&regs, "ax").unwrap(), 0x770a);
+        assert_eq!(get(&regs, "ah").unwrap(), 0x77);
+        assert_eq!(get(&regs, "al").unwrap(), 0x0a);
+        assert_eq!(get(&regs, "sil").unwrap(), 0x0d);
+        assert_eq!(get(&regs, "r8d").unwrap(), 0x5566_7709);
+        assert_eq!(get(&regs, "r8w").unwrap(), 0x7709);
+        assert_eq!(get(&regs, "eip").unwrap(), 0x5566_7710);
+        assert!(get(&regs, "sih").is_err());
+    }
+
+    #[test]
+    fn narrow_writes_keep_other_bits() {
+        let mut regs = regset();
+        set(&mut regs, "ah", 0xab).unwrap();
+        assert_eq!(get(&regs, "rax").unwrap(), 0x1122_3344_5566_ab0a);
+        set(&mut regs, "al", 0xcd).unwrap();
+        assert_eq!(get(&regs, "rax").unwrap(), 0x1122_3344_5566_abcd);
+        set(&mut regs, "ax", 0x1234).unwrap();
+        assert_eq!(get(&regs, "rax").unwrap(), 0x1122_3344_5566_1234);
+        set(&mut regs, "r9w", 0xffff).unwrap();
+        assert_eq!(get(&regs, "r9").unwrap(), 0x1122_3344_5566_ffff);
+
+        assert!(set(&mut regs, "ah", 0x100).is_err());
+        assert!(set(&mut regs, "ax", 0x1_0000).is_err());
+        assert_eq!(get(&regs, "rax").unwrap(), 0x1122_3344_5566_1234);
+    }
+
+    #[test]
+    fn dword_writes_zero_extend() {
+        let mut regs = regset();
+        set(&mut regs, "eax", 0xdead_beef).unwrap();
+        assert_eq!(get(&regs, "rax").unwrap(), 0xdead_beef);
+        set(&mut regs, "r8d", 1).unwrap();
+        assert_eq!(get(&regs, "r8").unwrap(), 1);
+        set(&mut regs, "esp", 0x7fff_0000).unwrap();
+        assert_eq!(get(&regs, "rsp").unwrap(), 0x7fff_0000);
+
+        assert!(set(&mut regs, "ebx", 1 << 32).is_err());
+        assert_eq!(get(&regs, "rbx").unwrap(), 0x1122_3344_5566_7705);
+    }
+
+    #[test]
+    fn eflags_are_decoded() {
+        assert_eq!(X86_64.decode_flags(0x246), ["PF", "ZF", "IF"]);
+        assert_eq!(X86_64.decode_flags(0x202), ["IF"]);
+        assert_eq!(X86_64.decode_flags(0x8c1), ["CF", "ZF", "SF", "OF"]);
+        assert_eq!(
+            X86_64.decode_flags(0x27_4fd5).len(),
+            EFLAGS.len(),
+            "every flag bit is decoded"
+        );
+    }
+
+    #[test]
+    fn single_flags_are_set() {
+        let mut regs = regset();
+        set(&mut regs, "eflags", 0x246).unwrap();
+        assert_eq!(get(&regs, "zf").unwrap(), 1);
+        assert_eq!(get(&regs, "cf").unwrap(), 0);
+
+        set(&mut regs, "cf", 1).unwrap();
+        set(&mut regs, "zf", 0).unwrap();
+        assert_eq!(get(&regs, "eflags").unwrap(), 0x207);
+        assert!(set(&mut regs, "of", 2).is_err());
+        assert!(get(&regs, "ZF").is_err());
+    }
+}
@@ -1,49 +1,37 @@
-use std::os::raw::c_void;
-
-use nix::{sys::ptrace, unistd::Pid};
+use crate::target::Target;
 
 pub struct Breakpoint {
-    program_pid: i32,
     addr: u64,
     enabled: bool,
-    replaced_instruction_opcode: Option<u8>,
+    // trap instruction of the architecture, at most one word long
+    instruction: &'static [u8],
+    replaced_instruction: Option<Vec<u8>>,
 }
 
 impl Breakpoint {
-    pub fn new(program_pid: i32, addr: u64) -> Breakpoint {
+    pub fn new(addr: u64, instruction: &'static [u8]) -> Breakpoint {
         Breakpoint {
-            program_pid,
             addr,
             enabled: false,
-            replaced_instruction_opcode: None,
+            instruction,
+            replaced_instruction: None,
         }
     }
 
-    pub fn switch(&mut self, enable: bool) -> Result<(), String> {
-        let pid = Pid::from_raw(self.program_pid);
-        let instruction = ptrace::read(pid, self.addr as *mut c_void)
-            .map_err(|e| format!("failed to peek instruction: {}", e))?;
-
-        let replaced_instruction = if enable {
-            self.replaced_instruction_opcode = Some((instruction & 0xFF) as u8);
-            const INT3_OPCODE: i64 = 0xCC;
-            (instruction & !0xFF) | INT3_OPCODE
+    pub fn switch<T: Target + ?Sized>(
+        &mut self,
+        target: &mut T,
+        enable: bool,
+    ) -> Result<(), String> {
+        if enable {
+            self.replaced_instruction =
+                Some(target.insert_breakpoint(self.addr, self.instruction)?);
         } else {
-            (instruction & !0xFF)
-                | self
-                    .replaced_instruction_opcode
-                    .ok_or_else(|| "opcode of replaced instruction isn't saved")?
-                    as i64
-        };
-
-        // SAFETY: addr pointer come outside the program. replaced_instruction isn't pointer, it's a data.
-        unsafe {
-            ptrace::write(
-                pid,
-                self.addr as *mut c_void,
-                replaced_instruction as *mut c_void,
-            )
-            .map_err(|e| format!("failed to poke breakpoint instruction: {}", e))?;
+            let replaced = self
+                .replaced_instruction
+                .as_ref()
+                .ok_or("opcode of replaced instruction isn't saved")?;
+            target.remove_breakpoint(self.addr, replaced)?;
         }
         self.enabled = enable;
 
@@ -51,6 +39,38 @@ impl Breakpoint {
     }
 
     pub fn enabled(&self) -> bool {
-        return self.enabled;
+        self.enabled
+    }
+
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    pub fn instruction(&self) -> &'static [u8] {
+        self.instruction
+    }
+
+    /// Returns the original program code hidden under the breakpoint.
+    pub fn replaced_bytes(&self) -> Option<&[u8]> {
+        self.replaced_instruction.as_deref()
     }
+
+    /// Updates an original byte after program code under the enabled breakpoint was rewritten.
+    pub fn set_replaced_byte(&mut self, offset: usize, byte: u8) {
+        if let Some(replaced) = &mut self.replaced_instruction {
+            replaced[offset] = byte;
+        }
+    }
+}
+
+/// Returns first bytes of the word read from little-endian memory.
+pub fn word_bytes(word: i64, len: usize) -> Vec<u8> {
+    word.to_le_bytes()[..len].to_vec()
+}
+
+/// Replaces first bytes of the word read from little-endian memory.
+pub fn patch_word(word: i64, bytes: &[u8]) -> i64 {
+    let mut result = word.to_le_bytes();
+    result[..bytes.len()].copy_from_slice(bytes);
+    i64::from_le_bytes(result)
 }
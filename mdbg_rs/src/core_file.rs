@@ -0,0 +1,622 @@
+use std::{collections::HashSet, fs::File, os::unix::fs::FileExt};
+
+use object::{
+    elf,
+    read::elf::{ElfFile, FileHeader, ProgramHeader},
+    Endianness, FileKind,
+};
+
+use crate::{
+    arch::{self, Arch},
+    fpregs::FpRegs,
+    linux_maps::{MemoryRegion, Permissions},
+    target::{Target, TargetStop},
+};
+
+pub(crate) const NT_X86_XSTATE: u32 = 0x202;
+
+/// Offsets of `struct elf_prstatus` fields, longs and timevals depend on the pointer size.
+pub(crate) struct PrStatusLayout {
+    pub cursig: usize,
+    // followed by ppid, pgrp and sid
+    pub pid: usize,
+    pub regs: usize,
+    pub size: usize,
+}
+
+pub(crate) fn prstatus_layout(pointer_size: usize, regset_size: usize) -> PrStatusLayout {
+    // siginfo header, cursig with padding, then pending and held signal masks
+    let pid = 16 + 2 * pointer_size;
+    // pid, ppid, pgrp and sid followed by four timevals
+    let regs = pid + 16 + 8 * pointer_size;
+    PrStatusLayout {
+        cursig: 12,
+        pid,
+        regs,
+        // pr_fpvalid follows the registers
+        size: (regs + regset_size + 4).next_multiple_of(pointer_size),
+    }
+}
+
+/// Offsets of `struct elf_prpsinfo` fields.
+pub(crate) struct PrPsInfoLayout {
+    // uid and gid follow each other
+    pub uid: usize,
+    pub id_size: usize,
+    // followed by ppid, pgrp and sid
+    pub pid: usize,
+    pub fname: usize,
+    pub psargs: usize,
+    pub size: usize,
+}
+
+pub(crate) const PRPSINFO_FNAME_LEN: usize = 16;
+pub(crate) const PRPSINFO_PSARGS_LEN: usize = 80;
+
+pub(crate) fn prpsinfo_layout(pointer_size: usize) -> PrPsInfoLayout {
+    // state, sname, zomb and nice chars followed by pr_flag
+    let uid = (4usize).next_multiple_of(pointer_size) + pointer_size;
+    // 32-bit x86 has 16-bit uid and gid
+    let id_size = pointer_size / 2;
+    let pid = uid + 2 * id_size;
+    let fname = pid + 16;
+    let psargs = fname + PRPSINFO_FNAME_LEN;
+    PrPsInfoLayout {
+        uid,
+        id_size,
+        pid,
+        fname,
+        psargs,
+        size: (psargs + PRPSINFO_PSARGS_LEN).next_multiple_of(pointer_size),
+    }
+}
+
+/// File mapped into the process memory, described by the `NT_FILE` note.
+pub struct FileMapping {
+    pub start: u64,
+    pub end: u64,
+    // in bytes
+    pub offset: u64,
+    pub path: String,
+}
+
+impl FileMapping {
+    fn contains(&self, addr: u64) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+}
+
+/// Process memory saved in a `PT_LOAD` segment, pages which weren't dumped
+/// have no data in the file.
+struct Segment {
+    vaddr: u64,
+    memsz: u64,
+    offset: u64,
+    filesz: u64,
+    flags: u32,
+}
+
+impl Segment {
+    fn contains(&self, addr: u64) -> bool {
+        (self.vaddr..self.vaddr + self.memsz).contains(&addr)
+    }
+}
+
+/// Thread state from `NT_PRSTATUS` and floating point notes following it.
+struct Thread {
+    tid: i32,
+    signal: i32,
+    regs: Vec<u8>,
+    xsave: Option<Vec<u8>>,
+    fxsave: Option<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Contents {
+    segments: Vec<Segment>,
+    files: Vec<FileMapping>,
+    threads: Vec<Thread>,
+    pid: Option<i32>,
+    command_line: Option<String>,
+    auxv: Vec<u8>,
+}
+
+/// ELF core file of a process which crashed or was dumped on request.
+pub struct CoreFile {
+    path: String,
+    data: memmap::Mmap,
+    arch: &'static dyn Arch,
+    contents: Contents,
+    // index of the thread registers are read from
+    current: usize,
+}
+
+impl CoreFile {
+    pub fn load(path: &str) -> Result<CoreFile, String> {
+        let file = File::open(path).map_err(|e| format!("failed to open file {}: {}", path, e))?;
+        let data =
+            unsafe { memmap::Mmap::map(&file).map_err(|e| format!("failed to mmap file: {}", e))? };
+        let arch = arch::for_elf(&data)?;
+
+        let contents = match FileKind::parse(&*data) {
+            Ok(FileKind::Elf32) => parse::<elf::FileHeader32<Endianness>>(&data, arch)?,
+            Ok(FileKind::Elf64) => parse::<elf::FileHeader64<Endianness>>(&data, arch)?,
+            _ => Err(format!("{} isn't an ELF file", path))?,
+        };
+        if contents.threads.is_empty() {
+            Err(format!("{} has no NT_PRSTATUS notes", path))?;
+        }
+
+        Ok(CoreFile {
+            path: path.to_owned(),
+            data,
+            arch,
+            contents,
+            current: 0,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn arch(&self) -> &'static dyn Arch {
+        self.arch
+    }
+
+    /// Returns pid of the dumped process.
+    pub fn pid(&self) -> i32 {
+        self.contents.pid.unwrap_or(self.contents.threads[0].tid)
+    }
+
+    /// Returns arguments of the dumped process, they are truncated to 80 bytes by the kernel.
+    pub fn command_line(&self) -> Option<&str> {
+        self.contents.command_line.as_deref()
+    }
+
+    /// Returns ids of dumped threads, the thread which caused the dump goes first.
+    pub fn threads(&self) -> Vec<i32> {
+        self.contents.threads.iter().map(|t| t.tid).collect()
+    }
+
+    pub fn current_thread(&self) -> i32 {
+        self.contents.threads[self.current].tid
+    }
+
+    pub fn select_thread(&mut self, tid: i32) -> Result<(), String> {
+        self.current = self
+            .contents
+            .threads
+            .iter()
+            .position(|t| t.tid == tid)
+            .ok_or(format!("thread {} isn't in the core file", tid))?;
+
+        Ok(())
+    }
+
+    /// Returns signal which was being delivered to the current thread, 0 if there was none.
+    pub fn signal(&self) -> i32 {
+        self.contents.threads[self.current].signal
+    }
+
+    pub fn file_mappings(&self) -> &[FileMapping] {
+        &self.contents.files
+    }
+
+    /// Reads up to `len` bytes, fewer bytes are returned if the range runs into memory
+    /// which is neither saved in the core file nor mapped from a readable file.
+    pub fn read(&self, addr: u64, len: usize) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(len);
+
+        while buf.len() < len {
+            let addr = addr + buf.len() as u64;
+            let left = (len - buf.len()) as u64;
+            let segment = match self.contents.segments.iter().find(|s| s.contains(addr)) {
+                Some(segment) => segment,
+                None => break,
+            };
+
+            let offset = addr - segment.vaddr;
+            if offset < segment.filesz {
+                let start = (segment.offset + offset) as usize;
+                let end = start + left.min(segment.filesz - offset) as usize;
+                match self.data.get(start..end) {
+                    Some(data) => buf.extend_from_slice(data),
+                    // core file is truncated
+                    None => break,
+                }
+                continue;
+            }
+
+            // pages which weren't dumped, like program code, are read from the mapped file
+            let mapping = match self.contents.files.iter().find(|f| f.contains(addr)) {
+                Some(mapping) => mapping,
+                None => break,
+            };
+            let chunk_len = left
+                .min(segment.vaddr + segment.memsz - addr)
+                .min(mapping.end - addr) as usize;
+            let mut chunk = vec![0u8; chunk_len];
+            let read = File::open(&mapping.path)
+                .and_then(|file| file.read_at(&mut chunk, mapping.offset + addr - mapping.start))
+                .unwrap_or(0);
+            buf.extend_from_slice(&chunk[..read]);
+            if read < chunk_len {
+                break;
+            }
+        }
+
+        buf
+    }
+
+    /// Returns memory regions of the dumped process like `/proc/<pid>/maps` describes them.
+    pub fn memory_regions(&self) -> Vec<MemoryRegion> {
+        self.contents
+            .segments
+            .iter()
+            .map(|segment| {
+                let mapping = self
+                    .contents
+                    .files
+                    .iter()
+                    .find(|f| f.contains(segment.vaddr));
+                MemoryRegion {
+                    start: segment.vaddr,
+                    end: segment.vaddr + segment.memsz,
+                    perms: Permissions {
+                        read: segment.flags & elf::PF_R != 0,
+                        write: segment.flags & elf::PF_W != 0,
+                        exec: segment.flags & elf::PF_X != 0,
+                        // core file doesn't tell shared mappings apart
+                        shared: false,
+                    },
+                    offset: mapping
+                        .map(|m| m.offset + segment.vaddr - m.start)
+                        .unwrap_or(0),
+                    dev: (0, 0),
+                    inode: 0,
+                    pathname: mapping.map(|m| m.path.clone()),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns paths of files mapped from the beginning together with their lowest address,
+    /// those are the executable, shared libraries and data files.
+    pub fn mapped_files(&self) -> Vec<(&str, u64)> {
+        let mut seen = HashSet::new();
+        self.contents
+            .files
+            .iter()
+            .filter(|f| f.offset == 0 && seen.insert(f.path.as_str()))
+            .map(|f| (f.path.as_str(), f.start))
+            .collect()
+    }
+}
+
+// dumped process can only be inspected
+impl Target for CoreFile {
+    fn arch(&self) -> &'static dyn Arch {
+        self.arch
+    }
+
+    fn pid(&self) -> i32 {
+        CoreFile::pid(self)
+    }
+
+    fn threads(&self) -> Vec<i32> {
+        CoreFile::threads(self)
+    }
+
+    fn current_thread(&self) -> i32 {
+        CoreFile::current_thread(self)
+    }
+
+    fn select_thread(&mut self, tid: i32) -> Result<(), String> {
+        CoreFile::select_thread(self, tid)
+    }
+
+    fn read_regs(&self) -> Result<Vec<u8>, String> {
+        Ok(self.contents.threads[self.current].regs.clone())
+    }
+
+    fn write_regs(&mut self, _regs: &[u8]) -> Result<(), String> {
+        Err("core file can't be modified".to_owned())
+    }
+
+    fn read_fp_regs(&self) -> Result<FpRegs, String> {
+        let thread = &self.contents.threads[self.current];
+        match (&thread.xsave, &thread.fxsave) {
+            (Some(xsave), _) => FpRegs::from_xsave(xsave.clone()),
+            (None, Some(fxsave)) => FpRegs::from_fxsave(fxsave.clone()),
+            (None, None) => Err(format!(
+                "core file has no fp registers of thread {}",
+                thread.tid
+            )),
+        }
+    }
+
+    fn write_fp_regs(&mut self, _regs: &mut FpRegs) -> Result<(), String> {
+        Err("core file can't be modified".to_owned())
+    }
+
+    fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+        Ok(self.read(addr, len))
+    }
+
+    fn write_memory(&mut self, _addr: u64, _data: &[u8]) -> Result<usize, String> {
+        Err("core file can't be modified".to_owned())
+    }
+
+    fn memory_regions(&self) -> Result<Vec<MemoryRegion>, String> {
+        Ok(CoreFile::memory_regions(self))
+    }
+
+    fn auxv(&self) -> Result<Vec<u8>, String> {
+        Ok(self.contents.auxv.clone())
+    }
+
+    fn wait_attach(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), String> {
+        Err("program is not running".to_owned())
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        Err("program is not running".to_owned())
+    }
+
+    fn wait(&mut self) -> Result<TargetStop, String> {
+        Err("program is not running".to_owned())
+    }
+
+    fn kill(&mut self) -> Result<(), String> {
+        Err("program is not running".to_owned())
+    }
+
+    fn detach(&mut self) -> Result<(), String> {
+        Err("program is not running".to_owned())
+    }
+}
+
+fn parse<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+    arch: &dyn Arch,
+) -> Result<Contents, String> {
+    let file =
+        ElfFile::<Elf>::parse(data).map_err(|e| format!("failed to parse core file: {}", e))?;
+    let endian = file.endian();
+    if file.raw_header().e_type(endian) != elf::ET_CORE {
+        Err("not a core file")?;
+    }
+
+    let mut contents = Contents::default();
+    for segment in file.raw_segments() {
+        match segment.p_type(endian) {
+            elf::PT_LOAD => contents.segments.push(Segment {
+                vaddr: segment.p_vaddr(endian).into(),
+                memsz: segment.p_memsz(endian).into(),
+                offset: segment.p_offset(endian).into(),
+                filesz: segment.p_filesz(endian).into(),
+                flags: segment.p_flags(endian),
+            }),
+            elf::PT_NOTE => {
+                let mut notes = match segment
+                    .notes(endian, data)
+                    .map_err(|e| format!("failed to parse notes: {}", e))?
+                {
+                    Some(notes) => notes,
+                    None => continue,
+                };
+                while let Some(note) = notes
+                    .next()
+                    .map_err(|e| format!("failed to parse note: {}", e))?
+                {
+                    if matches!(note.name(), b"CORE" | b"LINUX") {
+                        contents.add_note(note.n_type(endian), note.desc(), arch)?;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(contents)
+}
+
+impl Contents {
+    fn add_note(&mut self, n_type: u32, desc: &[u8], arch: &dyn Arch) -> Result<(), String> {
+        let pointer_size = arch.pointer_size();
+
+        match n_type {
+            elf::NT_PRSTATUS => {
+                let layout = prstatus_layout(pointer_size, arch.regset_size());
+                let regs = desc
+                    .get(layout.regs..layout.regs + arch.regset_size())
+                    .ok_or("NT_PRSTATUS note is too short")?;
+                self.threads.push(Thread {
+                    tid: read_le(desc, layout.pid, 4)? as i32,
+                    signal: read_le(desc, layout.cursig, 2)? as i32,
+                    regs: regs.to_vec(),
+                    xsave: None,
+                    fxsave: None,
+                });
+            }
+            elf::NT_PRPSINFO => {
+                let layout = prpsinfo_layout(pointer_size);
+                self.pid = Some(read_le(desc, layout.pid, 4)? as i32);
+                self.command_line = desc
+                    .get(layout.psargs..layout.psargs + PRPSINFO_PSARGS_LEN)
+                    .map(c_string);
+            }
+            elf::NT_FILE => self.files = parse_file_note(desc, pointer_size)?,
+            elf::NT_AUXV => self.auxv = desc.to_vec(),
+            // floating point notes follow NT_PRSTATUS of their thread
+            NT_X86_XSTATE => {
+                if let Some(thread) = self.threads.last_mut() {
+                    thread.xsave = Some(desc.to_vec());
+                }
+            }
+            // NT_FPREGSET has the FXSAVE layout on x86-64 only, i386 uses NT_PRXFPREG for it
+            elf::NT_FPREGSET | elf::NT_PRXFPREG if desc.len() == 512 => {
+                if let Some(thread) = self.threads.last_mut() {
+                    thread.fxsave = Some(desc.to_vec());
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `NT_FILE`: count and page size, `count` triples of start, end and
+/// offset in pages, then `count` NUL-terminated paths.
+fn parse_file_note(desc: &[u8], pointer_size: usize) -> Result<Vec<FileMapping>, String> {
+    let word = |index: usize| read_le(desc, index * pointer_size, pointer_size);
+    let count = word(0)? as usize;
+    let page_size = word(1)?;
+
+    let names_offset = (2 + 3 * count) * pointer_size;
+    let mut names = desc
+        .get(names_offset..)
+        .ok_or("NT_FILE note is too short")?
+        .split(|&b| b == 0);
+
+    (0..count)
+        .map(|i| {
+            let entry = 2 + 3 * i;
+            Ok(FileMapping {
+                start: word(entry)?,
+                end: word(entry + 1)?,
+                offset: word(entry + 2)? * page_size,
+                path: names
+                    .next()
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .ok_or("NT_FILE note has fewer paths than mappings")?,
+            })
+        })
+        .collect()
+}
+
+/// Reads little-endian unsigned integer of up to 8 bytes at the offset.
+fn read_le(data: &[u8], offset: usize, size: usize) -> Result<u64, String> {
+    let bytes = data.get(offset..offset + size).ok_or(format!(
+        "note is too short to read {} bytes at {}",
+        size, offset
+    ))?;
+    let mut raw = [0u8; 8];
+    raw[..size].copy_from_slice(bytes);
+    Ok(u64::from_le_bytes(raw))
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{AArch64, I386, X86_64};
+
+    fn prstatus(arch: &dyn Arch, pid: u32, signal: u16) -> Vec<u8> {
+        let layout = prstatus_layout(arch.pointer_size(), arch.regset_size());
+        let mut desc = vec![0u8; layout.size];
+        desc[layout.cursig..layout.cursig + 2].copy_from_slice(&signal.to_le_bytes());
+        desc[layout.pid..layout.pid + 4].copy_from_slice(&pid.to_le_bytes());
+        for (i, byte) in desc[layout.regs..layout.regs + arch.regset_size()]
+            .iter_mut()
+            .enumerate()
+        {
+            *byte = i as u8;
+        }
+        desc
+    }
+
+    #[test]
+    fn prstatus_matches_kernel_structs() {
+        // offsetof(struct elf_prstatus, pr_pid), pr_reg and sizeof
+        let layouts = [
+            (&X86_64 as &dyn Arch, 32, 112, 336),
+            (&I386, 24, 72, 144),
+            (&AArch64, 32, 112, 392),
+        ];
+        for (arch, pid, regs, size) in layouts {
+            let layout = prstatus_layout(arch.pointer_size(), arch.regset_size());
+            assert_eq!(layout.cursig, 12, "{}", arch.name());
+            assert_eq!(layout.pid, pid, "{}", arch.name());
+            assert_eq!(layout.regs, regs, "{}", arch.name());
+            assert_eq!(layout.size, size, "{}", arch.name());
+        }
+    }
+
+    #[test]
+    fn prpsinfo_matches_kernel_structs() {
+        // offsetof(struct elf_prpsinfo, pr_uid), pr_pid, pr_fname, pr_psargs and sizeof
+        let layouts = [(8, 16, 4, 24, 40, 56, 136), (4, 8, 2, 12, 28, 44, 124)];
+        for (pointer_size, uid, id_size, pid, fname, psargs, size) in layouts {
+            let layout = prpsinfo_layout(pointer_size);
+            assert_eq!(layout.uid, uid);
+            assert_eq!(layout.id_size, id_size);
+            assert_eq!(layout.pid, pid);
+            assert_eq!(layout.fname, fname);
+            assert_eq!(layout.psargs, psargs);
+            assert_eq!(layout.size, size);
+        }
+    }
+
+    #[test]
+    fn notes_describe_threads() {
+        for arch in [&X86_64 as &dyn Arch, &I386, &AArch64] {
+            let mut contents = Contents::default();
+            contents
+                .add_note(elf::NT_PRSTATUS, &prstatus(arch, 42, 11), arch)
+                .unwrap();
+            contents
+                .add_note(elf::NT_FPREGSET, &[7; 512], arch)
+                .unwrap();
+            contents
+                .add_note(elf::NT_PRSTATUS, &prstatus(arch, 43, 0), arch)
+                .unwrap();
+
+            let threads = &contents.threads;
+            assert_eq!(threads.len(), 2);
+            assert_eq!((threads[0].tid, threads[0].signal), (42, 11));
+            assert_eq!((threads[1].tid, threads[1].signal), (43, 0));
+            assert_eq!(threads[0].regs.len(), arch.regset_size());
+            assert_eq!(threads[0].regs[..3], [0, 1, 2]);
+            // floating point note belongs to the thread before it
+            assert_eq!(threads[0].fxsave.as_deref(), Some(&[7; 512][..]));
+            assert!(threads[1].fxsave.is_none());
+        }
+    }
+
+    #[test]
+    fn prpsinfo_note_gives_pid_and_command_line() {
+        for arch in [&X86_64 as &dyn Arch, &I386] {
+            let layout = prpsinfo_layout(arch.pointer_size());
+            let mut desc = vec![0u8; layout.size];
+            desc[layout.pid..layout.pid + 4].copy_from_slice(&1234u32.to_le_bytes());
+            let args = b"./calls --verbose ";
+            desc[layout.psargs..layout.psargs + args.len()].copy_from_slice(args);
+
+            let mut contents = Contents::default();
+            contents.add_note(elf::NT_PRPSINFO, &desc, arch).unwrap();
+            assert_eq!(contents.pid, Some(1234));
+            assert_eq!(contents.command_line.as_deref(), Some("./calls --verbose"));
+        }
+    }
+
+    #[test]
+    fn short_prstatus_is_rejected() {
+        let desc = prstatus(&X86_64, 1, 0);
+        let mut contents = Contents::default();
+        assert!(contents
+            .add_note(elf::NT_PRSTATUS, &desc[..200], &X86_64)
+            .is_err());
+        assert!(contents.threads.is_empty());
+    }
+}
@@ -1,213 +1,1280 @@
-use std::{collections::HashMap, os::raw::c_void};
-
-use nix::{
-    sys::{
-        ptrace,
-        signal::Signal,
-        wait::{self, waitpid},
-    },
-    unistd::Pid,
-};
+use std::{collections::HashMap, fs, path::Path};
 
 use crate::{
+    arch::{self, Arch},
     breakpoint::Breakpoint,
-    dwarf::Dwarf,
-    reg::{self, Reg, RegSelector},
+    core_file::CoreFile,
+    disasm::{self, Instruction},
+    dwarf::SourceLocation,
+    gcore,
+    image::{self, Image},
+    launcher::Launcher,
+    linux_maps,
+    reg::{self, RegSelector},
+    search::{self, SearchMatch, SearchPattern, SearchScope},
+    shared_libs::{self, Rendezvous, SharedLibrary},
+    target::{Target, TargetStop},
+    unwind::{self, CallFrames, Frame},
 };
 
-pub struct Debugger<'a> {
-    program_pid: i32,
-    dwarf: Dwarf<'a>,
-    load_addr: Option<u64>,
+const DISASSEMBLE_BATCH: usize = 256;
+
+/// Debugger of a program behind the target, `dyn Target` lets the backend be chosen at runtime.
+pub struct Debugger<'a, T: Target + ?Sized = dyn Target> {
+    target: Box<T>,
+    executable: Image<'a>,
+    // difference between runtime and DWARF addresses of the executable
+    load_bias: Option<u64>,
+    libraries: Vec<SharedLibrary<'a>>,
+    rendezvous: Option<Rendezvous>,
     breakpoints: HashMap<u64, Breakpoint>,
+    // deleted breakpoints leave None behind, so ids of others don't change
+    user_breakpoints: Vec<Option<UserBreakpoint>>,
+    // ids of pending breakpoints resolved since the last check
+    activated_breakpoints: Vec<usize>,
+    alive: bool,
+}
+
+struct UserBreakpoint {
+    reference: BreakpointRef,
+    // None while breakpoint is pending
+    addr: Option<u64>,
 }
 
 impl<'a> Debugger<'a> {
-    pub fn new(program_pid: i32, dwarf: Dwarf<'a>) -> Debugger<'a> {
+    /// Creates a debugger for post-mortem debugging of the program which dumped the core.
+    pub fn from_core(executable: Image<'a>, core: CoreFile) -> Result<Debugger<'a>, String> {
+        if core.arch().name() != executable.arch().name() {
+            Err(format!(
+                "core file of {} program doesn't match {} executable",
+                core.arch().name(),
+                executable.arch().name()
+            ))?;
+        }
+
+        let (load_addr, libraries) = load_core_images(&executable, &core)
+            .map_err(|e| format!("failed to load mapped files: {}", e))?;
+        let mut debugger: Debugger = Debugger::new(Box::new(core), executable);
+        debugger.alive = false;
+        debugger.libraries = libraries;
+        debugger.set_load_addr(load_addr)?;
+
+        Ok(debugger)
+    }
+}
+
+impl<'a, T: Target + ?Sized> Debugger<'a, T> {
+    pub fn new(target: Box<T>, executable: Image<'a>) -> Debugger<'a, T> {
         Debugger {
-            program_pid,
-            dwarf,
-            load_addr: None,
+            target,
+            executable,
+            load_bias: None,
+            libraries: Vec::new(),
+            rendezvous: None,
             breakpoints: HashMap::new(),
+            user_breakpoints: Vec::new(),
+            activated_breakpoints: Vec::new(),
+            alive: true,
         }
     }
 
-    pub fn set_load_addr(&mut self, addr: u64) {
-        self.load_addr = Some(addr);
+    pub(crate) fn target(&self) -> &T {
+        &self.target
     }
 
-    pub fn continue_execution(&mut self) -> Result<Option<i32>, String> {
-        let pid = Pid::from_raw(self.program_pid);
+    /// Returns ids of threads registers can be read from.
+    pub fn threads(&self) -> Vec<i32> {
+        self.target.threads()
+    }
 
-        self.step_over_breakpoint()
-            .map_err(|e| format!("failed to step over breakpoint: {}", e))?;
+    pub fn current_thread(&self) -> i32 {
+        self.target.current_thread()
+    }
 
-        ptrace::cont(pid, None).map_err(|e| format!("failed to continue program: {}", e))?;
-        self.wait_trap(false)
-            .map_err(|e| format!("failed to wait trap: {}", e))
+    pub fn select_thread(&mut self, tid: i32) -> Result<(), String> {
+        self.target.select_thread(tid)
     }
 
-    pub fn set_breakpoint(&mut self, reference: BreakpointRef) -> Result<(), String> {
-        let load_addr = self.load_addr.ok_or("load addr not set")?;
-        let addr = match reference {
-            BreakpointRef::Addr(addr) => Some(addr),
-            BreakpointRef::Line { filename, line } => self
-                .dwarf
-                .get_source_line_addr(filename, line)
-                .map_err(|e| format!("failed to get addr of source line: {}", e))?
-                .map(|addr| addr + load_addr),
+    pub fn alive(&self) -> bool {
+        self.alive
+    }
+
+    /// Kills the current process if it's still alive and launches a new one,
+    /// then re-inserts every breakpoint set in the previous session.
+    pub fn restart(&mut self, launcher: &Launcher) -> Result<(), String> {
+        if self.alive {
+            self.kill_process()
+                .map_err(|e| format!("failed to kill process: {}", e))?;
+        }
+
+        self.target
+            .launch(launcher)
+            .map_err(|e| format!("failed to launch program: {}", e))?;
+        self.alive = true;
+        self.forget_process_image();
+
+        self.wait_attach()
+            .map_err(|e| format!("failed to wait trap: {}", e))?;
+        // load address of PIE changes between runs, so line breakpoints are resolved again
+        let load_addr = self
+            .file_load_addr(launcher.program_path())
+            .map_err(|e| format!("failed to get load addr: {}", e))?;
+        self.set_load_addr(load_addr)
+            .map_err(|e| format!("failed to restore breakpoints: {}", e))?;
+        self.track_shared_libraries()
+            .map_err(|e| format!("failed to track shared libraries: {}", e))
+    }
+
+    /// Drops everything tied to the memory of the current process image,
+    /// user breakpoints become pending.
+    fn forget_process_image(&mut self) {
+        self.load_bias = None;
+        self.libraries.clear();
+        self.rendezvous = None;
+        self.breakpoints.clear();
+        for bp in self.user_breakpoints.iter_mut().flatten() {
+            bp.addr = None;
+        }
+    }
+
+    /// Process replaced its image with exec, so executable and libraries are looked up again.
+    fn handle_exec(&mut self, exe_path: &str) -> Result<(), String> {
+        self.forget_process_image();
+
+        // other program has no debug info loaded, breakpoints in it stay pending
+        if fs::canonicalize(self.executable.path()).ok() == fs::canonicalize(exe_path).ok() {
+            let load_addr = self
+                .file_load_addr(exe_path)
+                .map_err(|e| format!("failed to get load addr: {}", e))?;
+            self.set_load_addr(load_addr)?;
+        }
+
+        self.track_shared_libraries()
+    }
+
+    /// Returns the auxiliary vector the kernel passed to the program.
+    pub fn auxv(&self) -> Result<Vec<u8>, String> {
+        self.target.auxv()
+    }
+
+    /// Returns the lowest address the executable is mapped at in the current process.
+    pub fn executable_load_addr(&self) -> Result<u64, String> {
+        match self.file_load_addr(self.executable.path()) {
+            Ok(addr) => Ok(addr),
+            // stubs without host I/O like QEMU can't show the memory map
+            Err(e) => {
+                let bias = match self.target.executable_load_bias() {
+                    Some(bias) => bias,
+                    None if !self.executable.relocatable() => 0,
+                    None => return Err(e),
+                };
+                Ok(self.executable.load_addr(bias))
+            }
+        }
+    }
+
+    /// Returns the lowest address the file is mapped at, the path is compared after resolving symlinks.
+    fn file_load_addr(&self, path: &str) -> Result<u64, String> {
+        let path =
+            fs::canonicalize(path).map_err(|e| format!("failed to resolve {}: {}", path, e))?;
+
+        self.memory_regions()?
+            .iter()
+            .filter(|region| region.pathname.as_deref().map(Path::new) == Some(path.as_path()))
+            .map(|region| region.start)
+            .min()
+            .ok_or(format!("{} isn't mapped", path.display()))
+    }
+
+    pub fn program_pid(&self) -> i32 {
+        self.target.pid()
+    }
+
+    /// Returns output of the program the target passed to the debugger, like console
+    /// output packets of a remote stub, the output of local programs isn't captured.
+    pub fn take_program_output(&self) -> Vec<u8> {
+        self.target.take_output()
+    }
+
+    /// Sets the lowest address the executable is mapped at.
+    pub fn set_load_addr(&mut self, addr: u64) -> Result<(), String> {
+        self.load_bias = Some(self.executable.load_bias(addr));
+        self.resolve_pending_breakpoints()
+    }
+
+    /// Puts a breakpoint on the dynamic linker rendezvous function and loads
+    /// shared libraries which are already in the process memory.
+    pub fn track_shared_libraries(&mut self) -> Result<(), String> {
+        let rendezvous = match shared_libs::find_rendezvous(&*self.target, &self.executable)? {
+            Some(rendezvous) => rendezvous,
+            None => return Ok(()),
         };
 
+        self.enable_breakpoint_at(rendezvous.breakpoint_addr)
+            .map_err(|e| format!("failed to set rendezvous breakpoint: {}", e))?;
+        self.rendezvous = Some(rendezvous);
+
+        self.sync_shared_libraries()
+    }
+
+    pub fn shared_libraries(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.libraries
+            .iter()
+            .map(|lib| (lib.image.path(), lib.load_bias))
+    }
+
+    fn sync_shared_libraries(&mut self) -> Result<(), String> {
+        let rendezvous = match &self.rendezvous {
+            Some(rendezvous) => rendezvous,
+            None => return Ok(()),
+        };
+        let entries = match shared_libs::read_link_map(self, rendezvous)
+            .map_err(|e| format!("failed to read link map: {}", e))?
+        {
+            Some(entries) => entries,
+            None => return Ok(()),
+        };
+
+        let (loaded, unloaded): (Vec<_>, Vec<_>) = std::mem::take(&mut self.libraries)
+            .into_iter()
+            .partition(|lib| {
+                entries
+                    .iter()
+                    .any(|e| e.path == lib.image.path() && e.load_bias == lib.load_bias)
+            });
+        self.libraries = loaded;
+        for library in unloaded {
+            self.forget_library(&library);
+        }
+        for entry in entries {
+            let loaded = self
+                .libraries
+                .iter()
+                .any(|lib| lib.image.path() == entry.path && lib.load_bias == entry.load_bias);
+            if loaded {
+                continue;
+            }
+
+            // library without debug info and symbols is still useful, but unreadable one isn't
+            if let Ok(image) = image::load(&entry.path) {
+                self.libraries.push(SharedLibrary {
+                    image,
+                    load_bias: entry.load_bias,
+                });
+            }
+        }
+
+        self.resolve_pending_breakpoints()
+    }
+
+    /// Drops breakpoints of the unmapped library, its source line breakpoints become pending
+    /// and are resolved again if the library is loaded later.
+    fn forget_library(&mut self, library: &SharedLibrary<'a>) {
+        let in_library = |addr: u64| library.image.contains(addr.wrapping_sub(library.load_bias));
+
+        // the code is gone, so there is no instruction to restore
+        self.breakpoints.retain(|&addr, _| !in_library(addr));
+        for bp in self.user_breakpoints.iter_mut().flatten() {
+            if matches!(bp.reference, BreakpointRef::Line { .. }) && bp.addr.is_some_and(in_library)
+            {
+                bp.addr = None;
+            }
+        }
+    }
+
+    /// Sets the signal the program gets when it's resumed, None discards the one it stopped with.
+    pub fn set_pending_signal(&mut self, signal: Option<i32>) -> Result<(), String> {
+        self.target.set_pending_signal(signal)
+    }
+
+    pub fn continue_execution(&mut self) -> Result<StopReason, String> {
+        if !self.alive {
+            Err("program is not running")?;
+        }
+
+        loop {
+            let stepped = self
+                .step_over_breakpoint()
+                .map_err(|e| format!("failed to step over breakpoint: {}", e))?;
+            if let Some(reason) = stepped {
+                return Ok(reason);
+            }
+
+            self.target.resume()?;
+            let reason = match self
+                .wait_trap()
+                .map_err(|e| format!("failed to wait trap: {}", e))?
+            {
+                Some(reason) => reason,
+                None => continue,
+            };
+
+            let rendezvous_addr = self.rendezvous.as_ref().map(|r| r.breakpoint_addr);
+            match reason {
+                StopReason::Breakpoint(addr) if Some(addr) == rendezvous_addr => {
+                    self.sync_shared_libraries()
+                        .map_err(|e| format!("failed to load shared libraries: {}", e))?;
+                    // user may break on the dynamic linker function too, like a remote debugger does
+                    if self.is_user_breakpoint(addr) {
+                        return Ok(reason);
+                    }
+                }
+                reason => return Ok(reason),
+            }
+        }
+    }
+
+    fn is_user_breakpoint(&self, addr: u64) -> bool {
+        self.user_breakpoints
+            .iter()
+            .flatten()
+            .any(|bp| bp.addr == Some(addr))
+    }
+
+    /// Executes a single machine instruction, the original one if PC is at a breakpoint.
+    pub fn step_instruction(&mut self) -> Result<StopReason, String> {
+        if !self.alive {
+            Err("program is not running")?;
+        }
+
+        let pc = self
+            .get_pc()
+            .map_err(|e| format!("failed to get PC register value: {}", e))?;
+        if self.breakpoints.get(&pc).is_some_and(|bp| bp.enabled()) {
+            let stepped = self
+                .step_over_breakpoint()
+                .map_err(|e| format!("failed to step over breakpoint: {}", e))?;
+            return Ok(stepped.unwrap_or(StopReason::Step));
+        }
+
+        self.target.step()?;
+        // exec is the only stop handled by the debugger, the new image is stopped at its entry
+        Ok(self
+            .wait_trap()
+            .map_err(|e| format!("failed to wait trap: {}", e))?
+            .unwrap_or(StopReason::Step))
+    }
+
+    /// Steps until the program reaches another source line, functions with line info are entered.
+    pub fn step_line(&mut self) -> Result<StopReason, String> {
+        self.step_line_over(false)
+    }
+
+    /// Steps until the program reaches another source line, called functions run to their return.
+    pub fn next_line(&mut self) -> Result<StopReason, String> {
+        self.step_line_over(true)
+    }
+
+    fn step_line_over(&mut self, over_calls: bool) -> Result<StopReason, String> {
+        if !self.alive {
+            Err("program is not running")?;
+        }
+
+        let start_line = self.current_line()?;
+        let start = self.current_frame()?;
+        // like gdb, code without line info is run until it returns
+        if start_line.is_none() {
+            return match self.caller_frame()? {
+                Some(caller) => self.run_to_return(caller),
+                None => self.continue_execution(),
+            };
+        }
+
+        loop {
+            match self.step_instruction()? {
+                StopReason::Step => (),
+                reason => return Ok(reason),
+            }
+
+            let frame = self.current_frame()?;
+            let line = self.current_line()?;
+            if frame.cfa < start.cfa {
+                // entered a called function
+                if !over_calls && line.is_some() {
+                    return Ok(StopReason::Step);
+                }
+                let caller = self
+                    .caller_frame()?
+                    .ok_or("called function has no caller")?;
+                match self.run_to_return(caller)? {
+                    StopReason::Step => continue,
+                    reason => return Ok(reason),
+                }
+            }
+
+            match line {
+                Some(line) if Some(&line) != start_line.as_ref() || frame.cfa != start.cfa => {
+                    return Ok(StopReason::Step);
+                }
+                Some(_) => (),
+                // returned into code without line info, like the libc code calling main
+                None if frame.cfa > start.cfa => return self.continue_execution(),
+                None => (),
+            }
+        }
+    }
+
+    /// Runs until the current function returns to its caller.
+    pub fn step_out(&mut self) -> Result<StopReason, String> {
+        if !self.alive {
+            Err("program is not running")?;
+        }
+
+        let caller = self
+            .caller_frame()?
+            .ok_or("the outermost frame can't be stepped out of")?;
+        self.run_to_return(caller)
+    }
+
+    /// Runs until the program is back in the caller frame,
+    /// recursive calls may pass the return address before that.
+    fn run_to_return(&mut self, caller: Frame) -> Result<StopReason, String> {
+        loop {
+            match self.run_to(caller.pc)? {
+                StopReason::Breakpoint(addr) if addr == caller.pc => {
+                    if self.current_frame()?.cfa >= caller.cfa {
+                        return Ok(StopReason::Step);
+                    }
+                }
+                reason => return Ok(reason),
+            }
+        }
+    }
+
+    /// Continues the program with a temporary breakpoint at the address.
+    fn run_to(&mut self, addr: u64) -> Result<StopReason, String> {
+        self.enable_breakpoint_at(addr)
+            .map_err(|e| format!("failed to enable breakpoint: {}", e))?;
+        let result = self.continue_execution();
+        self.release_breakpoint_at(addr)?;
+
+        result
+    }
+
+    /// Returns source file and line of the PC, None if there is no line info for it.
+    fn current_line(&self) -> Result<Option<(String, u64)>, String> {
+        Ok(self
+            .get_source_location(self.get_pc()?)?
+            .map(|location| (location.filename, location.line)))
+    }
+
+    fn current_frame(&self) -> Result<Frame, String> {
+        unwind::backtrace(self, 1)?
+            .first()
+            .copied()
+            .ok_or("failed to unwind current frame".to_owned())
+    }
+
+    fn caller_frame(&self) -> Result<Option<Frame>, String> {
+        Ok(unwind::backtrace(self, 2)?.get(1).copied())
+    }
+
+    /// Returns frames of the call stack, the current function goes first.
+    pub fn backtrace(&self) -> Result<Vec<Frame>, String> {
+        unwind::backtrace(self, usize::MAX)
+    }
+
+    /// Returns call frame info of the loaded object containing the address with its load bias.
+    pub(crate) fn call_frames(&self, addr: u64) -> Option<(&CallFrames<'a>, u64)> {
+        self.images().find_map(|(image, bias)| {
+            let file_addr = addr.checked_sub(bias)?;
+            if !image.contains(file_addr) {
+                return None;
+            }
+            Some((image.call_frames()?, bias))
+        })
+    }
+
+    /// Sets a breakpoint and returns its id, ids start from 1.
+    /// Breakpoint stays pending until its source line is found in a loaded object.
+    pub fn set_breakpoint(&mut self, reference: BreakpointRef) -> Result<usize, String> {
+        let addr = self.resolve_breakpoint(&reference)?;
         if let Some(addr) = addr {
-            let breakpoint = self
-                .breakpoints
-                .entry(addr)
-                .or_insert(Breakpoint::new(self.program_pid, addr));
-            breakpoint
-                .switch(true)
+            self.enable_breakpoint_at(addr)
                 .map_err(|e| format!("failed to enable breakpoint: {}", e))?;
-        } else {
-            Err("addr of source line not found")?;
+        }
+        self.user_breakpoints
+            .push(Some(UserBreakpoint { reference, addr }));
+
+        Ok(self.user_breakpoints.len())
+    }
+
+    pub fn delete_breakpoint(&mut self, id: usize) -> Result<(), String> {
+        let bp = id
+            .checked_sub(1)
+            .and_then(|index| self.user_breakpoints.get_mut(index))
+            .and_then(Option::take)
+            .ok_or(format!("no breakpoint number {}", id))?;
+
+        match bp.addr {
+            Some(addr) => self.release_breakpoint_at(addr),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the breakpoint at the address unless a user breakpoint or the rendezvous one shares it.
+    fn release_breakpoint_at(&mut self, addr: u64) -> Result<(), String> {
+        let shared = self.is_user_breakpoint(addr)
+            || self.rendezvous.as_ref().map(|r| r.breakpoint_addr) == Some(addr);
+        if !shared {
+            if let Some(mut breakpoint) = self.breakpoints.remove(&addr) {
+                if breakpoint.enabled() && self.alive {
+                    breakpoint
+                        .switch(&mut *self.target, false)
+                        .map_err(|e| format!("failed to disable breakpoint: {}", e))?;
+                }
+            }
         }
 
         Ok(())
     }
 
-    pub fn get_register_value(&self, reg: &RegSelector) -> Result<u64, String> {
-        let mut regs = ptrace::getregs(Pid::from_raw(self.program_pid))
-            .map_err(|e| format!("failed to get regs: {}", e))?;
+    /// Returns ids of pending breakpoints which got an address since the previous call.
+    pub fn take_activated_breakpoints(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.activated_breakpoints)
+    }
 
-        Ok(reg::get_register_value(&mut regs, reg))
+    /// Tells if the breakpoint exists, pending or not.
+    pub fn has_breakpoint(&self, id: usize) -> bool {
+        id.checked_sub(1)
+            .and_then(|index| self.user_breakpoints.get(index))
+            .is_some_and(Option::is_some)
     }
 
-    pub fn set_register_value(&self, reg: &RegSelector, value: u64) -> Result<(), String> {
-        let mut regs = ptrace::getregs(Pid::from_raw(self.program_pid))
-            .map_err(|e| format!("failed to get regs: {}", e))?;
+    /// Returns address of the breakpoint, None if it is pending.
+    pub fn breakpoint_addr(&self, id: usize) -> Option<u64> {
+        id.checked_sub(1)
+            .and_then(|index| self.user_breakpoints.get(index))
+            .and_then(|bp| bp.as_ref()?.addr)
+    }
+
+    /// Returns ids of user breakpoints at the address.
+    pub fn breakpoints_at(&self, addr: u64) -> Vec<usize> {
+        self.user_breakpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, bp)| bp.as_ref().and_then(|bp| bp.addr) == Some(addr))
+            .map(|(index, _)| index + 1)
+            .collect()
+    }
 
-        reg::set_register_value(&mut regs, reg, value);
+    fn resolve_pending_breakpoints(&mut self) -> Result<(), String> {
+        for index in 0..self.user_breakpoints.len() {
+            let reference = match &self.user_breakpoints[index] {
+                Some(bp) if bp.addr.is_none() => bp.reference.clone(),
+                _ => continue,
+            };
+
+            if let Some(addr) = self.resolve_breakpoint(&reference)? {
+                self.enable_breakpoint_at(addr)
+                    .map_err(|e| format!("failed to enable breakpoint: {}", e))?;
+                if let Some(bp) = &mut self.user_breakpoints[index] {
+                    bp.addr = Some(addr);
+                }
+                self.activated_breakpoints.push(index + 1);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_breakpoint(&self, reference: &BreakpointRef) -> Result<Option<u64>, String> {
+        let (filename, line) = match reference {
+            BreakpointRef::Addr(addr) => return Ok(Some(*addr)),
+            BreakpointRef::Line { filename, line } => (filename, *line),
+        };
+
+        for (image, bias) in self.images() {
+            if let Some(addr) = image
+                .dwarf()
+                .get_source_line_addr(filename.clone(), line)
+                .map_err(|e| format!("failed to get addr of source line: {}", e))?
+            {
+                return Ok(Some(addr + bias));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn enable_breakpoint_at(&mut self, addr: u64) -> Result<(), String> {
+        let breakpoint = self.breakpoints.entry(addr).or_insert(Breakpoint::new(
+            addr,
+            self.target.arch().breakpoint_instruction(),
+        ));
+        if !breakpoint.enabled() {
+            breakpoint.switch(&mut *self.target, true)?;
+        }
 
-        ptrace::setregs(Pid::from_raw(self.program_pid), regs)
-            .map_err(|e| format!("failed to set regs: {}", e))?;
         Ok(())
     }
 
-    pub fn dump_registers(&self) -> Result<HashMap<String, u64>, String> {
-        let mut regs = ptrace::getregs(Pid::from_raw(self.program_pid))
-            .map_err(|e| format!("failed to get regs: {}", e))?;
+    /// Returns loaded objects with their load bias, the executable goes first.
+    fn images(&self) -> impl Iterator<Item = (&Image<'a>, u64)> {
+        self.load_bias
+            .map(|load_bias| (&self.executable, load_bias))
+            .into_iter()
+            .chain(self.libraries.iter().map(|lib| (&lib.image, lib.load_bias)))
+    }
+
+    pub fn get_source_location(&self, addr: u64) -> Result<Option<SourceLocation>, String> {
+        for (image, bias) in self.images() {
+            if let Some(location) = addr
+                .checked_sub(bias)
+                .map(|addr| image.dwarf().get_addr_source_line(addr))
+                .transpose()?
+                .flatten()
+            {
+                return Ok(Some(location));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns `symbol+offset` description of the address.
+    pub fn symbolize(&self, addr: u64) -> Option<String> {
+        self.images().find_map(|(image, bias)| {
+            let (symbol, offset) = image.symbolize(addr.checked_sub(bias)?)?;
+            Some(match offset {
+                0 => symbol.name.clone(),
+                offset => format!("{}+{:#x}", symbol.name, offset),
+            })
+        })
+    }
+
+    /// Returns runtime address of the symbol from the executable or a shared library.
+    pub fn find_symbol_addr(&self, name: &str) -> Option<u64> {
+        self.images()
+            .find_map(|(image, bias)| image.find_symbol(name).map(|s| s.addr + bias))
+    }
+
+    pub fn arch(&self) -> &'static dyn Arch {
+        self.target.arch()
+    }
+
+    pub fn get_register_value(&self, reg: &RegSelector) -> Result<u64, String> {
+        let regs = self.target.read_regs()?;
+        reg::get_register_value(self.arch(), &regs, reg)
+    }
+
+    pub fn set_register_value(&mut self, reg: &RegSelector, value: u64) -> Result<(), String> {
+        let mut regs = self.target.read_regs()?;
+        reg::set_register_value(self.arch(), &mut regs, reg, value)?;
+        self.target.write_regs(&regs)
+    }
+
+    /// Returns value of the program counter register.
+    pub fn get_pc(&self) -> Result<u64, String> {
+        self.get_register_value(&RegSelector::Name(self.arch().pc_register()))
+    }
+
+    /// Returns general purpose registers in architectural order.
+    pub fn dump_registers(&self) -> Result<Vec<(String, u64)>, String> {
+        let regs = self.target.read_regs()?;
+        Ok(reg::dump_registers(self.arch(), &regs))
+    }
+
+    /// Returns raw little-endian value of the register, x87, SSE, AVX or AVX-512 registers
+    /// like `xmm0` are selected by name too.
+    pub fn get_register_bytes(&self, reg: &RegSelector) -> Result<Vec<u8>, String> {
+        if !reg.is_fp() {
+            let size = reg::register_size(self.arch(), reg)?;
+            return Ok(self.get_register_value(reg)?.to_le_bytes()[..size].to_vec());
+        }
+
+        self.target
+            .read_fp_regs()?
+            .get(reg.name())
+            .ok_or(format!("register {} isn't supported", reg.name()))
+    }
 
-        Ok(reg::dump_registers(&mut regs))
+    pub fn set_register_bytes(&mut self, reg: &RegSelector, value: &[u8]) -> Result<(), String> {
+        if !reg.is_fp() {
+            let mut raw = [0u8; 8];
+            raw.get_mut(..value.len())
+                .ok_or(format!(
+                    "value of {} must be at most 8 bytes long",
+                    reg.name()
+                ))?
+                .copy_from_slice(value);
+            return self.set_register_value(reg, u64::from_le_bytes(raw));
+        }
+
+        let mut regs = self.target.read_fp_regs()?;
+        regs.set(reg.name(), value)?;
+        self.target.write_fp_regs(&mut regs)
+    }
+
+    pub fn dump_fp_registers(&self) -> Result<Vec<(String, Vec<u8>)>, String> {
+        Ok(self.target.read_fp_regs()?.dump())
     }
 
     pub fn read_memory(&self, addr: u64) -> Result<i64, String> {
-        ptrace::read(Pid::from_raw(self.program_pid), addr as *mut c_void)
-            .map_err(|e| format!("failed to read memory: {}", e))
+        let bytes: [u8; 8] = self
+            .target
+            .read_memory(addr, 8)?
+            .try_into()
+            .map_err(|_| format!("failed to read memory: cannot access {:#x}", addr))?;
+        Ok(i64::from_le_bytes(bytes))
     }
 
-    pub fn write_memory(&self, addr: u64, value: i64) -> Result<(), String> {
-        // SAFETY: addr pointer come outside the program. value isn't pointer, it's a data.
-        unsafe {
-            ptrace::write(
-                Pid::from_raw(self.program_pid),
-                addr as *mut c_void,
-                value as *mut c_void,
-            )
-            .map_err(|e| format!("failed to write memory: {}", e))
+    pub fn write_memory(&mut self, addr: u64, value: i64) -> Result<(), String> {
+        if self.target.write_memory(addr, &value.to_le_bytes())? < 8 {
+            Err(format!("failed to write memory: cannot access {:#x}", addr))?;
         }
+        Ok(())
     }
 
-    fn step_over_breakpoint(&mut self) -> Result<(), String> {
+    /// Reads up to `len` bytes, fewer bytes are returned if the range runs into unmapped memory.
+    /// Program code under breakpoints is returned as it was before the breakpoints were set.
+    pub fn read_bytes(&self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+        let mut buf = self.target.read_memory(addr, len)?;
+        let read = buf.len();
+
+        for bp in self.breakpoints_in(addr, read) {
+            if let Some(replaced) = bp.replaced_bytes() {
+                for (offset, pos) in overlap(bp.addr(), replaced.len(), addr, read) {
+                    buf[pos] = replaced[offset];
+                }
+            }
+        }
+
+        Ok(buf)
+    }
+
+    /// Writes the data and returns number of bytes written,
+    /// breakpoints in the range stay enabled and restore the new code when disabled.
+    pub fn write_bytes(&mut self, addr: u64, data: &[u8]) -> Result<usize, String> {
+        let mut patched = data.to_vec();
+        for bp in self.breakpoints_in(addr, data.len()) {
+            let instruction = bp.instruction();
+            for (offset, pos) in overlap(bp.addr(), instruction.len(), addr, data.len()) {
+                patched[pos] = instruction[offset];
+            }
+        }
+
+        let written = self.target.write_memory(addr, &patched)?;
+
+        for bp in self.breakpoints.values_mut() {
+            if bp.enabled() {
+                for (offset, pos) in overlap(bp.addr(), bp.instruction().len(), addr, written) {
+                    bp.set_replaced_byte(offset, data[pos]);
+                }
+            }
+        }
+
+        Ok(written)
+    }
+
+    /// Returns addresses of the pattern in readable memory regions inside the scope.
+    pub fn search_memory(
+        &self,
+        scope: &SearchScope,
+        pattern: &SearchPattern,
+    ) -> Result<Vec<SearchMatch>, String> {
+        let pattern = pattern.to_bytes();
+        let regions = self.memory_regions()?;
+
+        let mut matches = Vec::new();
+        for (start, end, region) in search::scope_ranges(&regions, scope) {
+            let read = |addr, len| self.read_bytes(addr, len);
+            matches.extend(
+                search::search_range(read, start, end, &pattern)?
+                    .into_iter()
+                    .map(|addr| SearchMatch {
+                        addr,
+                        region: region.clone(),
+                    }),
+            );
+        }
+
+        Ok(matches)
+    }
+
+    /// Returns memory regions of the process, like `/proc/<pid>/maps` lists them.
+    pub fn memory_regions(&self) -> Result<Vec<linux_maps::MemoryRegion>, String> {
+        self.target.memory_regions()
+    }
+
+    /// Writes ELF core file of the stopped program which can be loaded later
+    /// by `mdbg core` or gdb, the program keeps running under the debugger.
+    pub fn generate_core(&self, path: &str) -> Result<(), String> {
+        if !self.alive {
+            Err("program is not running")?;
+        }
+
+        gcore::write(self, path)
+    }
+
+    /// Decodes `count` instructions starting from the address.
+    pub fn disassemble(&self, addr: u64, count: usize) -> Result<Vec<Instruction>, String> {
+        let bitness = match self.arch().name() {
+            "x86_64" => 64,
+            "i386" => 32,
+            name => Err(format!("disassembly isn't supported on {}", name))?,
+        };
+        // code is read for a batch of instructions at a time, so a huge count reads only
+        // the memory which decodes
+        let mut instructions = Vec::new();
+        let mut next = addr;
+        while instructions.len() < count {
+            let batch = (count - instructions.len()).min(DISASSEMBLE_BATCH);
+            let code = self.read_bytes(next, batch * disasm::MAX_INSTRUCTION_LEN)?;
+            let decoded = disasm::disassemble(&code, next, batch, bitness);
+            let done = decoded.len() < batch;
+            if let Some(last) = decoded.last() {
+                next = last.addr + last.bytes.len() as u64;
+            }
+            instructions.extend(decoded);
+            if done {
+                break;
+            }
+        }
+
+        Ok(instructions)
+    }
+
+    fn breakpoints_in(&self, addr: u64, len: usize) -> impl Iterator<Item = &Breakpoint> {
+        let end = addr.saturating_add(len as u64);
+        self.breakpoints.values().filter(move |bp| {
+            bp.enabled() && bp.addr() < end && bp.addr() + bp.instruction().len() as u64 > addr
+        })
+    }
+
+    /// Executes the original instruction under the breakpoint at PC, returns the stop reason
+    /// if the program didn't just finish the step, like when it got a signal or exited.
+    fn step_over_breakpoint(&mut self) -> Result<Option<StopReason>, String> {
         let rip = self
-            .get_register_value(&RegSelector::Reg(Reg::RIP))
-            .map_err(|e| format!("failed to get RIP register value: {}", e))?;
+            .get_pc()
+            .map_err(|e| format!("failed to get PC register value: {}", e))?;
 
         match self.breakpoints.get_mut(&rip) {
             Some(bp) if bp.enabled() => {
-                bp.switch(false)
+                bp.switch(&mut *self.target, false)
                     .map_err(|e| format!("failed to disable breakpoint: {}", e))?;
             }
-            _ => return Ok(()),
+            _ => return Ok(None),
         }
 
-        let pid = Pid::from_raw(self.program_pid);
-        ptrace::step(pid, None).map_err(|e| format!("failed to single step program: {}", e))?;
-        self.wait_trap(false)
-            .map_err(|e| format!("failed to wait trap: {}", e))?;
+        self.target.step()?;
+        let reason = self
+            .wait_trap()
+            .map_err(|e| format!("failed to wait trap: {}", e))?
+            .filter(|reason| !matches!(reason, StopReason::Step));
+        // program is gone, or exec happened during the step and breakpoints are reset
+        if !self.alive || !self.breakpoints.contains_key(&rip) {
+            return Ok(reason);
+        }
 
         // redeclare bp due to reborrow self as mutable
         // unwrap because already check that breakpoint exists
         let bp = self.breakpoints.get_mut(&rip).unwrap();
-        bp.switch(true)
+        bp.switch(&mut *self.target, true)
             .map_err(|e| format!("failed to enable breakpoint: {}", e))?;
 
-        Ok(())
+        Ok(reason)
     }
 
-    pub fn wait_attach(&self) -> Result<(), String> {
-        self.wait_trap(true).map(|_| ())
+    pub fn wait_attach(&mut self) -> Result<(), String> {
+        self.target.wait_attach()
     }
 
-    fn wait_trap(&self, si_code_must_user: bool) -> Result<Option<i32>, String> {
-        let status = waitpid(Pid::from_raw(self.program_pid), None)
-            .map_err(|e| format!("failed to wait pid: {}", e))?;
+    /// Removes all breakpoints from the process memory and detaches from it,
+    /// so the process keeps running without the debugger.
+    pub fn detach(&mut self) -> Result<(), String> {
+        if !self.alive {
+            Err("program is not running")?;
+        }
 
-        match status {
-            wait::WaitStatus::Stopped(_, Signal::SIGTRAP) => {
-                const SI_USER: i32 = 0x0;
-                const TRAP_BRKPT: i32 = 0x1;
-                const TRAP_TRACE: i32 = 0x2;
-                const SI_KERNEL: i32 = 0x80;
+        for bp in self.breakpoints.values_mut().filter(|bp| bp.enabled()) {
+            bp.switch(&mut *self.target, false)
+                .map_err(|e| format!("failed to disable breakpoint: {}", e))?;
+        }
+        self.breakpoints.clear();
 
-                let siginfo = ptrace::getsiginfo(Pid::from_raw(self.program_pid))
-                    .map_err(|e| format!("failed to get siginfo: {}", e))?;
+        self.target.detach()?;
+        self.alive = false;
 
-                if si_code_must_user && siginfo.si_code != SI_USER {
-                    Err("could not attach to debugee process: wrong ci code")?;
-                }
+        Ok(())
+    }
 
-                match siginfo.si_code {
-                    // hit breakpoint
-                    SI_KERNEL | TRAP_BRKPT => {
-                        let reg = RegSelector::Reg(Reg::RIP);
-                        let rip = self
-                            .get_register_value(&reg)
-                            .map_err(|e| format!("failed to get RIP register value: {}", e))?;
-                        self.set_register_value(&reg, rip - 1)
-                            .map_err(|e| format!("failed to set value to RIP register: {}", e))?;
-                    }
+    pub fn kill(&mut self) -> Result<(), String> {
+        if !self.alive {
+            Err("program is not running")?;
+        }
 
-                    // traceme or signle step
-                    SI_USER | TRAP_TRACE => (),
+        self.breakpoints.clear();
+        self.kill_process()
+    }
 
-                    _ => Err(format!("Uknown SIGTRAP code: {}", siginfo.si_code))?,
-                }
+    fn kill_process(&mut self) -> Result<(), String> {
+        self.target.kill()?;
+        self.alive = false;
+
+        Ok(())
+    }
+
+    /// Waits for the program to stop, returns None if the stop was handled by the debugger itself.
+    fn wait_trap(&mut self) -> Result<Option<StopReason>, String> {
+        match self.target.wait()? {
+            TargetStop::Breakpoint(addr) => Ok(Some(StopReason::Breakpoint(addr))),
+            TargetStop::Step => Ok(Some(StopReason::Step)),
+            TargetStop::Interrupted => Ok(Some(StopReason::Interrupted)),
+            TargetStop::Exec(path) => {
+                self.handle_exec(&path)
+                    .map_err(|e| format!("failed to handle exec: {}", e))?;
+                Ok(None)
             }
-            wait::WaitStatus::Stopped(_, Signal::SIGSTOP) => (), // debugger attached
-            wait::WaitStatus::Signaled(_, Signal::SIGSEGV, _) => {
-                Err("Segfault occured.")?;
+            TargetStop::Exited(status) => {
+                self.alive = false;
+                Ok(Some(StopReason::Exited(status)))
             }
-            wait::WaitStatus::Exited(_, status) => {
-                return Ok(Some(status));
+            TargetStop::Signaled(signal) => {
+                self.alive = false;
+                Ok(Some(StopReason::Signaled(signal)))
             }
-            _ => Err(format!("Uknown signal: {:?}", status))?,
+            TargetStop::Signal(signal) => Ok(Some(StopReason::Signal(signal))),
         }
+    }
+}
 
-        Ok(None)
+/// Finds the executable and shared libraries among files mapped into the dumped process,
+/// returns the lowest address of the executable together with the libraries.
+fn load_core_images<'a>(
+    executable: &Image<'a>,
+    core: &CoreFile,
+) -> Result<(u64, Vec<SharedLibrary<'a>>), String> {
+    let executable_path = fs::canonicalize(executable.path()).ok();
+
+    let mut load_addr = None;
+    let mut libraries = Vec::new();
+    for (path, start) in core.mapped_files() {
+        if fs::canonicalize(path).ok() == executable_path {
+            load_addr = Some(start);
+            continue;
+        }
+        // data files like locale archive are mapped as well
+        if arch::for_elf_file(path).is_err() {
+            continue;
+        }
+
+        if let Ok(image) = image::load(path) {
+            libraries.push(SharedLibrary {
+                load_bias: image.load_bias(start),
+                image,
+            });
+        }
     }
+
+    let load_addr = load_addr.ok_or(format!(
+        "{} isn't mapped in the core file",
+        executable.path()
+    ))?;
+    Ok((load_addr, libraries))
 }
 
+pub enum StopReason {
+    Breakpoint(u64),
+    Step,
+    Interrupted,
+    Exited(i32),
+    /// Program was killed by the signal.
+    Signaled(i32),
+    /// Program received the signal, it gets the signal when it's resumed.
+    Signal(i32),
+}
+
+#[derive(Clone)]
 pub enum BreakpointRef {
     Addr(u64),
     Line { filename: String, line: u64 },
 }
 
-impl BreakpointRef {
-    pub fn clone(&self) -> BreakpointRef {
-        match self {
-            BreakpointRef::Addr(addr) => BreakpointRef::Addr(*addr),
-            BreakpointRef::Line { filename, line } => BreakpointRef::Line {
-                filename: filename.clone(),
-                line: *line,
-            },
+/// Returns offsets inside the `len` bytes at `addr` which fall into the buffer at `buf_addr`
+/// together with their positions in the buffer.
+fn overlap(addr: u64, len: usize, buf_addr: u64, buf_len: usize) -> Vec<(usize, usize)> {
+    (0..len)
+        .filter_map(|offset| {
+            let pos = (addr + offset as u64).checked_sub(buf_addr)? as usize;
+            (pos < buf_len).then_some((offset, pos))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+    use crate::{
+        fpregs::FpRegs,
+        linux_maps::{MemoryRegion, Permissions},
+    };
+
+    const BASE: u64 = 0x1000;
+
+    /// Program with registers and a page of memory kept in the test, stops are scripted.
+    struct MockTarget {
+        arch: &'static dyn Arch,
+        regs: Vec<u8>,
+        memory: Vec<u8>,
+        stops: VecDeque<TargetStop>,
+        // byte at PC every time the program was single stepped
+        stepped_code: Vec<u8>,
+    }
+
+    impl MockTarget {
+        fn new(arch: &'static dyn Arch, stops: Vec<TargetStop>) -> MockTarget {
+            MockTarget {
+                arch,
+                regs: vec![0; arch.regset_size()],
+                memory: (0..0x100).map(|i| i as u8).collect(),
+                stops: stops.into(),
+                stepped_code: Vec::new(),
+            }
+        }
+
+        fn set_pc(&mut self, value: u64) {
+            let pc = RegSelector::Name(self.arch.pc_register());
+            reg::set_register_value(self.arch, &mut self.regs, &pc, value).unwrap();
+        }
+
+        fn offset(&self, addr: u64) -> usize {
+            (addr - BASE) as usize
+        }
+    }
+
+    impl Target for MockTarget {
+        fn arch(&self) -> &'static dyn Arch {
+            self.arch
+        }
+
+        fn pid(&self) -> i32 {
+            1
+        }
+
+        fn read_regs(&self) -> Result<Vec<u8>, String> {
+            Ok(self.regs.clone())
+        }
+
+        fn write_regs(&mut self, regs: &[u8]) -> Result<(), String> {
+            self.regs = regs.to_vec();
+            Ok(())
+        }
+
+        fn read_fp_regs(&self) -> Result<FpRegs, String> {
+            Err("no fp registers".to_owned())
+        }
+
+        fn write_fp_regs(&mut self, _regs: &mut FpRegs) -> Result<(), String> {
+            Err("no fp registers".to_owned())
+        }
+
+        fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+            let start = self.offset(addr).min(self.memory.len());
+            let end = (start + len).min(self.memory.len());
+            Ok(self.memory[start..end].to_vec())
+        }
+
+        fn write_memory(&mut self, addr: u64, data: &[u8]) -> Result<usize, String> {
+            let start = self.offset(addr);
+            self.memory[start..start + data.len()].copy_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn memory_regions(&self) -> Result<Vec<MemoryRegion>, String> {
+            Ok(vec![MemoryRegion {
+                start: BASE,
+                end: BASE + self.memory.len() as u64,
+                perms: Permissions {
+                    read: true,
+                    write: true,
+                    exec: true,
+                    shared: false,
+                },
+                offset: 0,
+                dev: (0, 0),
+                inode: 0,
+                pathname: None,
+            }])
         }
+
+        fn auxv(&self) -> Result<Vec<u8>, String> {
+            Ok(Vec::new())
+        }
+
+        fn wait_attach(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn resume(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn step(&mut self) -> Result<(), String> {
+            let pc = reg::get_register_value(
+                self.arch,
+                &self.regs,
+                &RegSelector::Name(self.arch.pc_register()),
+            )?;
+            self.stepped_code.push(self.memory[self.offset(pc)]);
+            Ok(())
+        }
+
+        fn wait(&mut self) -> Result<TargetStop, String> {
+            let stop = self.stops.pop_front().ok_or("no stops left")?;
+            if let TargetStop::Breakpoint(addr) = stop {
+                self.set_pc(addr);
+            }
+            Ok(stop)
+        }
+
+        fn kill(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn detach(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn debugger(stops: Vec<TargetStop>) -> Debugger<'static, MockTarget> {
+        let path = std::env::current_exe().unwrap();
+        let executable = image::load(&path.to_string_lossy()).unwrap();
+        let target = MockTarget::new(executable.arch(), stops);
+        Debugger::new(Box::new(target), executable)
+    }
+
+    fn trap(debugger: &Debugger<MockTarget>) -> u8 {
+        debugger.arch().breakpoint_instruction()[0]
+    }
+
+    #[test]
+    fn breakpoint_is_hidden_from_reads() {
+        let mut debugger = debugger(Vec::new());
+        let addr = BASE + 0x10;
+        debugger.set_breakpoint(BreakpointRef::Addr(addr)).unwrap();
+
+        assert_eq!(
+            debugger.target().read_memory(addr, 1).unwrap(),
+            [trap(&debugger)]
+        );
+        assert_eq!(
+            debugger.read_bytes(addr - 1, 3).unwrap(),
+            [0x0f, 0x10, 0x11]
+        );
+    }
+
+    #[test]
+    fn written_code_stays_under_breakpoint() {
+        let mut debugger = debugger(Vec::new());
+        let addr = BASE + 0x10;
+        debugger.set_breakpoint(BreakpointRef::Addr(addr)).unwrap();
+
+        assert_eq!(debugger.write_bytes(addr - 1, &[1, 2, 3]).unwrap(), 3);
+        assert_eq!(
+            debugger.target().read_memory(addr - 1, 3).unwrap(),
+            [1, trap(&debugger), 3]
+        );
+        assert_eq!(debugger.read_bytes(addr - 1, 3).unwrap(), [1, 2, 3]);
+
+        debugger.detach().unwrap();
+        assert_eq!(
+            debugger.target().read_memory(addr - 1, 3).unwrap(),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn continue_steps_over_breakpoint() {
+        let addr = BASE + 0x20;
+        let mut debugger = debugger(vec![
+            TargetStop::Breakpoint(addr),
+            TargetStop::Step,
+            TargetStop::Exited(3),
+        ]);
+        debugger.set_breakpoint(BreakpointRef::Addr(addr)).unwrap();
+
+        assert!(matches!(
+            debugger.continue_execution().unwrap(),
+            StopReason::Breakpoint(stop) if stop == addr
+        ));
+        assert_eq!(debugger.get_pc().unwrap(), addr);
+
+        assert!(matches!(
+            debugger.continue_execution().unwrap(),
+            StopReason::Exited(3)
+        ));
+        // original instruction was executed and the breakpoint was put back
+        assert_eq!(debugger.target().stepped_code, [0x20]);
+        assert_eq!(
+            debugger.target().read_memory(addr, 1).unwrap(),
+            [trap(&debugger)]
+        );
+        assert!(!debugger.alive());
+        assert!(debugger.continue_execution().is_err());
+    }
+
+    #[test]
+    fn signal_stops_program_until_it_is_killed() {
+        let addr = BASE + 0x20;
+        let mut debugger = debugger(vec![
+            TargetStop::Breakpoint(addr),
+            // instruction under the breakpoint faults
+            TargetStop::Signal(11),
+            TargetStop::Signaled(11),
+        ]);
+        debugger.set_breakpoint(BreakpointRef::Addr(addr)).unwrap();
+        debugger.continue_execution().unwrap();
+
+        assert!(matches!(
+            debugger.continue_execution().unwrap(),
+            StopReason::Signal(11)
+        ));
+        assert!(debugger.alive());
+        assert_eq!(
+            debugger.target().read_memory(addr, 1).unwrap(),
+            [trap(&debugger)]
+        );
+
+        assert!(matches!(
+            debugger.continue_execution().unwrap(),
+            StopReason::Signaled(11)
+        ));
+        assert!(!debugger.alive());
+        assert_eq!(debugger.target().stepped_code, [0x20, 0x20]);
+    }
+
+    #[test]
+    fn registers_and_memory_go_to_target() {
+        let mut debugger = debugger(Vec::new());
+        let pc = RegSelector::Name(debugger.arch().pc_register());
+
+        debugger.set_register_value(&pc, BASE + 0x30).unwrap();
+        assert_eq!(debugger.get_pc().unwrap(), BASE + 0x30);
+
+        debugger.write_memory(BASE + 0x40, -2).unwrap();
+        assert_eq!(debugger.read_memory(BASE + 0x40).unwrap(), -2);
+        assert!(debugger.read_memory(BASE + 0xfc).is_err());
+        assert!(debugger
+            .get_register_bytes(&RegSelector::Name("xmm0"))
+            .is_err());
+    }
+
+    #[test]
+    fn general_registers_are_read_as_bytes() {
+        let mut debugger = debugger(Vec::new());
+        let pc = RegSelector::Name(debugger.arch().pc_register());
+
+        debugger.set_register_bytes(&pc, &[0x34, 0x12]).unwrap();
+        assert_eq!(debugger.get_pc().unwrap(), 0x1234);
+        assert_eq!(
+            debugger.get_register_bytes(&pc).unwrap(),
+            0x1234u64.to_le_bytes()
+        );
+        assert!(debugger.set_register_bytes(&pc, &[0; 9]).is_err());
+        assert!(!pc.is_fp());
+        assert!(RegSelector::Name("ymm15").is_fp());
     }
 }
@@ -0,0 +1,34 @@
+use iced_x86::{Decoder, DecoderOptions, Formatter, IntelFormatter};
+
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+pub struct Instruction {
+    pub addr: u64,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+/// Decodes up to `count` x86 instructions of 32 or 64 bit mode, stops at the first invalid one.
+pub fn disassemble(code: &[u8], addr: u64, count: usize, bitness: u32) -> Vec<Instruction> {
+    let mut decoder = Decoder::with_ip(bitness, code, addr, DecoderOptions::NONE);
+    let mut formatter = IntelFormatter::new();
+    let mut result = Vec::new();
+
+    while decoder.can_decode() && result.len() < count {
+        let instruction = decoder.decode();
+        if instruction.is_invalid() {
+            break;
+        }
+
+        let mut text = String::new();
+        formatter.format(&instruction, &mut text);
+        let start = (instruction.ip() - addr) as usize;
+        result.push(Instruction {
+            addr: instruction.ip(),
+            bytes: code[start..start + instruction.len()].to_vec(),
+            text,
+        });
+    }
+
+    result
+}
@@ -2,6 +2,11 @@ use std::borrow::{self, Cow};
 
 use object::{File, Object, ObjectSection};
 
+pub struct SourceLocation {
+    pub filename: String,
+    pub line: u64,
+}
+
 pub struct Dwarf<'a> {
     dwarf: gimli::Dwarf<gimli::EndianSlice<'a, gimli::RunTimeEndian>>,
 }
@@ -27,7 +32,18 @@ impl<'a> Dwarf<'a> {
                 if self
                     .get_unit_name(&unit)
                     .map_err(|e| format!("failed to get name of dwarf unit: {}", e))?
-                    .filter(|name| name == &filename)
+                    .filter(|name| {
+                        // relative unit names are matched against the path in the compilation directory too
+                        name == &filename
+                            || unit
+                                .comp_dir
+                                .and_then(|dir| dir.to_string().ok())
+                                .filter(|dir| {
+                                    std::path::Path::new(dir).join(name)
+                                        == std::path::Path::new(&filename)
+                                })
+                                .is_some()
+                    })
                     .is_some()
                 {
                     break unit.line_program;
@@ -55,6 +71,74 @@ impl<'a> Dwarf<'a> {
         })
     }
 
+    pub fn get_addr_source_line(&self, addr: u64) -> Result<Option<SourceLocation>, String> {
+        let mut units = self.dwarf.units();
+
+        while let Some(header) = units
+            .next()
+            .map_err(|e| format!("failed to get next header of dwarf unit: {}", e))?
+        {
+            let unit = self
+                .dwarf
+                .unit(header)
+                .map_err(|e| format!("failed to construct dwarf unit from header: {}", e))?;
+            let mut rows = match unit.line_program.clone() {
+                Some(program) => program.rows(),
+                None => continue,
+            };
+
+            // row describes addresses up to the next row in the same sequence
+            let mut prev_row: Option<gimli::LineRow> = None;
+            while let Some((header, row)) = rows
+                .next_row()
+                .map_err(|e| format!("failed to get next row of source: {}", e))?
+            {
+                if let Some(prev) = prev_row.filter(|prev| prev.address() <= addr) {
+                    if addr < row.address() {
+                        let filename = match prev.file(header) {
+                            Some(file) => self.get_file_path(&unit, header, file)?,
+                            None => return Ok(None),
+                        };
+                        return Ok(prev.line().map(|line| SourceLocation {
+                            filename,
+                            line: line.get(),
+                        }));
+                    }
+                }
+
+                prev_row = if row.end_sequence() { None } else { Some(*row) };
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn get_file_path(
+        &self,
+        unit: &gimli::Unit<gimli::EndianSlice<gimli::RunTimeEndian>, usize>,
+        header: &gimli::LineProgramHeader<gimli::EndianSlice<gimli::RunTimeEndian>, usize>,
+        file: &gimli::FileEntry<gimli::EndianSlice<gimli::RunTimeEndian>, usize>,
+    ) -> Result<String, String> {
+        let attr_string = |attr| -> Result<String, String> {
+            Ok(self
+                .dwarf
+                .attr_string(unit, attr)
+                .map_err(|e| format!("failed to load file name: {}", e))?
+                .to_string_lossy()
+                .into_owned())
+        };
+
+        let name = attr_string(file.path_name())?;
+        if name.starts_with('/') {
+            return Ok(name);
+        }
+
+        match file.directory(header) {
+            Some(dir) => Ok(format!("{}/{}", attr_string(dir)?, name)),
+            None => Ok(name),
+        }
+    }
+
     fn get_unit_name(
         &self,
         unit: &gimli::Unit<gimli::EndianSlice<gimli::RunTimeEndian>, usize>,
@@ -84,7 +168,9 @@ impl<'a> Dwarf<'a> {
     }
 }
 
-pub fn load_dwarf(mmap: &[u8]) -> Result<(gimli::Dwarf<Cow<[u8]>>, gimli::RunTimeEndian), String> {
+pub type DwarfSections<'a> = gimli::Dwarf<Cow<'a, [u8]>>;
+
+pub fn load_dwarf(mmap: &[u8]) -> Result<(DwarfSections<'_>, gimli::RunTimeEndian), String> {
     let object: File =
         object::File::parse(mmap).map_err(|e| format!("failed to parse object file: {}", e))?;
 
@@ -116,7 +202,7 @@ pub fn borrow_section<'a>(
     let borrow_section: &dyn for<'b> Fn(
         &'b borrow::Cow<'b, [u8]>,
     ) -> gimli::EndianSlice<'b, gimli::RunTimeEndian> =
-        &|section| gimli::EndianSlice::new(&*section, endian);
+        &|section| gimli::EndianSlice::new(section, endian);
 
     dwarf.borrow(borrow_section)
 }
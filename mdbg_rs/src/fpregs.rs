@@ -0,0 +1,314 @@
+#[cfg(target_arch = "x86_64")]
+use std::os::raw::c_void;
+
+#[cfg(target_arch = "x86_64")]
+use nix::{errno::Errno, libc};
+
+// the legacy area has the FXSAVE layout, which PTRACE_GETFPREGS returns as well
+const LEGACY_AREA_LEN: usize = 512;
+const XSAVE_HEADER_LEN: usize = 64;
+// xstate_bv field of the XSAVE header
+const XSTATE_BV_OFFSET: usize = LEGACY_AREA_LEN;
+// kernel stores XCR0 into the software reserved bytes of the legacy area
+const SW_XFEATURES_OFFSET: usize = 464;
+#[cfg(target_arch = "x86_64")]
+const NT_X86_XSTATE: libc::c_int = 0x202;
+
+const ST_OFFSET: usize = 32;
+const XMM_OFFSET: usize = 160;
+
+// XSAVE state components
+const X87: u32 = 0;
+const SSE: u32 = 1;
+const AVX: u32 = 2;
+const OPMASK: u32 = 5;
+const ZMM_HI256: u32 = 6;
+const HI16_ZMM: u32 = 7;
+
+/// Names of floating point and vector registers in dump order.
+fn register_names() -> impl Iterator<Item = String> {
+    let indexed = |prefix: &'static str, count| (0..count).map(move |i| format!("{}{}", prefix, i));
+
+    indexed("st", 8)
+        .chain(["fctrl", "fstat", "ftag", "fop", "mxcsr"].map(str::to_owned))
+        .chain(indexed("xmm", 32))
+        .chain(indexed("ymm", 32))
+        .chain(indexed("zmm", 32))
+        .chain(indexed("k", 8))
+}
+
+/// Checks that the name belongs to an x87, SSE, AVX or AVX-512 register,
+/// the register may still be unsupported by the CPU.
+pub(crate) fn is_fp_register(name: &str) -> bool {
+    register_names().any(|n| n == name)
+}
+
+/// Piece of a register value in the XSAVE area.
+struct Piece {
+    offset: usize,
+    len: usize,
+    component: u32,
+}
+
+/// Contents of the XSAVE area of a stopped thread.
+pub struct FpRegs {
+    data: Vec<u8>,
+    // state components enabled in XCR0
+    xfeatures: u64,
+    // PTRACE_GETFPREGS fallback was used, only the legacy area is valid
+    legacy: bool,
+}
+
+impl FpRegs {
+    #[cfg(target_arch = "x86_64")]
+    pub fn read(pid: i32) -> Result<FpRegs, String> {
+        let size = (xsave_cpuid(0).1 as usize).max(LEGACY_AREA_LEN + XSAVE_HEADER_LEN);
+        let mut data = vec![0u8; size];
+        let mut iov = libc::iovec {
+            iov_base: data.as_mut_ptr() as *mut c_void,
+            iov_len: data.len(),
+        };
+
+        // SAFETY: the kernel writes at most iov_len bytes into the buffer.
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETREGSET,
+                pid,
+                NT_X86_XSTATE as *mut c_void,
+                &mut iov as *mut libc::iovec,
+            )
+        };
+        if res != -1 {
+            data.truncate(iov.iov_len);
+            return FpRegs::from_xsave(data);
+        }
+
+        // CPU without XSAVE support
+        match Errno::last() {
+            Errno::EINVAL | Errno::ENODEV => (),
+            e => Err(format!("failed to get xstate registers: {}", e))?,
+        }
+        data.truncate(LEGACY_AREA_LEN);
+        // SAFETY: user_fpregs_struct is exactly 512 bytes long.
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                pid,
+                std::ptr::null_mut::<c_void>(),
+                data.as_mut_ptr() as *mut c_void,
+            )
+        };
+        if res == -1 {
+            Err(format!("failed to get fp registers: {}", Errno::last()))?;
+        }
+
+        FpRegs::from_fxsave(data)
+    }
+
+    /// Wraps XSAVE area saved outside of the thread, like in a core file.
+    pub fn from_xsave(data: Vec<u8>) -> Result<FpRegs, String> {
+        if data.len() < LEGACY_AREA_LEN + XSAVE_HEADER_LEN {
+            Err(format!("XSAVE area is too short: {} bytes", data.len()))?;
+        }
+
+        let xfeatures = read_u64(&data, SW_XFEATURES_OFFSET);
+        Ok(FpRegs {
+            data,
+            xfeatures,
+            legacy: false,
+        })
+    }
+
+    /// Wraps FXSAVE area which has x87 and SSE registers only.
+    pub fn from_fxsave(data: Vec<u8>) -> Result<FpRegs, String> {
+        if data.len() != LEGACY_AREA_LEN {
+            Err(format!(
+                "FXSAVE area must be {} bytes long",
+                LEGACY_AREA_LEN
+            ))?;
+        }
+
+        Ok(FpRegs {
+            data,
+            xfeatures: 1 << X87 | 1 << SSE,
+            legacy: true,
+        })
+    }
+
+    // AArch64 NT_PRFPREG register set has another layout, which isn't supported yet
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn read(_pid: i32) -> Result<FpRegs, String> {
+        Err("fp registers are supported only on x86-64".to_owned())
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn write(&mut self, pid: i32) -> Result<(), String> {
+        let res = if self.legacy {
+            // SAFETY: the buffer has the size of user_fpregs_struct.
+            unsafe {
+                libc::ptrace(
+                    libc::PTRACE_SETFPREGS,
+                    pid,
+                    std::ptr::null_mut::<c_void>(),
+                    self.data.as_mut_ptr() as *mut c_void,
+                )
+            }
+        } else {
+            let mut iov = libc::iovec {
+                iov_base: self.data.as_mut_ptr() as *mut c_void,
+                iov_len: self.data.len(),
+            };
+            // SAFETY: the kernel reads at most iov_len bytes from the buffer.
+            unsafe {
+                libc::ptrace(
+                    libc::PTRACE_SETREGSET,
+                    pid,
+                    NT_X86_XSTATE as *mut c_void,
+                    &mut iov as *mut libc::iovec,
+                )
+            }
+        };
+
+        if res == -1 {
+            Err(format!("failed to set fp registers: {}", Errno::last()))?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn write(&mut self, _pid: i32) -> Result<(), String> {
+        Err("fp registers are supported only on x86-64".to_owned())
+    }
+
+    /// Returns x87 and SSE state in the FXSAVE layout.
+    pub fn fxsave_area(&self) -> &[u8] {
+        &self.data[..LEGACY_AREA_LEN]
+    }
+
+    /// Returns the whole XSAVE area, None if only the FXSAVE area is known.
+    pub fn xsave_area(&self) -> Option<&[u8]> {
+        (!self.legacy).then_some(&self.data)
+    }
+
+    /// Returns raw little-endian value of the register, None if it isn't supported.
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        let pieces = self.layout(name)?;
+        Some(
+            pieces
+                .iter()
+                .flat_map(|p| &self.data[p.offset..p.offset + p.len])
+                .copied()
+                .collect(),
+        )
+    }
+
+    pub fn set(&mut self, name: &str, value: &[u8]) -> Result<(), String> {
+        let pieces = self
+            .layout(name)
+            .ok_or(format!("register {} isn't supported", name))?;
+        let len: usize = pieces.iter().map(|p| p.len).sum();
+        if value.len() != len {
+            Err(format!("register {} is {} bytes long", name, len))?;
+        }
+
+        let mut rest = value;
+        for piece in pieces {
+            let (chunk, tail) = rest.split_at(piece.len);
+            self.data[piece.offset..piece.offset + piece.len].copy_from_slice(chunk);
+            rest = tail;
+
+            if !self.legacy {
+                // kernel ignores components which are marked as being in init state
+                let xstate_bv = read_u64(&self.data, XSTATE_BV_OFFSET) | 1 << piece.component;
+                self.data[XSTATE_BV_OFFSET..XSTATE_BV_OFFSET + 8]
+                    .copy_from_slice(&xstate_bv.to_le_bytes());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns values of all supported registers.
+    pub fn dump(&self) -> Vec<(String, Vec<u8>)> {
+        register_names()
+            .filter_map(|name| Some((name.clone(), self.get(&name)?)))
+            .collect()
+    }
+
+    fn layout(&self, name: &str) -> Option<Vec<Piece>> {
+        let legacy = |offset, len, component| {
+            Some(vec![Piece {
+                offset,
+                len,
+                component,
+            }])
+        };
+        match name {
+            "fctrl" => return legacy(0, 2, X87),
+            "fstat" => return legacy(2, 2, X87),
+            // abridged tag word with one bit per register
+            "ftag" => return legacy(4, 1, X87),
+            "fop" => return legacy(6, 2, X87),
+            "mxcsr" => return legacy(24, 4, SSE),
+            _ => (),
+        }
+
+        let digits = name.find(|c: char| c.is_ascii_digit())?;
+        let index: usize = name[digits..].parse().ok()?;
+        let (low, hi16) = (index < 16, index.checked_sub(16));
+        let mut pieces = match (&name[..digits], hi16) {
+            ("st", _) if index < 8 => legacy(ST_OFFSET + index * 16, 10, X87)?,
+            ("xmm" | "ymm" | "zmm", None) => legacy(XMM_OFFSET + index * 16, 16, SSE)?,
+            ("xmm", Some(i)) if i < 16 => vec![self.piece(HI16_ZMM, i * 64, 16)?],
+            ("ymm", Some(i)) if i < 16 => vec![self.piece(HI16_ZMM, i * 64, 32)?],
+            ("zmm", Some(i)) if i < 16 => vec![self.piece(HI16_ZMM, i * 64, 64)?],
+            ("k", _) if index < 8 => vec![self.piece(OPMASK, index * 8, 8)?],
+            _ => return None,
+        };
+
+        // upper halves of low registers live in separate components
+        if low && matches!(&name[..digits], "ymm" | "zmm") {
+            pieces.push(self.piece(AVX, index * 16, 16)?);
+        }
+        if low && &name[..digits] == "zmm" {
+            pieces.push(self.piece(ZMM_HI256, index * 32, 32)?);
+        }
+
+        Some(pieces)
+    }
+
+    /// Returns piece of the extended state component if the CPU supports it.
+    fn piece(&self, component: u32, offset: usize, len: usize) -> Option<Piece> {
+        if self.legacy || self.xfeatures & 1 << component == 0 {
+            return None;
+        }
+
+        // CPUID reports offsets of components in the standard XSAVE format
+        let offset = xsave_cpuid(component).0 as usize + offset;
+        (offset + len <= self.data.len()).then_some(Piece {
+            offset,
+            len,
+            component,
+        })
+    }
+}
+
+/// Returns EBX and ECX of the XSAVE CPUID leaf, offset and size of components.
+#[cfg(target_arch = "x86_64")]
+fn xsave_cpuid(subleaf: u32) -> (u32, u32) {
+    let result = std::arch::x86_64::__cpuid_count(0xd, subleaf);
+    (result.ebx, result.ecx)
+}
+
+// other architectures have no XSAVE area, so only requests of the registers fail
+#[cfg(not(target_arch = "x86_64"))]
+fn xsave_cpuid(_subleaf: u32) -> (u32, u32) {
+    (0, 0)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
@@ -0,0 +1,404 @@
+use std::{
+    fs::File,
+    io::{Seek, SeekFrom, Write},
+};
+
+use object::elf;
+
+use crate::{
+    arch::Arch,
+    core_file::{self, NT_X86_XSTATE, PRPSINFO_FNAME_LEN, PRPSINFO_PSARGS_LEN},
+    debugger::Debugger,
+    linux_maps::{self, MemoryRegion},
+    target::Target,
+};
+
+// memory is copied into the file in pieces of this size
+const CHUNK_LEN: usize = 1 << 20;
+
+/// Little-endian ELF data, words have the pointer size of the program.
+struct Bytes {
+    data: Vec<u8>,
+    pointer_size: usize,
+}
+
+impl Bytes {
+    fn new(pointer_size: usize) -> Bytes {
+        Bytes {
+            data: Vec::new(),
+            pointer_size,
+        }
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn word(&mut self, value: u64) {
+        self.data
+            .extend_from_slice(&value.to_le_bytes()[..self.pointer_size]);
+    }
+}
+
+struct ProgramHeader {
+    p_type: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+impl ProgramHeader {
+    fn write(&self, out: &mut Bytes) {
+        out.u32(self.p_type);
+        // p_flags moved next to p_type in 64-bit format for alignment
+        if out.pointer_size == 8 {
+            out.u32(self.flags);
+        }
+        out.word(self.offset);
+        out.word(self.vaddr);
+        // p_paddr
+        out.word(0);
+        out.word(self.filesz);
+        out.word(self.memsz);
+        if out.pointer_size != 8 {
+            out.u32(self.flags);
+        }
+        out.word(self.align);
+    }
+}
+
+/// Fields of `/proc/<pid>/stat` which go into the process notes.
+struct ProcStat {
+    state: u8,
+    ppid: i32,
+    pgrp: i32,
+    sid: i32,
+    nice: i8,
+}
+
+/// Writes ELF core file of the stopped process with the traced thread registers
+/// and all readable memory, code under breakpoints is saved without them.
+pub fn write<T: Target + ?Sized>(debugger: &Debugger<T>, path: &str) -> Result<(), String> {
+    let arch = debugger.arch();
+    let pointer_size = arch.pointer_size();
+    let page_size = linux_maps::page_size();
+
+    let regions = debugger.memory_regions()?;
+    let notes = build_notes(debugger.target(), &regions)?;
+
+    let (ehsize, phentsize) = match pointer_size {
+        8 => (64, 56),
+        _ => (52, 32),
+    };
+    let phnum = regions.len() + 1;
+    if phnum >= elf::PN_XNUM as usize {
+        Err(format!("too many memory regions: {}", regions.len()))?;
+    }
+    let notes_offset = (ehsize + phnum * phentsize) as u64;
+
+    let mut file =
+        File::create(path).map_err(|e| format!("failed to create file {}: {}", path, e))?;
+
+    let mut headers = vec![ProgramHeader {
+        p_type: elf::PT_NOTE,
+        flags: 0,
+        offset: notes_offset,
+        vaddr: 0,
+        filesz: notes.len() as u64,
+        memsz: 0,
+        align: 4,
+    }];
+    // memory goes after notes, every segment starts at a page boundary
+    let mut offset = (notes_offset + notes.len() as u64).next_multiple_of(page_size);
+    for region in &regions {
+        let filesz = match region.perms.read {
+            true => copy_region(debugger, &mut file, offset, region)?,
+            false => 0,
+        };
+        let flag = |set, flag| if set { flag } else { 0 };
+        headers.push(ProgramHeader {
+            p_type: elf::PT_LOAD,
+            flags: flag(region.perms.read, elf::PF_R)
+                | flag(region.perms.write, elf::PF_W)
+                | flag(region.perms.exec, elf::PF_X),
+            offset,
+            vaddr: region.start,
+            filesz,
+            memsz: region.end - region.start,
+            align: page_size,
+        });
+        offset = (offset + filesz).next_multiple_of(page_size);
+    }
+
+    let mut out = Bytes::new(pointer_size);
+    out.data.extend_from_slice(&elf::ELFMAG);
+    out.data.push(match pointer_size {
+        8 => elf::ELFCLASS64,
+        _ => elf::ELFCLASS32,
+    });
+    out.data.push(elf::ELFDATA2LSB);
+    out.data.push(elf::EV_CURRENT);
+    out.data.push(elf::ELFOSABI_NONE);
+    out.data.resize(16, 0);
+    out.u16(elf::ET_CORE);
+    out.u16(arch.elf_machine());
+    out.u32(elf::EV_CURRENT as u32);
+    // e_entry, e_phoff and e_shoff
+    out.word(0);
+    out.word(ehsize as u64);
+    out.word(0);
+    // e_flags
+    out.u32(0);
+    out.u16(ehsize as u16);
+    out.u16(phentsize as u16);
+    out.u16(phnum as u16);
+    // no section headers
+    out.u16(0);
+    out.u16(0);
+    out.u16(0);
+    for header in headers {
+        header.write(&mut out);
+    }
+    out.data.extend_from_slice(&notes);
+
+    file.seek(SeekFrom::Start(0))
+        .and_then(|_| file.write_all(&out.data))
+        .map_err(|e| format!("failed to write core file headers: {}", e))
+}
+
+/// Copies the region memory into the file at the offset, returns number of bytes copied.
+fn copy_region<T: Target + ?Sized>(
+    debugger: &Debugger<T>,
+    file: &mut File,
+    offset: u64,
+    region: &MemoryRegion,
+) -> Result<u64, String> {
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|e| format!("failed to seek core file: {}", e))?;
+
+    let mut addr = region.start;
+    while addr < region.end {
+        let len = (region.end - addr).min(CHUNK_LEN as u64) as usize;
+        let data = debugger.read_bytes(addr, len)?;
+        file.write_all(&data)
+            .map_err(|e| format!("failed to write memory at {:#x}: {}", addr, e))?;
+
+        addr += data.len() as u64;
+        // the rest of the region can't be read, like [vvar]
+        if data.len() < len {
+            break;
+        }
+    }
+
+    Ok(addr - region.start)
+}
+
+/// Builds notes from the target state, process details are read from its `/proc` files.
+fn build_notes<T: Target + ?Sized>(
+    target: &T,
+    regions: &[MemoryRegion],
+) -> Result<Vec<u8>, String> {
+    let arch = target.arch();
+    let pid = target.pid();
+    let stat = read_stat(target)?;
+    let regs = target.read_regs()?;
+    // floating point notes are known for x86 only
+    let fp_note = match arch.elf_machine() {
+        elf::EM_X86_64 => Some(("CORE", elf::NT_FPREGSET)),
+        elf::EM_386 => Some(("LINUX", elf::NT_PRXFPREG)),
+        _ => None,
+    };
+    let fpregs = fp_note.map(|_| target.read_fp_regs()).transpose()?;
+    let auxv = target.auxv()?;
+
+    // same order as the kernel uses
+    let mut notes = Vec::new();
+    let prstatus = prstatus(arch, pid, &stat, &regs, fpregs.is_some());
+    push_note(&mut notes, "CORE", elf::NT_PRSTATUS, &prstatus);
+    push_note(
+        &mut notes,
+        "CORE",
+        elf::NT_PRPSINFO,
+        &prpsinfo(target, &stat)?,
+    );
+    push_note(&mut notes, "CORE", elf::NT_AUXV, &auxv);
+    push_note(&mut notes, "CORE", elf::NT_FILE, &file_note(arch, regions));
+    if let (Some((name, n_type)), Some(fpregs)) = (fp_note, &fpregs) {
+        push_note(&mut notes, name, n_type, fpregs.fxsave_area());
+        if let Some(xsave) = fpregs.xsave_area() {
+            push_note(&mut notes, "LINUX", NT_X86_XSTATE, xsave);
+        }
+    }
+
+    Ok(notes)
+}
+
+/// Appends note with the name and the description padded to 4 bytes.
+fn push_note(notes: &mut Vec<u8>, name: &str, n_type: u32, desc: &[u8]) {
+    notes.extend_from_slice(&(name.len() as u32 + 1).to_le_bytes());
+    notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    notes.extend_from_slice(&n_type.to_le_bytes());
+    notes.extend_from_slice(name.as_bytes());
+    notes.push(0);
+    notes.resize(notes.len().next_multiple_of(4), 0);
+    notes.extend_from_slice(desc);
+    notes.resize(notes.len().next_multiple_of(4), 0);
+}
+
+fn put(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+    buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn prstatus(arch: &dyn Arch, pid: i32, stat: &ProcStat, regs: &[u8], fp_valid: bool) -> Vec<u8> {
+    let layout = core_file::prstatus_layout(arch.pointer_size(), regs.len());
+    let mut desc = vec![0u8; layout.size];
+
+    // signal fields stay zero, the process is stopped by the debugger
+    for (i, id) in [pid, stat.ppid, stat.pgrp, stat.sid].iter().enumerate() {
+        put(&mut desc, layout.pid + i * 4, &id.to_le_bytes());
+    }
+    put(&mut desc, layout.regs, regs);
+    // pr_fpvalid
+    put(
+        &mut desc,
+        layout.regs + regs.len(),
+        &(fp_valid as u32).to_le_bytes(),
+    );
+
+    desc
+}
+
+fn prpsinfo<T: Target + ?Sized>(target: &T, stat: &ProcStat) -> Result<Vec<u8>, String> {
+    let (arch, pid) = (target.arch(), target.pid());
+    let layout = core_file::prpsinfo_layout(arch.pointer_size());
+    let mut desc = vec![0u8; layout.size];
+
+    // pr_state is index of the state in "RSDTZW", pr_sname is the letter itself
+    desc[0] = b"RSDTZW".iter().position(|&s| s == stat.state).unwrap_or(0) as u8;
+    desc[1] = stat.state;
+    desc[2] = (stat.state == b'Z') as u8;
+    desc[3] = stat.nice as u8;
+
+    let status = target
+        .read_proc_file("status")
+        .map_err(|e| format!("failed to read owner of process {}: {}", pid, e))?;
+    let status = String::from_utf8_lossy(&status);
+    let owner = [status_id(&status, "Uid:")?, status_id(&status, "Gid:")?];
+    for (i, id) in owner.iter().enumerate() {
+        let offset = layout.uid + i * layout.id_size;
+        put(&mut desc, offset, &id.to_le_bytes()[..layout.id_size]);
+    }
+    for (i, id) in [pid, stat.ppid, stat.pgrp, stat.sid].iter().enumerate() {
+        put(&mut desc, layout.pid + i * 4, &id.to_le_bytes());
+    }
+
+    let comm = target
+        .read_proc_file("comm")
+        .map_err(|e| format!("failed to read name of process {}: {}", pid, e))?;
+    let comm = comm.trim_ascii_end();
+    put(
+        &mut desc,
+        layout.fname,
+        &comm[..comm.len().min(PRPSINFO_FNAME_LEN)],
+    );
+
+    // arguments are separated by spaces and cut to fit with the terminating NUL
+    let mut args = target
+        .read_proc_file("cmdline")
+        .map_err(|e| format!("failed to read arguments of process {}: {}", pid, e))?;
+    args.truncate(PRPSINFO_PSARGS_LEN - 1);
+    for byte in &mut args {
+        if *byte == 0 {
+            *byte = b' ';
+        }
+    }
+    put(&mut desc, layout.psargs, args.trim_ascii_end());
+
+    Ok(desc)
+}
+
+/// Builds `NT_FILE` note from file mappings of the process.
+fn file_note(arch: &dyn Arch, regions: &[MemoryRegion]) -> Vec<u8> {
+    let page_size = linux_maps::page_size();
+    // pseudo paths like [heap] aren't files
+    let files: Vec<(&MemoryRegion, &str)> = regions
+        .iter()
+        .filter_map(|region| Some((region, region.pathname.as_deref()?)))
+        .filter(|(_, path)| path.starts_with('/'))
+        .collect();
+
+    let mut note = Bytes::new(arch.pointer_size());
+    note.word(files.len() as u64);
+    note.word(page_size);
+    for (region, _) in &files {
+        note.word(region.start);
+        note.word(region.end);
+        note.word(region.offset / page_size);
+    }
+    for (_, path) in files {
+        note.data.extend_from_slice(path.as_bytes());
+        note.data.push(0);
+    }
+
+    note.data
+}
+
+/// Returns the real id from the `Uid:` or `Gid:` line of `/proc/<pid>/status`.
+fn status_id(status: &str, key: &str) -> Result<u32, String> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key))
+        .and_then(|ids| ids.split_whitespace().next())
+        .ok_or(format!("{} is missing in process status", key))?
+        .parse()
+        .map_err(|e| format!("failed to parse {} of process status: {}", key, e))
+}
+
+fn read_stat<T: Target + ?Sized>(target: &T) -> Result<ProcStat, String> {
+    let stat = target
+        .read_proc_file("stat")
+        .map_err(|e| format!("failed to read stat of process {}: {}", target.pid(), e))?;
+    let stat = String::from_utf8_lossy(&stat);
+    // process name may contain spaces and parentheses, so fields are counted after the last one
+    let fields: Vec<&str> = stat
+        .rsplit_once(')')
+        .ok_or("process name isn't terminated")?
+        .1
+        .split_whitespace()
+        .collect();
+    let field = |index: usize| -> Result<i64, String> {
+        fields
+            .get(index)
+            .ok_or(format!("stat field {} is missing", index))?
+            .parse()
+            .map_err(|e| format!("failed to parse stat field {}: {}", index, e))
+    };
+
+    Ok(ProcStat {
+        state: fields.first().ok_or("process state is missing")?.as_bytes()[0],
+        ppid: field(1)? as i32,
+        pgrp: field(2)? as i32,
+        sid: field(3)? as i32,
+        nice: field(16)? as i8,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_real_ids_from_status() {
+        let status = "Name:\tcat\nUid:\t1000\t0\t0\t0\nGid:\t100\t100\t100\t100\n";
+        assert_eq!(status_id(status, "Uid:"), Ok(1000));
+        assert_eq!(status_id(status, "Gid:"), Ok(100));
+        assert!(status_id("Name:\tcat\n", "Uid:").is_err());
+    }
+}
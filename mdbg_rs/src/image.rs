@@ -0,0 +1,202 @@
+use std::{collections::BTreeMap, fs, ops::Range, os::unix::fs::MetadataExt, sync::Mutex};
+
+use object::{Object, ObjectKind, ObjectSection, ObjectSegment, ObjectSymbol, SymbolKind};
+
+use crate::{
+    arch::{self, Arch},
+    dwarf::{self, Dwarf},
+    linux_maps,
+    unwind::CallFrames,
+};
+
+pub struct Symbol {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+}
+
+/// ELF file mapped into the debugger memory together with its debug info and symbols.
+pub struct Image<'a> {
+    path: String,
+    arch: &'static dyn Arch,
+    dwarf: Dwarf<'a>,
+    // sorted by address
+    symbols: Vec<Symbol>,
+    // ET_EXEC file is linked to absolute addresses and never relocated
+    relocatable: bool,
+    // lowest virtual address of PT_LOAD segments
+    min_vaddr: u64,
+    // file addresses from the lowest to the highest PT_LOAD segment end
+    vaddr_range: Range<u64>,
+    call_frames: Option<CallFrames<'a>>,
+    // path of the dynamic linker from `.interp`, None for static programs and libraries
+    interpreter: Option<String>,
+}
+
+impl<'a> Image<'a> {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn arch(&self) -> &'static dyn Arch {
+        self.arch
+    }
+
+    pub fn dwarf(&self) -> &Dwarf<'a> {
+        &self.dwarf
+    }
+
+    /// Returns difference between runtime and file addresses for the image mapped at `base`.
+    pub fn load_bias(&self, base: u64) -> u64 {
+        if !self.relocatable {
+            return 0;
+        }
+
+        base.wrapping_sub(self.min_vaddr & !(linux_maps::page_size() - 1))
+    }
+
+    /// Returns the address the image is mapped at with the load bias, reverse of `load_bias`.
+    pub fn load_addr(&self, bias: u64) -> u64 {
+        (self.min_vaddr & !(linux_maps::page_size() - 1)).wrapping_add(bias)
+    }
+
+    /// Returns true for position independent images which are loaded at any address.
+    pub fn relocatable(&self) -> bool {
+        self.relocatable
+    }
+
+    pub fn interpreter(&self) -> Option<&str> {
+        self.interpreter.as_deref()
+    }
+
+    /// Returns true if the file address belongs to the loaded part of the file.
+    pub fn contains(&self, addr: u64) -> bool {
+        self.vaddr_range.contains(&addr)
+    }
+
+    pub fn call_frames(&self) -> Option<&CallFrames<'a>> {
+        self.call_frames.as_ref()
+    }
+
+    pub fn find_symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    /// Returns symbol containing the address and offset of the address inside it.
+    pub fn symbolize(&self, addr: u64) -> Option<(&Symbol, u64)> {
+        let index = self.symbols.partition_point(|s| s.addr <= addr);
+        let symbol = self.symbols[..index]
+            .iter()
+            .rev()
+            .find(|s| addr < s.addr + s.size.max(1))?;
+
+        Some((symbol, addr - symbol.addr))
+    }
+}
+
+// device, inode, modification time and size tell versions of a file apart
+type FileKey = (u64, u64, i64, i64, u64);
+
+/// Mapped file and its parsed DWARF sections, they live as long as the process.
+struct MappedFile {
+    data: &'static [u8],
+    dwarf: &'static dwarf::DwarfSections<'static>,
+    endian: gimli::RunTimeEndian,
+}
+
+// images of libraries are loaded again on every restart, exec and dlopen,
+// so each version of a file is mapped once instead of leaking a new mapping every time
+static MAPPED_FILES: Mutex<BTreeMap<FileKey, MappedFile>> = Mutex::new(BTreeMap::new());
+
+fn map_file(path: &str) -> Result<(&'static [u8], Dwarf<'static>), String> {
+    let file = fs::File::open(path).map_err(|e| format!("failed to open file {}: {}", path, e))?;
+    let metadata = file
+        .metadata()
+        .map_err(|e| format!("failed to get metadata of {}: {}", path, e))?;
+    let key = (
+        metadata.dev(),
+        metadata.ino(),
+        metadata.mtime(),
+        metadata.mtime_nsec(),
+        metadata.size(),
+    );
+
+    let mut files = MAPPED_FILES
+        .lock()
+        .map_err(|_| "mapped files lock is poisoned")?;
+    let mapped = match files.get(&key) {
+        Some(mapped) => mapped,
+        None => {
+            let mmap = unsafe {
+                memmap::Mmap::map(&file).map_err(|e| format!("failed to mmap file: {}", e))?
+            };
+            let data: &'static [u8] = Box::leak(Box::new(mmap));
+            let (dwarf, endian) = dwarf::load_dwarf(data)?;
+            files.entry(key).or_insert(MappedFile {
+                data,
+                dwarf: Box::leak(Box::new(dwarf)),
+                endian,
+            })
+        }
+    };
+
+    Ok((
+        mapped.data,
+        Dwarf::new(dwarf::borrow_section(mapped.dwarf, mapped.endian)),
+    ))
+}
+
+pub fn load(path: &str) -> Result<Image<'static>, String> {
+    let (data, dwarf) = map_file(path)?;
+    let arch = arch::for_elf(data)?;
+
+    let object =
+        object::File::parse(data).map_err(|e| format!("failed to parse object file: {}", e))?;
+
+    let min_vaddr = object.segments().map(|s| s.address()).min().unwrap_or(0);
+    let max_vaddr = object
+        .segments()
+        .map(|s| s.address() + s.size())
+        .max()
+        .unwrap_or(0);
+
+    Ok(Image {
+        path: path.to_owned(),
+        arch,
+        dwarf,
+        symbols: load_symbols(&object),
+        relocatable: object.kind() != ObjectKind::Executable,
+        min_vaddr,
+        vaddr_range: min_vaddr..max_vaddr,
+        call_frames: CallFrames::load(&object, arch.pointer_size()),
+        interpreter: object
+            .section_by_name(".interp")
+            .and_then(|section| section.data().ok())
+            .map(|path| {
+                String::from_utf8_lossy(path)
+                    .trim_end_matches('\0')
+                    .to_owned()
+            }),
+    })
+}
+
+fn load_symbols(object: &object::File) -> Vec<Symbol> {
+    let mut symbols: Vec<Symbol> = object
+        .symbols()
+        .chain(object.dynamic_symbols())
+        .filter(|s| matches!(s.kind(), SymbolKind::Text | SymbolKind::Data))
+        .filter(|s| s.is_definition() && s.address() != 0)
+        .filter_map(|s| {
+            Some(Symbol {
+                name: s.name().ok().filter(|name| !name.is_empty())?.to_owned(),
+                addr: s.address(),
+                size: s.size(),
+            })
+        })
+        .collect();
+
+    symbols.sort_by(|a, b| a.addr.cmp(&b.addr).then_with(|| a.name.cmp(&b.name)));
+    symbols.dedup_by(|a, b| a.addr == b.addr && a.name == b.name);
+
+    symbols
+}
@@ -0,0 +1,114 @@
+use std::{
+    fs::{File, OpenOptions},
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+};
+
+use nix::sys::ptrace;
+
+#[derive(Clone)]
+pub struct Launcher {
+    program_path: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    cwd: Option<String>,
+    stdin: Option<String>,
+    stdout: Option<String>,
+    stderr: Option<String>,
+}
+
+impl Launcher {
+    pub fn new(program_path: &str) -> Launcher {
+        Launcher {
+            program_path: program_path.to_owned(),
+            args: Vec::new(),
+            env: Vec::new(),
+            cwd: None,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+        }
+    }
+
+    pub fn args<I: IntoIterator<Item = String>>(mut self, args: I) -> Launcher {
+        self.args.extend(args);
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Launcher {
+        self.env.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn cwd(mut self, cwd: &str) -> Launcher {
+        self.cwd = Some(cwd.to_owned());
+        self
+    }
+
+    pub fn stdin(mut self, path: &str) -> Launcher {
+        self.stdin = Some(path.to_owned());
+        self
+    }
+
+    pub fn stdout(mut self, path: &str) -> Launcher {
+        self.stdout = Some(path.to_owned());
+        self
+    }
+
+    pub fn stderr(mut self, path: &str) -> Launcher {
+        self.stderr = Some(path.to_owned());
+        self
+    }
+
+    pub fn program_path(&self) -> &str {
+        &self.program_path
+    }
+
+    /// Starts the program as a traced child process and returns its pid.
+    /// The child stops with SIGTRAP on exec, so the caller must wait for it
+    /// before issuing any other ptrace request.
+    pub fn launch(&self) -> Result<i32, String> {
+        let mut command = Command::new(&self.program_path);
+        // own process group keeps Ctrl-C at the debugger prompt away from the program,
+        // frontends hand the terminal to the group while the program runs
+        command
+            .args(&self.args)
+            .envs(self.env.iter().cloned())
+            .process_group(0);
+
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        if let Some(path) = &self.stdin {
+            let file = File::open(path)
+                .map_err(|e| format!("failed to open stdin file {}: {}", path, e))?;
+            command.stdin(Stdio::from(file));
+        }
+        if let Some(path) = &self.stdout {
+            command.stdout(Stdio::from(create_output_file(path)?));
+        }
+        if let Some(path) = &self.stderr {
+            command.stderr(Stdio::from(create_output_file(path)?));
+        }
+
+        // SAFETY: traceme only issues a syscall, it doesn't allocate or take locks.
+        unsafe {
+            command.pre_exec(|| ptrace::traceme().map_err(|e| e.into()));
+        }
+
+        let child = command
+            .spawn()
+            .map_err(|e| format!("failed to spawn {}: {}", self.program_path, e))?;
+
+        Ok(child.id() as i32)
+    }
+}
+
+fn create_output_file(path: &str) -> Result<File, String> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .map_err(|e| format!("failed to open output file {}: {}", path, e))
+}
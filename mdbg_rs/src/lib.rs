@@ -1,23 +1,55 @@
+pub mod arch;
 mod breakpoint;
+mod core_file;
 mod debugger;
+mod disasm;
 mod dwarf;
+mod fpregs;
+mod gcore;
+mod image;
+mod launcher;
 pub mod linux_maps;
+mod memory;
 mod reg;
+pub mod rsp;
+mod search;
+mod shared_libs;
+mod target;
+mod unwind;
 
-use std::fs;
-
-pub use debugger::{BreakpointRef, Debugger};
-use dwarf::Dwarf;
+pub use core_file::{CoreFile, FileMapping};
+pub use debugger::{BreakpointRef, Debugger, StopReason};
+pub use disasm::Instruction;
+pub use dwarf::SourceLocation;
+pub use fpregs::FpRegs;
+pub use launcher::Launcher;
 pub use reg::{Reg, RegSelector};
+pub use search::{SearchMatch, SearchPattern, SearchScope};
+pub use target::{PtraceTarget, RemoteTarget, Target, TargetStop};
+pub use unwind::Frame;
 
 pub fn load_in_memory(program_pid: i32, program_path: &str) -> Result<Debugger<'static>, String> {
-    let file = fs::File::open(program_path)
-        .map_err(|e| format!("failed to open file {}: {}", program_path, e))?;
-    let mmap =
-        unsafe { memmap::Mmap::map(&file).map_err(|e| format!("failed to mmap file: {}", e))? };
+    let executable = image::load(program_path)?;
+    let target = PtraceTarget::new(program_pid, executable.arch());
+    Ok(Debugger::new(Box::new(target), executable))
+}
 
-    let (dwarf, endian) = dwarf::load_dwarf(Box::leak(Box::new(mmap)))?;
-    let dwarf = Dwarf::new(dwarf::borrow_section(Box::leak(Box::new(dwarf)), endian));
+/// Returns the difference between runtime addresses and addresses in the ELF file,
+/// it is zero for non-PIE executables.
+pub fn get_load_bias(program_pid: i32, program_path: &str) -> Result<u64, String> {
+    let image = image::load(program_path)?;
+    let load_addr = linux_maps::get_load_addr(program_pid, program_path)?;
+    Ok(image.load_bias(load_addr))
+}
+
+/// Loads the executable and connects to the GDB remote stub at `host:port` which runs it.
+pub fn load_remote(program_path: &str, address: &str) -> Result<Debugger<'static>, String> {
+    let executable = image::load(program_path)?;
+    let target = RemoteTarget::connect(address, executable.arch())?;
+    Ok(Debugger::new(Box::new(target), executable))
+}
 
-    Ok(Debugger::new(program_pid, dwarf))
+/// Loads the executable and the core file it dumped for post-mortem debugging.
+pub fn load_core(program_path: &str, core: CoreFile) -> Result<Debugger<'static>, String> {
+    Debugger::from_core(image::load(program_path)?, core)
 }
@@ -1,16 +1,197 @@
-use regex::Regex;
-use std::fs::read_to_string;
+use std::{
+    fs::{self, read_to_string},
+    path::Path,
+};
 
-pub fn get_load_addr(pid: i32, executable_path: &str) -> Result<u64, String> {
+use nix::unistd::{sysconf, SysconfVar};
+
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub exec: bool,
+    // private (copy on write) mapping otherwise
+    pub shared: bool,
+}
+
+/// One line of `/proc/<pid>/maps`.
+pub struct MemoryRegion {
+    pub start: u64,
+    pub end: u64,
+    pub perms: Permissions,
+    pub offset: u64,
+    pub dev: (u32, u32),
+    pub inode: u64,
+    // file path or pseudo path like [heap], None for anonymous mappings
+    pub pathname: Option<String>,
+}
+
+impl MemoryRegion {
+    pub fn contains(&self, addr: u64) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+}
+
+pub fn read_maps(pid: i32) -> Result<Vec<MemoryRegion>, String> {
     let maps = read_to_string(format!("/proc/{}/maps", pid))
-        .map_err(|e| format!("failed to read maps file {}: {}", executable_path, e))?;
-
-    let regexp = Regex::new(&format!("([0-9a-f]*)-.*{}", executable_path))
-        .expect("failed to compile regexp");
-    Ok(regexp
-        .captures_iter(&maps)
-        .map(|c| c.get(1).unwrap().as_str())
-        .map(|addr| u64::from_str_radix(addr, 16).expect("failed to parse address"))
+        .map_err(|e| format!("failed to read maps file of process {}: {}", pid, e))?;
+
+    parse_maps(&maps)
+}
+
+pub fn parse_maps(maps: &str) -> Result<Vec<MemoryRegion>, String> {
+    maps.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| parse_region(line).map_err(|e| format!("failed to parse `{}`: {}", line, e)))
+        .collect()
+}
+
+fn parse_region(line: &str) -> Result<MemoryRegion, String> {
+    // pathname may contain spaces, so only first five fields are split
+    let mut fields = line.splitn(6, ' ');
+    let mut next_field = |name| fields.next().ok_or(format!("{} is missing", name));
+
+    let (start, end) = next_field("address range")?
+        .split_once('-')
+        .ok_or("address range has no `-`")?;
+    let perms = next_field("permissions")?.as_bytes();
+    let offset = next_field("offset")?;
+    let (major, minor) = next_field("device")?
+        .split_once(':')
+        .ok_or("device has no `:`")?;
+    let inode = next_field("inode")?;
+    let pathname = fields.next().map(str::trim_start).filter(|p| !p.is_empty());
+
+    if perms.len() != 4 {
+        Err("permissions must have 4 characters")?;
+    }
+
+    Ok(MemoryRegion {
+        start: parse_hex(start)?,
+        end: parse_hex(end)?,
+        perms: Permissions {
+            read: perms[0] == b'r',
+            write: perms[1] == b'w',
+            exec: perms[2] == b'x',
+            shared: perms[3] == b's',
+        },
+        offset: parse_hex(offset)?,
+        dev: (parse_hex(major)? as u32, parse_hex(minor)? as u32),
+        inode: inode
+            .parse()
+            .map_err(|e| format!("failed to parse inode: {}", e))?,
+        pathname: pathname.map(|p| p.to_owned()),
+    })
+}
+
+fn parse_hex(value: &str) -> Result<u64, String> {
+    u64::from_str_radix(value, 16).map_err(|e| format!("failed to parse {}: {}", value, e))
+}
+
+/// Returns regions mapped from the file, the path is compared after resolving symlinks.
+pub fn find_file_regions(pid: i32, path: &str) -> Result<Vec<MemoryRegion>, String> {
+    let path = fs::canonicalize(path).map_err(|e| format!("failed to resolve {}: {}", path, e))?;
+
+    Ok(read_maps(pid)?
+        .into_iter()
+        .filter(|region| region.pathname.as_deref().map(Path::new) == Some(path.as_path()))
+        .collect())
+}
+
+/// Returns the lowest address the executable file is mapped at.
+pub fn get_load_addr(pid: i32, executable_path: &str) -> Result<u64, String> {
+    find_file_regions(pid, executable_path)?
+        .iter()
+        .map(|region| region.start)
         .min()
-        .unwrap())
+        .ok_or(format!("{} isn't mapped", executable_path))
+}
+
+/// Returns path of the file mapped at the address, None for anonymous mappings.
+pub fn get_mapped_path(pid: i32, addr: u64) -> Result<Option<String>, String> {
+    Ok(read_maps(pid)?
+        .into_iter()
+        .find(|region| region.contains(addr))
+        .and_then(|region| region.pathname))
+}
+
+pub fn page_size() -> u64 {
+    const DEFAULT_PAGE_SIZE: u64 = 0x1000;
+
+    sysconf(SysconfVar::PAGE_SIZE)
+        .ok()
+        .flatten()
+        .map(|size| size as u64)
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(line: &str) -> MemoryRegion {
+        let mut regions = parse_maps(line).unwrap();
+        assert_eq!(regions.len(), 1);
+        regions.remove(0)
+    }
+
+    #[test]
+    fn parses_file_mapping() {
+        let region = parse_one(
+            "55d4c3a00000-55d4c3a21000 r-xp 00001000 fd:01 1835030                    /usr/bin/cat",
+        );
+
+        assert_eq!(region.start, 0x55d4c3a00000);
+        assert_eq!(region.end, 0x55d4c3a21000);
+        assert!(region.perms.read && !region.perms.write && region.perms.exec);
+        assert!(!region.perms.shared);
+        assert_eq!(region.offset, 0x1000);
+        assert_eq!(region.dev, (0xfd, 0x01));
+        assert_eq!(region.inode, 1835030);
+        assert_eq!(region.pathname.as_deref(), Some("/usr/bin/cat"));
+    }
+
+    #[test]
+    fn keeps_spaces_in_pathname() {
+        let region = parse_one(
+            "7f0000000000-7f0000001000 r--s 00000000 08:02 42                         /tmp/my lib  v2.so",
+        );
+
+        assert!(region.perms.shared);
+        assert_eq!(region.pathname.as_deref(), Some("/tmp/my lib  v2.so"));
+    }
+
+    #[test]
+    fn keeps_deleted_suffix() {
+        let region = parse_one(
+            "7f0000000000-7f0000001000 r-xp 00000000 08:02 42                         /tmp/libx.so (deleted)",
+        );
+
+        assert_eq!(region.pathname.as_deref(), Some("/tmp/libx.so (deleted)"));
+    }
+
+    #[test]
+    fn parses_pseudo_and_anonymous_mappings() {
+        let regions = parse_maps(
+            "ffffffffff600000-ffffffffff601000 --xp 00000000 00:00 0                  [vsyscall]\n\
+             7ffd1c9e0000-7ffd1ca01000 rw-p 00000000 00:00 0                          [stack]\n\
+             7f0000002000-7f0000004000 rw-p 00000000 00:00 0 \n",
+        )
+        .unwrap();
+
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].start, 0xffffffffff600000);
+        assert!(!regions[0].perms.read && regions[0].perms.exec);
+        assert_eq!(regions[0].pathname.as_deref(), Some("[vsyscall]"));
+        assert_eq!(regions[1].pathname.as_deref(), Some("[stack]"));
+        assert_eq!(regions[2].pathname, None);
+        assert!(regions[2].contains(0x7f0000003fff));
+        assert!(!regions[2].contains(0x7f0000004000));
+    }
+
+    #[test]
+    fn rejects_malformed_line() {
+        assert!(parse_maps("7f0000000000 r-xp 00000000 08:02 42").is_err());
+        assert!(parse_maps("7f0000000000-7f0000001000 r-x 00000000 08:02 42").is_err());
+        assert!(parse_maps("7f0000000000-7f0000001000 r-xp 00000000 08:02").is_err());
+    }
 }
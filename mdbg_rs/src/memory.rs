@@ -0,0 +1,134 @@
+use std::{
+    fs::OpenOptions,
+    io::{IoSlice, IoSliceMut},
+    os::unix::fs::FileExt,
+};
+
+use nix::{
+    errno::Errno,
+    sys::uio::{process_vm_readv, process_vm_writev, RemoteIoVec},
+    unistd::Pid,
+};
+
+use crate::linux_maps;
+
+/// Reads process memory into the buffer and returns number of bytes read,
+/// the read stops at the first page which isn't mapped.
+pub fn read(pid: i32, addr: u64, buf: &mut [u8]) -> Result<usize, String> {
+    let mut done = 0;
+    for (chunk_addr, range) in page_chunks(addr, buf.len()) {
+        let chunk = &mut buf[range];
+        let remote = [RemoteIoVec {
+            base: chunk_addr as usize,
+            len: chunk.len(),
+        }];
+
+        let result = process_vm_readv(Pid::from_raw(pid), &mut [IoSliceMut::new(chunk)], &remote);
+        let read = match result {
+            Ok(read) => read,
+            // the syscall isn't allowed, e.g. by seccomp, so the slow path is taken
+            Err(Errno::ENOSYS | Errno::EPERM) => {
+                return Ok(done + read_proc_mem(pid, chunk_addr, &mut buf[done..])?)
+            }
+            Err(Errno::EFAULT | Errno::EIO) => break,
+            Err(e) => Err(format!("failed to read memory at {:#x}: {}", chunk_addr, e))?,
+        };
+
+        done += read;
+        if read < remote[0].len {
+            break;
+        }
+    }
+
+    Ok(done)
+}
+
+/// Writes the data into process memory and returns number of bytes written,
+/// read-only pages like program code are written through `/proc/<pid>/mem`.
+pub fn write(pid: i32, addr: u64, data: &[u8]) -> Result<usize, String> {
+    let mut done = 0;
+    for (chunk_addr, range) in page_chunks(addr, data.len()) {
+        let chunk = &data[range];
+        let remote = [RemoteIoVec {
+            base: chunk_addr as usize,
+            len: chunk.len(),
+        }];
+
+        let written = match process_vm_writev(Pid::from_raw(pid), &[IoSlice::new(chunk)], &remote) {
+            Ok(written) if written == chunk.len() => written,
+            // process_vm_writev respects page protection unlike ptrace
+            Ok(_) | Err(Errno::EFAULT | Errno::ENOSYS | Errno::EPERM) => {
+                match write_proc_mem(pid, chunk_addr, chunk) {
+                    Ok(written) => written,
+                    Err(_) if done > 0 => break,
+                    Err(e) => Err(e)?,
+                }
+            }
+            Err(e) => Err(format!(
+                "failed to write memory at {:#x}: {}",
+                chunk_addr, e
+            ))?,
+        };
+
+        done += written;
+        if written < chunk.len() {
+            break;
+        }
+    }
+
+    Ok(done)
+}
+
+fn read_proc_mem(pid: i32, addr: u64, buf: &mut [u8]) -> Result<usize, String> {
+    let file = OpenOptions::new()
+        .read(true)
+        .open(format!("/proc/{}/mem", pid))
+        .map_err(|e| format!("failed to open memory file: {}", e))?;
+
+    let mut done = 0;
+    for (chunk_addr, range) in page_chunks(addr, buf.len()) {
+        let chunk_len = range.len();
+        match file.read_at(&mut buf[range], chunk_addr) {
+            Ok(read) => {
+                done += read;
+                if read < chunk_len {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+
+    Ok(done)
+}
+
+fn write_proc_mem(pid: i32, addr: u64, data: &[u8]) -> Result<usize, String> {
+    let file = OpenOptions::new()
+        .write(true)
+        .open(format!("/proc/{}/mem", pid))
+        .map_err(|e| format!("failed to open memory file: {}", e))?;
+
+    file.write_at(data, addr)
+        .map_err(|e| format!("failed to write memory at {:#x}: {}", addr, e))
+}
+
+/// Splits the address range into pieces which don't cross page boundaries,
+/// returns address of each piece and its range in the buffer.
+fn page_chunks(addr: u64, len: usize) -> impl Iterator<Item = (u64, std::ops::Range<usize>)> {
+    let page_size = linux_maps::page_size();
+    let mut offset = 0;
+
+    std::iter::from_fn(move || {
+        if offset >= len {
+            return None;
+        }
+
+        let chunk_addr = addr + offset as u64;
+        let page_left = (page_size - chunk_addr % page_size) as usize;
+        let chunk_len = page_left.min(len - offset);
+        let range = offset..offset + chunk_len;
+        offset += chunk_len;
+
+        Some((chunk_addr, range))
+    })
+}
@@ -1,6 +1,16 @@
-use std::collections::HashMap;
+use std::os::raw::c_void;
 
-#[derive(PartialEq, Eq)]
+use nix::{errno::Errno, libc};
+
+use crate::{
+    arch::{Arch, RegInfo, SubRegInfo},
+    fpregs,
+};
+
+const NT_PRSTATUS: libc::c_int = 1;
+
+/// General purpose registers of x86-64.
+#[derive(PartialEq, Eq, Clone, Copy)]
 #[allow(dead_code)]
 pub enum Reg {
     R15,
@@ -31,10 +41,37 @@ pub enum Reg {
     GS,
 }
 
-struct RegDescriptor<'a> {
-    reg: Reg,
-    name: &'static str,
-    value_ptr: &'a mut u64,
+impl Reg {
+    fn name(&self) -> &'static str {
+        match self {
+            Reg::R15 => "r15",
+            Reg::R14 => "r14",
+            Reg::R13 => "r13",
+            Reg::R12 => "r12",
+            Reg::RBP => "rbp",
+            Reg::RBX => "rbx",
+            Reg::R11 => "r11",
+            Reg::R10 => "r10",
+            Reg::R9 => "r9",
+            Reg::R8 => "r8",
+            Reg::RAX => "rax",
+            Reg::RCX => "rcx",
+            Reg::RDX => "rdx",
+            Reg::RSI => "rsi",
+            Reg::RDI => "rdi",
+            Reg::RIP => "rip",
+            Reg::CS => "cs",
+            Reg::EFLAGS => "eflags",
+            Reg::RSP => "rsp",
+            Reg::SS => "ss",
+            Reg::FSBASE => "fsbase",
+            Reg::GSBASE => "gsbase",
+            Reg::DS => "ds",
+            Reg::ES => "es",
+            Reg::FS => "fs",
+            Reg::GS => "gs",
+        }
+    }
 }
 
 pub enum RegSelector<'a> {
@@ -42,183 +79,205 @@ pub enum RegSelector<'a> {
     Name(&'a str),
 }
 
+impl RegSelector<'_> {
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            RegSelector::Reg(reg) => reg.name(),
+            RegSelector::Name(name) => name,
+        }
+    }
+
+    /// Checks that the name belongs to an x87, SSE, AVX or AVX-512 register,
+    /// their values don't fit in u64 and are accessed as bytes.
+    pub fn is_fp(&self) -> bool {
+        matches!(self, RegSelector::Name(name) if fpregs::is_fp_register(name))
+    }
+}
+
+/// Reads general purpose registers of the stopped thread as raw `NT_PRSTATUS` register set.
+pub fn read_regset(pid: i32, arch: &dyn Arch) -> Result<Vec<u8>, String> {
+    let mut regs = vec![0u8; arch.regset_size()];
+    let mut iov = libc::iovec {
+        iov_base: regs.as_mut_ptr() as *mut c_void,
+        iov_len: regs.len(),
+    };
+
+    // SAFETY: the kernel writes at most iov_len bytes into the buffer.
+    let res = unsafe {
+        libc::ptrace(
+            libc::PTRACE_GETREGSET,
+            pid,
+            NT_PRSTATUS as *mut c_void,
+            &mut iov as *mut libc::iovec,
+        )
+    };
+    if res == -1 {
+        Err(format!("failed to get regs: {}", Errno::last()))?;
+    }
+
+    Ok(regs)
+}
+
+pub fn write_regset(pid: i32, regs: &mut [u8]) -> Result<(), String> {
+    let mut iov = libc::iovec {
+        iov_base: regs.as_mut_ptr() as *mut c_void,
+        iov_len: regs.len(),
+    };
+
+    // SAFETY: the kernel reads at most iov_len bytes from the buffer.
+    let res = unsafe {
+        libc::ptrace(
+            libc::PTRACE_SETREGSET,
+            pid,
+            NT_PRSTATUS as *mut c_void,
+            &mut iov as *mut libc::iovec,
+        )
+    };
+    if res == -1 {
+        Err(format!("failed to set regs: {}", Errno::last()))?;
+    }
+
+    Ok(())
+}
+
+/// Finds sub-register by name, flags are one bit wide sub-registers
+/// of the flags register named in lower case, like zf.
+fn find_sub_register(arch: &dyn Arch, name: &str) -> Result<SubRegInfo, String> {
+    let flags = arch.flags().iter().map(|(flag, bit)| SubRegInfo {
+        name: flag.to_lowercase(),
+        parent: arch.flags_register(),
+        shift: *bit,
+        bits: 1,
+        zero_extend: false,
+    });
+
+    arch.sub_registers()
+        .into_iter()
+        .chain(flags)
+        .find(|sub| sub.name == name)
+        .ok_or(format!("unknown register {}", name))
+}
+
+fn find_full_register(arch: &dyn Arch, name: &str) -> Option<RegInfo> {
+    arch.registers()
+        .iter()
+        .find(|reg| reg.name == name)
+        .copied()
+}
+
+/// Returns size of the general purpose register or sub-register in bytes.
+pub fn register_size(arch: &dyn Arch, selector: &RegSelector) -> Result<usize, String> {
+    let name = selector.name();
+    match find_full_register(arch, name) {
+        Some(reg) => Ok(reg.size),
+        None => Ok((find_sub_register(arch, name)?.bits as usize).div_ceil(8)),
+    }
+}
+
+fn read_value(regs: &[u8], reg: &RegInfo) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes[..reg.size].copy_from_slice(&regs[reg.offset..reg.offset + reg.size]);
+    u64::from_le_bytes(bytes)
+}
+
 pub fn set_register_value(
-    regs: &mut nix::libc::user_regs_struct,
+    arch: &dyn Arch,
+    regs: &mut [u8],
     selector: &RegSelector,
     value: u64,
-) {
-    select_regs(regs, Some(selector), Some(value));
+) -> Result<(), String> {
+    let name = selector.name();
+    if let Some(reg) = find_full_register(arch, name) {
+        if reg.size < 8 && value >> (reg.size * 8) != 0 {
+            Err(format!(
+                "value {:#x} doesn't fit in {} bytes",
+                value, reg.size
+            ))?;
+        }
+        regs[reg.offset..reg.offset + reg.size].copy_from_slice(&value.to_le_bytes()[..reg.size]);
+        return Ok(());
+    }
+
+    let sub = find_sub_register(arch, name)?;
+    let mask = u64::MAX >> (64 - sub.bits);
+    if value & !mask != 0 {
+        Err(format!(
+            "value {:#x} doesn't fit in {} bits",
+            value, sub.bits
+        ))?;
+    }
+
+    // bits outside of the sub-register are kept, unless the CPU clears the upper ones
+    let kept = match sub.zero_extend {
+        true => !(u64::MAX << sub.shift),
+        false => !(mask << sub.shift),
+    };
+    let full = get_register_value(arch, regs, &RegSelector::Name(sub.parent))?;
+    let full = full & kept | value << sub.shift;
+    set_register_value(arch, regs, &RegSelector::Name(sub.parent), full)
 }
 
-pub fn get_register_value(regs: &mut nix::libc::user_regs_struct, selector: &RegSelector) -> u64 {
-    *select_regs(regs, Some(selector), None)
-        .unwrap()
-        .values()
-        .into_iter()
-        .last()
-        .unwrap()
+pub fn get_register_value(
+    arch: &dyn Arch,
+    regs: &[u8],
+    selector: &RegSelector,
+) -> Result<u64, String> {
+    let name = selector.name();
+    if let Some(reg) = find_full_register(arch, name) {
+        return Ok(read_value(regs, &reg));
+    }
+
+    let sub = find_sub_register(arch, name)?;
+    let full = get_register_value(arch, regs, &RegSelector::Name(sub.parent))?;
+
+    Ok(full >> sub.shift & u64::MAX >> (64 - sub.bits))
 }
 
-pub fn dump_registers(regs: &mut nix::libc::user_regs_struct) -> HashMap<String, u64> {
-    select_regs(regs, None, None).unwrap()
+/// Returns full registers in architectural order.
+pub fn dump_registers(arch: &dyn Arch, regs: &[u8]) -> Vec<(String, u64)> {
+    arch.registers()
+        .iter()
+        .map(|reg| (reg.name.to_owned(), read_value(regs, reg)))
+        .collect()
 }
 
-fn select_regs(
-    regs: &mut nix::libc::user_regs_struct,
-    selector: Option<&RegSelector>,
-    set_value: Option<u64>,
-) -> Option<HashMap<String, u64>> {
-    let descpriptors = [
-        RegDescriptor {
-            reg: Reg::R15,
-            name: "r15",
-            value_ptr: &mut regs.r15,
-        },
-        RegDescriptor {
-            reg: Reg::R14,
-            name: "r14",
-            value_ptr: &mut regs.r14,
-        },
-        RegDescriptor {
-            reg: Reg::R13,
-            name: "r13",
-            value_ptr: &mut regs.r13,
-        },
-        RegDescriptor {
-            reg: Reg::R12,
-            name: "r12",
-            value_ptr: &mut regs.r12,
-        },
-        RegDescriptor {
-            reg: Reg::RBP,
-            name: "rbp",
-            value_ptr: &mut regs.rbp,
-        },
-        RegDescriptor {
-            reg: Reg::RBX,
-            name: "rbx",
-            value_ptr: &mut regs.rbx,
-        },
-        RegDescriptor {
-            reg: Reg::R11,
-            name: "r11",
-            value_ptr: &mut regs.r11,
-        },
-        RegDescriptor {
-            reg: Reg::R10,
-            name: "r10",
-            value_ptr: &mut regs.r10,
-        },
-        RegDescriptor {
-            reg: Reg::R9,
-            name: "r9",
-            value_ptr: &mut regs.r9,
-        },
-        RegDescriptor {
-            reg: Reg::R8,
-            name: "r8",
-            value_ptr: &mut regs.r8,
-        },
-        RegDescriptor {
-            reg: Reg::RAX,
-            name: "rax",
-            value_ptr: &mut regs.rax,
-        },
-        RegDescriptor {
-            reg: Reg::RCX,
-            name: "rcx",
-            value_ptr: &mut regs.rcx,
-        },
-        RegDescriptor {
-            reg: Reg::RDX,
-            name: "rdx",
-            value_ptr: &mut regs.rdx,
-        },
-        RegDescriptor {
-            reg: Reg::RSI,
-            name: "rsi",
-            value_ptr: &mut regs.rsi,
-        },
-        RegDescriptor {
-            reg: Reg::RDI,
-            name: "rdi",
-            value_ptr: &mut regs.rdi,
-        },
-        RegDescriptor {
-            reg: Reg::RIP,
-            name: "rip",
-            value_ptr: &mut regs.rip,
-        },
-        RegDescriptor {
-            reg: Reg::CS,
-            name: "cs",
-            value_ptr: &mut regs.cs,
-        },
-        RegDescriptor {
-            reg: Reg::EFLAGS,
-            name: "eflags",
-            value_ptr: &mut regs.eflags,
-        },
-        RegDescriptor {
-            reg: Reg::RSP,
-            name: "rsp",
-            value_ptr: &mut regs.rsp,
-        },
-        RegDescriptor {
-            reg: Reg::SS,
-            name: "ss",
-            value_ptr: &mut regs.ss,
-        },
-        RegDescriptor {
-            reg: Reg::FSBASE,
-            name: "fsbase",
-            value_ptr: &mut regs.fs_base,
-        },
-        RegDescriptor {
-            reg: Reg::GSBASE,
-            name: "gsbase",
-            value_ptr: &mut regs.gs_base,
-        },
-        RegDescriptor {
-            reg: Reg::DS,
-            name: "ds",
-            value_ptr: &mut regs.ds,
-        },
-        RegDescriptor {
-            reg: Reg::ES,
-            name: "es",
-            value_ptr: &mut regs.es,
-        },
-        RegDescriptor {
-            reg: Reg::FS,
-            name: "fs",
-            value_ptr: &mut regs.fs,
-        },
-        RegDescriptor {
-            reg: Reg::GS,
-            name: "gs",
-            value_ptr: &mut regs.gs,
-        },
-    ];
-
-    let mut result = HashMap::new();
-    for decriptor in descpriptors {
-        match &selector {
-            Some(RegSelector::Reg(reg)) if decriptor.reg != *reg => continue,
-            Some(RegSelector::Name(name)) if decriptor.name != *name => continue,
-            _ => (),
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{I386, X86_64};
 
-        match set_value {
-            Some(value) => {
-                *decriptor.value_ptr = value;
-                break;
-            }
-            None => {
-                result.insert(decriptor.name.to_owned(), *decriptor.value_ptr);
-            }
-        }
+    #[test]
+    fn sizes_follow_sub_register_bits() {
+        let size = |arch: &dyn Arch, name| register_size(arch, &RegSelector::Name(name));
+        assert_eq!(size(&X86_64, "rax"), Ok(8));
+        assert_eq!(size(&X86_64, "eax"), Ok(4));
+        assert_eq!(size(&X86_64, "ax"), Ok(2));
+        assert_eq!(size(&X86_64, "ah"), Ok(1));
+        assert_eq!(size(&X86_64, "zf"), Ok(1));
+        assert_eq!(size(&I386, "eax"), Ok(4));
+        assert!(size(&X86_64, "xax").is_err());
     }
 
-    Some(result)
+    #[test]
+    fn sub_register_writes_stay_inside_parent() {
+        let mut regs = vec![0xffu8; I386.regset_size()];
+        let eax = RegSelector::Name("eax");
+        set_register_value(&I386, &mut regs, &RegSelector::Name("ah"), 0x12).unwrap();
+        assert_eq!(get_register_value(&I386, &regs, &eax), Ok(0xffff_12ff));
+        set_register_value(&I386, &mut regs, &RegSelector::Name("ax"), 0).unwrap();
+        assert_eq!(get_register_value(&I386, &regs, &eax), Ok(0xffff_0000));
+        assert!(set_register_value(&I386, &mut regs, &eax, 1 << 32).is_err());
+        assert!(set_register_value(&I386, &mut regs, &RegSelector::Name("al"), 0x100).is_err());
+        assert_eq!(get_register_value(&I386, &regs, &eax), Ok(0xffff_0000));
+    }
+
+    #[test]
+    fn registers_are_dumped_in_order() {
+        let mut regs = vec![0u8; 27 * 8];
+        set_register_value(&X86_64, &mut regs, &RegSelector::Reg(Reg::RAX), 7).unwrap();
+        let dump = dump_registers(&X86_64, &regs);
+        assert_eq!(dump.len(), 26);
+        assert_eq!(dump[0], ("rax".to_owned(), 7));
+        assert_eq!(dump[16], ("rip".to_owned(), 0));
+    }
 }
@@ -0,0 +1,431 @@
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+};
+
+/// Byte sent outside of packets to interrupt the running program.
+pub const INTERRUPT: u8 = 0x03;
+
+/// GDB number of a signal it has no name for.
+pub const GDB_SIGNAL_UNKNOWN: u8 = 143;
+
+// GDB numbers signals on its own, only the first few match Linux (x86 and AArch64) ones
+const SIGNALS: [(u8, i32); 31] = [
+    (1, 1),   // SIGHUP
+    (2, 2),   // SIGINT
+    (3, 3),   // SIGQUIT
+    (4, 4),   // SIGILL
+    (5, 5),   // SIGTRAP
+    (6, 6),   // SIGABRT
+    (8, 8),   // SIGFPE
+    (9, 9),   // SIGKILL
+    (10, 7),  // SIGBUS
+    (11, 11), // SIGSEGV
+    (12, 31), // SIGSYS
+    (13, 13), // SIGPIPE
+    (14, 14), // SIGALRM
+    (15, 15), // SIGTERM
+    (16, 23), // SIGURG
+    (17, 19), // SIGSTOP
+    (18, 20), // SIGTSTP
+    (19, 18), // SIGCONT
+    (20, 17), // SIGCHLD
+    (21, 21), // SIGTTIN
+    (22, 22), // SIGTTOU
+    (23, 29), // SIGIO
+    (24, 24), // SIGXCPU
+    (25, 25), // SIGXFSZ
+    (26, 26), // SIGVTALRM
+    (27, 27), // SIGPROF
+    (28, 28), // SIGWINCH
+    (30, 10), // SIGUSR1
+    (31, 12), // SIGUSR2
+    (32, 30), // SIGPWR
+    (33, 29), // SIGPOLL is SIGIO on Linux
+];
+
+// real-time signals 33 to 63 are numbered from 45, 32 and 64 are outside the range
+const GDB_REALTIME_33: u8 = 45;
+const GDB_REALTIME_32: u8 = 77;
+const GDB_REALTIME_64: u8 = 78;
+
+/// Converts GDB signal number of a stop reply into the Linux one.
+pub fn linux_signal(gdb: u8) -> Option<i32> {
+    match gdb {
+        GDB_REALTIME_33..=75 => Some((gdb - GDB_REALTIME_33) as i32 + 33),
+        GDB_REALTIME_32 => Some(32),
+        GDB_REALTIME_64 => Some(64),
+        _ => SIGNALS
+            .iter()
+            .find(|(number, _)| *number == gdb)
+            .map(|(_, linux)| *linux),
+    }
+}
+
+/// Converts Linux signal number into the GDB one for stop replies.
+pub fn gdb_signal(linux: i32) -> u8 {
+    match linux {
+        33..=63 => (linux - 33) as u8 + GDB_REALTIME_33,
+        32 => GDB_REALTIME_32,
+        64 => GDB_REALTIME_64,
+        _ => SIGNALS
+            .iter()
+            .find(|(_, number)| *number == linux)
+            .map_or(GDB_SIGNAL_UNKNOWN, |(gdb, _)| *gdb),
+    }
+}
+
+pub enum Packet {
+    Data(Vec<u8>),
+    Interrupt,
+}
+
+/// GDB Remote Serial Protocol connection, packets are framed as `$data#checksum`
+/// and acknowledged with `+` until no-ack mode is agreed on.
+pub struct Connection<S> {
+    stream: S,
+    buf: VecDeque<u8>,
+    no_ack: bool,
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub fn new(stream: S) -> Connection<S> {
+        Connection {
+            stream,
+            buf: VecDeque::new(),
+            no_ack: false,
+        }
+    }
+
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    /// Stops sending and expecting acknowledgments, called after `QStartNoAckMode` is accepted.
+    pub fn set_no_ack(&mut self) {
+        self.no_ack = true;
+    }
+
+    /// Sends the packet and waits until the other side acknowledges it.
+    pub fn send(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        packet.extend_from_slice(data);
+        packet.extend_from_slice(format!("#{:02x}", checksum(data)).as_bytes());
+
+        loop {
+            self.stream
+                .write_all(&packet)
+                .and_then(|_| self.stream.flush())
+                .map_err(|e| format!("failed to send packet: {}", e))?;
+            if self.no_ack {
+                return Ok(());
+            }
+
+            loop {
+                match self.read_byte()?.ok_or("connection closed")? {
+                    b'+' => return Ok(()),
+                    // corrupted packet is sent again
+                    b'-' => break,
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Returns the next packet with run-length encoding expanded, None if the connection is closed.
+    pub fn receive(&mut self) -> Result<Option<Packet>, String> {
+        loop {
+            let byte = match self.read_byte()? {
+                Some(byte) => byte,
+                None => return Ok(None),
+            };
+            match byte {
+                b'$' => (),
+                INTERRUPT => return Ok(Some(Packet::Interrupt)),
+                // acknowledgments of earlier packets and line noise
+                _ => continue,
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()?.ok_or("connection closed inside packet")? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let mut sum = [0u8; 2];
+            for digit in &mut sum {
+                *digit = self.read_byte()?.ok_or("connection closed inside packet")?;
+            }
+
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                == Some(checksum(&data));
+            if !self.no_ack {
+                self.write_ack(if valid { b'+' } else { b'-' })?;
+            }
+            if valid {
+                return Ok(Some(Packet::Data(expand_runs(&data))));
+            }
+        }
+    }
+
+    fn write_ack(&mut self, ack: u8) -> Result<(), String> {
+        self.stream
+            .write_all(&[ack])
+            .and_then(|_| self.stream.flush())
+            .map_err(|e| format!("failed to send acknowledgment: {}", e))
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, String> {
+        if self.buf.is_empty() {
+            let mut chunk = [0u8; 4096];
+            let read = self
+                .stream
+                .read(&mut chunk)
+                .map_err(|e| format!("failed to read from connection: {}", e))?;
+            self.buf.extend(&chunk[..read]);
+        }
+
+        Ok(self.buf.pop_front())
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+/// Expands `x*N` into the byte repeated `N - 29` more times.
+fn expand_runs(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        match (data[i], result.last().copied(), data.get(i + 1)) {
+            (b'*', Some(last), Some(&count)) => {
+                result.extend(std::iter::repeat_n(last, count.saturating_sub(29) as usize));
+                i += 2;
+            }
+            (byte, _, _) => {
+                result.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    result
+}
+
+/// Escapes bytes of binary data which have special meaning in packets.
+pub fn escape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    for &byte in data {
+        match byte {
+            b'$' | b'#' | b'}' | b'*' => result.extend_from_slice(&[b'}', byte ^ 0x20]),
+            _ => result.push(byte),
+        }
+    }
+
+    result
+}
+
+pub fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => result.extend(bytes.next().map(|b| b ^ 0x20)),
+            _ => result.push(byte),
+        }
+    }
+
+    result
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>, String> {
+    // from_str_radix accepts a sign, so digits are checked upfront
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) || !hex.len().is_multiple_of(2) {
+        Err(format!("invalid hex string {}", hex))?;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|e| format!("failed to parse hex {}: {}", hex, e))
+        })
+        .collect()
+}
+
+pub fn parse_hex(value: &str) -> Result<u64, String> {
+    u64::from_str_radix(value, 16).map_err(|e| format!("failed to parse {}: {}", value, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    /// Stream which reads the scripted input and keeps everything written to it.
+    struct ScriptedStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for ScriptedStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for ScriptedStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn connection(input: &[u8]) -> Connection<ScriptedStream> {
+        Connection::new(ScriptedStream {
+            input: io::Cursor::new(input.to_vec()),
+            output: Vec::new(),
+        })
+    }
+
+    fn frame(data: &[u8]) -> Vec<u8> {
+        [b"$", data, format!("#{:02x}", checksum(data)).as_bytes()].concat()
+    }
+
+    fn data(packet: Option<Packet>) -> Vec<u8> {
+        match packet {
+            Some(Packet::Data(data)) => data,
+            _ => panic!("data packet expected"),
+        }
+    }
+
+    #[test]
+    fn expands_runs() {
+        // count is the printable character minus 29
+        assert_eq!(expand_runs(b"0* "), b"0000");
+        assert_eq!(expand_runs(b"a*\"b"), b"aaaaaab");
+        assert_eq!(expand_runs(b"x*~").len(), 98);
+        assert_eq!(expand_runs(b"12*!3"), b"1222223");
+        assert_eq!(expand_runs(b"plain"), b"plain");
+    }
+
+    #[test]
+    fn keeps_stars_without_run() {
+        // nothing to repeat at the start and no count at the end
+        assert_eq!(expand_runs(b"*!a"), b"*!a");
+        assert_eq!(expand_runs(b"a*"), b"a*");
+        // count below 29 repeats nothing
+        assert_eq!(expand_runs(b"a*\x10b"), b"ab");
+    }
+
+    #[test]
+    fn escapes_special_bytes() {
+        assert_eq!(escape(b"a$b#c}d*"), b"a}\x04b}\x03c}]d}\x0a");
+        assert_eq!(unescape(b"a}\x04b}\x03c}]d}\x0a"), b"a$b#c}d*");
+
+        let all: Vec<u8> = (0..=255).collect();
+        let escaped = escape(&all);
+        assert!(!escaped.iter().any(|b| matches!(b, b'$' | b'#' | b'*')));
+        assert_eq!(unescape(&escaped), all);
+    }
+
+    #[test]
+    fn drops_dangling_escape() {
+        assert_eq!(unescape(b"ab}"), b"ab");
+        assert_eq!(unescape(b""), b"");
+    }
+
+    #[test]
+    fn decodes_hex() {
+        assert_eq!(decode_hex("00ff7A").unwrap(), [0x00, 0xff, 0x7a]);
+        assert_eq!(decode_hex("").unwrap(), []);
+        assert_eq!(encode_hex(&[0x00, 0xff, 0x7a]), "00ff7a");
+
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+        assert!(decode_hex("+1").is_err());
+        // two byte character isn't split in the middle
+        assert!(decode_hex("é").is_err());
+    }
+
+    #[test]
+    fn converts_signal_numbers() {
+        // SIGSEGV has the same number, SIGUSR1 and SIGBUS don't
+        assert_eq!(linux_signal(11), Some(11));
+        assert_eq!(linux_signal(30), Some(10));
+        assert_eq!(linux_signal(10), Some(7));
+        assert_eq!(linux_signal(17), Some(19));
+        assert_eq!(linux_signal(45), Some(33));
+        assert_eq!(linux_signal(75), Some(63));
+        assert_eq!(linux_signal(77), Some(32));
+        assert_eq!(linux_signal(78), Some(64));
+        assert_eq!(linux_signal(0), None);
+        assert_eq!(linux_signal(GDB_SIGNAL_UNKNOWN), None);
+
+        for linux in 1..=64 {
+            // Linux SIGSTKFLT is unknown to GDB
+            if linux == 16 {
+                assert_eq!(gdb_signal(linux), GDB_SIGNAL_UNKNOWN);
+                continue;
+            }
+            assert_eq!(linux_signal(gdb_signal(linux)), Some(linux), "{}", linux);
+        }
+    }
+
+    #[test]
+    fn receives_packets_and_acknowledges_them() {
+        let input = [
+            &b"+"[..],
+            &frame(b"m10,4"),
+            &[INTERRUPT],
+            b"$g#00",
+            &frame(b"g"),
+        ]
+        .concat();
+        let mut connection = connection(&input);
+
+        assert_eq!(data(connection.receive().unwrap()), b"m10,4");
+        assert!(matches!(
+            connection.receive().unwrap(),
+            Some(Packet::Interrupt)
+        ));
+        // packet with wrong checksum is skipped and retransmission requested
+        assert_eq!(data(connection.receive().unwrap()), b"g");
+        assert!(connection.receive().unwrap().is_none());
+        assert_eq!(connection.stream().output, b"+-+");
+    }
+
+    #[test]
+    fn expands_runs_of_received_packet() {
+        let mut connection = connection(&frame(b"0* "));
+        connection.set_no_ack();
+
+        assert_eq!(data(connection.receive().unwrap()), b"0000");
+        assert!(connection.stream().output.is_empty());
+    }
+
+    #[test]
+    fn resends_packet_until_acknowledged() {
+        let mut acked = connection(b"-+");
+        acked.send(b"OK").unwrap();
+        assert_eq!(acked.stream().output, b"$OK#9a$OK#9a");
+
+        let mut unacked = connection(b"");
+        unacked.set_no_ack();
+        unacked.send(b"OK").unwrap();
+        assert_eq!(unacked.stream().output, b"$OK#9a");
+    }
+}
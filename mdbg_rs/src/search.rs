@@ -0,0 +1,149 @@
+use crate::linux_maps::MemoryRegion;
+
+// regions are read in pieces to keep memory usage low for big mappings
+const CHUNK_LEN: usize = 1 << 20;
+
+pub enum SearchPattern {
+    Bytes(Vec<u8>),
+    Int {
+        value: u64,
+        width: usize,
+        big_endian: bool,
+    },
+    Utf8(String),
+    Utf16(String),
+}
+
+impl SearchPattern {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            SearchPattern::Bytes(bytes) => bytes.clone(),
+            SearchPattern::Int {
+                value,
+                width,
+                big_endian: false,
+            } => value.to_le_bytes()[..*width].to_vec(),
+            SearchPattern::Int {
+                value,
+                width,
+                big_endian: true,
+            } => value.to_be_bytes()[8 - width..].to_vec(),
+            SearchPattern::Utf8(string) => string.as_bytes().to_vec(),
+            SearchPattern::Utf16(string) => string
+                .encode_utf16()
+                .flat_map(|unit| unit.to_le_bytes())
+                .collect(),
+        }
+    }
+}
+
+pub enum SearchScope {
+    All,
+    Range { start: u64, end: u64 },
+    // regions whose pathname contains the string, e.g. [heap] or libc
+    Region(String),
+}
+
+pub struct SearchMatch {
+    pub addr: u64,
+    pub region: Option<String>,
+}
+
+/// Returns the parts of readable regions which are inside the scope.
+pub fn scope_ranges(
+    regions: &[MemoryRegion],
+    scope: &SearchScope,
+) -> Vec<(u64, u64, Option<String>)> {
+    regions
+        .iter()
+        .filter(|region| region.perms.read)
+        .filter_map(|region| {
+            let (start, end) = match scope {
+                SearchScope::All => (region.start, region.end),
+                SearchScope::Range { start, end } => {
+                    (region.start.max(*start), region.end.min(*end))
+                }
+                SearchScope::Region(name) => match &region.pathname {
+                    Some(pathname) if pathname.contains(name.as_str()) => {
+                        (region.start, region.end)
+                    }
+                    _ => return None,
+                },
+            };
+
+            (start < end).then(|| (start, end, region.pathname.clone()))
+        })
+        .collect()
+}
+
+/// Scans the range with `read` and returns addresses of all pattern occurrences.
+pub fn search_range<F: Fn(u64, usize) -> Result<Vec<u8>, String>>(
+    read: F,
+    start: u64,
+    end: u64,
+    pattern: &[u8],
+) -> Result<Vec<u64>, String> {
+    let mut result = Vec::new();
+    if pattern.is_empty() {
+        return Ok(result);
+    }
+
+    let mut addr = start;
+    while addr < end {
+        // chunks overlap so matches crossing chunk border aren't lost
+        let len = CHUNK_LEN.min((end - addr) as usize) + pattern.len() - 1;
+        let chunk = read(addr, len)?;
+
+        result.extend(
+            chunk
+                .windows(pattern.len())
+                .enumerate()
+                .filter(|(_, window)| *window == pattern)
+                .map(|(offset, _)| addr + offset as u64)
+                .filter(|&match_addr| match_addr < end),
+        );
+
+        if chunk.len() < len {
+            // rest of the range isn't readable
+            break;
+        }
+        addr += (len - (pattern.len() - 1)) as u64;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(memory: &[u8], start: u64, pattern: &[u8]) -> Vec<u64> {
+        let end = start + memory.len() as u64;
+        let read = |addr: u64, len: usize| {
+            let offset = (addr - start) as usize;
+            Ok(memory[offset..(offset + len).min(memory.len())].to_vec())
+        };
+        search_range(read, start, end, pattern).unwrap()
+    }
+
+    #[test]
+    fn finds_match_crossing_chunk_border() {
+        let mut memory = vec![0u8; CHUNK_LEN * 2];
+        memory[CHUNK_LEN - 2..CHUNK_LEN + 2].copy_from_slice(b"find");
+        memory[CHUNK_LEN * 2 - 4..].copy_from_slice(b"find");
+        assert_eq!(
+            search(&memory, 0x1000, b"find"),
+            [
+                0x1000 + CHUNK_LEN as u64 - 2,
+                0x1000 + CHUNK_LEN as u64 * 2 - 4
+            ]
+        );
+    }
+
+    #[test]
+    fn match_at_chunk_start_is_reported_once() {
+        let mut memory = vec![0u8; CHUNK_LEN + 16];
+        memory[CHUNK_LEN..CHUNK_LEN + 2].copy_from_slice(&[0xde, 0xad]);
+        assert_eq!(search(&memory, 0, &[0xde, 0xad]), [CHUNK_LEN as u64]);
+    }
+}
@@ -0,0 +1,222 @@
+use std::fs;
+
+use crate::{
+    debugger::Debugger,
+    image::{self, Image},
+    linux_maps,
+    target::Target,
+};
+
+pub struct SharedLibrary<'a> {
+    pub image: Image<'a>,
+    pub load_bias: u64,
+}
+
+/// Location of the dynamic linker `r_debug` structure and of the function
+/// the dynamic linker calls every time the list of loaded objects changes.
+pub struct Rendezvous {
+    pub r_debug_addr: u64,
+    pub breakpoint_addr: u64,
+}
+
+pub struct LinkMapEntry {
+    pub path: String,
+    pub load_bias: u64,
+}
+
+pub fn find_rendezvous<T: Target + ?Sized>(
+    target: &T,
+    executable: &Image,
+) -> Result<Option<Rendezvous>, String> {
+    let interpreter_base = match get_interpreter_base(target)? {
+        Some(base) => base,
+        // statically linked program
+        None => return Ok(None),
+    };
+
+    let path = match target.memory_regions() {
+        Ok(regions) => regions
+            .into_iter()
+            .find(|region| region.contains(interpreter_base))
+            .and_then(|region| region.pathname)
+            .ok_or("dynamic linker isn't mapped")?,
+        // remote stubs without host I/O can't show the memory map
+        Err(e) => executable
+            .interpreter()
+            .ok_or(format!("failed to get path of dynamic linker: {}", e))?
+            .to_owned(),
+    };
+    let interpreter =
+        image::load(&path).map_err(|e| format!("failed to load dynamic linker {}: {}", path, e))?;
+
+    let symbol_addr = |name| {
+        interpreter
+            .find_symbol(name)
+            .map(|s| s.addr + interpreter_base)
+            .ok_or(format!("symbol {} not found in dynamic linker", name))
+    };
+
+    Ok(Some(Rendezvous {
+        r_debug_addr: symbol_addr("_r_debug")?,
+        breakpoint_addr: symbol_addr("_dl_debug_state")?,
+    }))
+}
+
+/// Reads the list of loaded objects, returns None while the dynamic linker is changing it.
+pub fn read_link_map<T: Target + ?Sized>(
+    debugger: &Debugger<T>,
+    rendezvous: &Rendezvous,
+) -> Result<Option<Vec<LinkMapEntry>>, String> {
+    const RT_CONSISTENT: u64 = 0;
+
+    // fields are pointer sized, int is padded to pointer size as well
+    let pointer_size = debugger.arch().pointer_size();
+    let field = |index: u64| index * pointer_size as u64;
+    // struct r_debug { int r_version; struct link_map *r_map; ElfW(Addr) r_brk; int r_state; ... }
+    let r_map_offset = field(1);
+    let r_state_offset = field(3);
+    // struct link_map { ElfW(Addr) l_addr; char *l_name; ElfW(Dyn) *l_ld; struct link_map *l_next; ... }
+    let l_name_offset = field(1);
+    let l_next_offset = field(3);
+
+    let read_uint = |addr, size| -> Result<u64, String> {
+        let bytes = debugger.read_bytes(addr, size)?;
+        if bytes.len() < size {
+            Err(format!("cannot access memory at {:#x}", addr))?;
+        }
+        Ok(read_le(&bytes))
+    };
+    let read_word = |addr| read_uint(addr, pointer_size);
+
+    let state = read_uint(rendezvous.r_debug_addr + r_state_offset, 4)?;
+    if state != RT_CONSISTENT {
+        return Ok(None);
+    }
+
+    let mut entries = Vec::new();
+    let mut link_map = read_word(rendezvous.r_debug_addr + r_map_offset)?;
+    while link_map != 0 {
+        let name = read_c_string(
+            |addr, len| debugger.read_bytes(addr, len),
+            read_word(link_map + l_name_offset)?,
+        )?;
+        // main program and vdso have no file to load
+        if !name.is_empty() && fs::metadata(&name).is_ok() {
+            entries.push(LinkMapEntry {
+                path: name,
+                load_bias: read_word(link_map)?,
+            });
+        }
+
+        link_map = read_word(link_map + l_next_offset)?;
+    }
+
+    Ok(Some(entries))
+}
+
+/// Reads NUL-terminated string with `read`, which may return fewer bytes than asked
+/// when the memory ends. Reads stop at page boundaries, so a string at the end
+/// of a mapping is read without touching the unmapped page after it.
+fn read_c_string<F: Fn(u64, usize) -> Result<Vec<u8>, String>>(
+    read: F,
+    mut addr: u64,
+) -> Result<String, String> {
+    // paths are at most PATH_MAX bytes long
+    const MAX_LEN: usize = 4096;
+
+    let mut bytes = Vec::new();
+    if addr == 0 {
+        return Ok(String::new());
+    }
+
+    let page_size = linux_maps::page_size();
+    while bytes.len() < MAX_LEN {
+        let len = (page_size - addr % page_size) as usize;
+        let chunk = read(addr, len)?;
+        if let Some(end) = chunk.iter().position(|&b| b == 0) {
+            bytes.extend_from_slice(&chunk[..end]);
+            return Ok(String::from_utf8_lossy(&bytes).into_owned());
+        }
+        if chunk.len() < len {
+            Err(format!(
+                "cannot access memory at {:#x}",
+                addr + chunk.len() as u64
+            ))?;
+        }
+        bytes.extend_from_slice(&chunk);
+        addr += len as u64;
+    }
+
+    Err(format!("string at {:#x} is too long", addr))
+}
+
+/// Reads little-endian unsigned integer of up to 8 bytes.
+fn read_le(bytes: &[u8]) -> u64 {
+    let mut raw = [0u8; 8];
+    raw[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(raw)
+}
+
+fn get_interpreter_base<T: Target + ?Sized>(target: &T) -> Result<Option<u64>, String> {
+    const AT_BASE: u64 = 7;
+
+    let pointer_size = target.arch().pointer_size();
+    let auxv = target.auxv()?;
+
+    // entries are pairs of pointer sized key and value
+    Ok(auxv
+        .chunks_exact(pointer_size * 2)
+        .map(|entry| {
+            (
+                read_le(&entry[..pointer_size]),
+                read_le(&entry[pointer_size..]),
+            )
+        })
+        .find(|(key, _)| *key == AT_BASE)
+        .map(|(_, value)| value)
+        .filter(|&base| base != 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // memory of two pages at 0x10000, reads past the end are cut short
+    fn read(memory: &[u8]) -> impl Fn(u64, usize) -> Result<Vec<u8>, String> + '_ {
+        move |addr, len| {
+            let start = (addr - 0x10000) as usize;
+            if start >= memory.len() {
+                Err(format!("cannot access memory at {:#x}", addr))?;
+            }
+            Ok(memory[start..(start + len).min(memory.len())].to_vec())
+        }
+    }
+
+    #[test]
+    fn reads_string_at_end_of_memory() {
+        let page_size = linux_maps::page_size() as usize;
+        let mut memory = vec![b'x'; page_size * 2];
+        memory[page_size * 2 - 5..].copy_from_slice(b"/lib\0");
+        let end = 0x10000 + page_size as u64 * 2;
+        assert_eq!(read_c_string(read(&memory), end - 5), Ok("/lib".to_owned()));
+        assert_eq!(read_c_string(read(&memory), end - 1), Ok(String::new()));
+        assert_eq!(read_c_string(read(&memory), 0), Ok(String::new()));
+    }
+
+    #[test]
+    fn reads_string_across_pages() {
+        let page_size = linux_maps::page_size() as usize;
+        let mut memory = vec![0u8; page_size * 2];
+        memory[page_size - 3..page_size + 4].copy_from_slice(b"libc.so");
+        let addr = 0x10000 + page_size as u64 - 3;
+        assert_eq!(read_c_string(read(&memory), addr), Ok("libc.so".to_owned()));
+    }
+
+    #[test]
+    fn unterminated_string_fails() {
+        let page_size = linux_maps::page_size() as usize;
+        let memory = vec![b'x'; page_size * 2];
+        assert!(read_c_string(read(&memory), 0x10000 + page_size as u64).is_err());
+        assert!(read_c_string(read(&memory), 0x10000).is_err());
+    }
+}
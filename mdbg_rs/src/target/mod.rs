@@ -0,0 +1,129 @@
+mod ptrace;
+mod remote;
+
+pub use ptrace::PtraceTarget;
+pub use remote::RemoteTarget;
+
+use crate::{arch::Arch, fpregs::FpRegs, launcher::Launcher, linux_maps::MemoryRegion};
+
+/// Why the target stopped, reported by `Target::wait`.
+#[derive(Debug)]
+pub enum TargetStop {
+    /// Breakpoint instruction trapped, PC is already moved back to the breakpoint address.
+    Breakpoint(u64),
+    /// Single step finished.
+    Step,
+    /// Program was stopped by a signal from the user or the debugger.
+    Interrupted,
+    /// Program replaced its image with the executable at the path.
+    Exec(String),
+    Exited(i32),
+    /// Program was killed by the signal.
+    Signaled(i32),
+    /// Program received the signal, it gets the signal when it's resumed.
+    Signal(i32),
+}
+
+/// Program under the debugger: a live process, a core file or a remote stub.
+pub trait Target: Send {
+    /// Architecture of the program, it may change after exec.
+    fn arch(&self) -> &'static dyn Arch;
+
+    fn pid(&self) -> i32;
+
+    /// Returns ids of threads registers can be read from.
+    fn threads(&self) -> Vec<i32> {
+        vec![self.pid()]
+    }
+
+    fn current_thread(&self) -> i32 {
+        self.pid()
+    }
+
+    fn select_thread(&mut self, tid: i32) -> Result<(), String> {
+        if tid != self.current_thread() {
+            Err(format!("thread {} isn't traced", tid))?;
+        }
+        Ok(())
+    }
+
+    /// Reads general purpose registers of the current thread as raw `NT_PRSTATUS` register set.
+    fn read_regs(&self) -> Result<Vec<u8>, String>;
+
+    fn write_regs(&mut self, regs: &[u8]) -> Result<(), String>;
+
+    fn read_fp_regs(&self) -> Result<FpRegs, String>;
+
+    fn write_fp_regs(&mut self, regs: &mut FpRegs) -> Result<(), String>;
+
+    /// Reads up to `len` bytes, fewer bytes are returned if the range runs into unmapped memory.
+    fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>, String>;
+
+    /// Writes the data and returns number of bytes written.
+    fn write_memory(&mut self, addr: u64, data: &[u8]) -> Result<usize, String>;
+
+    /// Returns memory regions like `/proc/<pid>/maps` lists them.
+    fn memory_regions(&self) -> Result<Vec<MemoryRegion>, String>;
+
+    /// Returns the auxiliary vector the program was started with.
+    fn auxv(&self) -> Result<Vec<u8>, String>;
+
+    /// Reads a file of the program's `/proc/<pid>` directory, like `stat` or `cmdline`.
+    fn read_proc_file(&self, name: &str) -> Result<Vec<u8>, String> {
+        Err(format!("target has no /proc/{}", name))
+    }
+
+    /// Returns load bias of the executable if the target knows it without the memory map.
+    fn executable_load_bias(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns output of the program which came through the target since the last call.
+    fn take_output(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Puts the trap instruction at the address and returns the program code it replaced.
+    fn insert_breakpoint(&mut self, addr: u64, instruction: &[u8]) -> Result<Vec<u8>, String> {
+        let replaced = self.read_memory(addr, instruction.len())?;
+        if replaced.len() < instruction.len()
+            || self.write_memory(addr, instruction)? < instruction.len()
+        {
+            Err(format!("cannot access memory at {:#x}", addr))?;
+        }
+
+        Ok(replaced)
+    }
+
+    fn remove_breakpoint(&mut self, addr: u64, replaced: &[u8]) -> Result<(), String> {
+        if self.write_memory(addr, replaced)? < replaced.len() {
+            Err(format!("cannot access memory at {:#x}", addr))?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the first stop after the program was launched or attached.
+    fn wait_attach(&mut self) -> Result<(), String>;
+
+    /// Sets the signal the program gets when it's resumed, None suppresses the one it stopped with.
+    fn set_pending_signal(&mut self, _signal: Option<i32>) -> Result<(), String> {
+        Err("target can't deliver signals".to_owned())
+    }
+
+    fn resume(&mut self) -> Result<(), String>;
+
+    fn step(&mut self) -> Result<(), String>;
+
+    /// Waits until the program stops after `resume` or `step`.
+    fn wait(&mut self) -> Result<TargetStop, String>;
+
+    /// Replaces the program with a new process started by the launcher.
+    fn launch(&mut self, _launcher: &Launcher) -> Result<(), String> {
+        Err("target can't launch programs".to_owned())
+    }
+
+    fn kill(&mut self) -> Result<(), String>;
+
+    /// Lets the program run without the debugger.
+    fn detach(&mut self) -> Result<(), String>;
+}
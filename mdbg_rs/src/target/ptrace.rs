@@ -0,0 +1,292 @@
+use std::{fs, os::raw::c_void};
+
+use nix::{
+    sys::{
+        ptrace,
+        signal::{self, Signal},
+        wait::{self, waitpid},
+    },
+    unistd::Pid,
+};
+
+use super::{Target, TargetStop};
+use crate::{
+    arch::{self, Arch, SI_USER},
+    breakpoint::{patch_word, word_bytes},
+    fpregs::FpRegs,
+    launcher::Launcher,
+    linux_maps::{self, MemoryRegion},
+    memory,
+    reg::{self, RegSelector},
+};
+
+const READ_CHUNK_LEN: usize = 0x10000;
+
+/// Local process traced with ptrace.
+pub struct PtraceTarget {
+    pid: i32,
+    arch: &'static dyn Arch,
+    // signal the program stopped with, it's delivered when the program is resumed
+    pending_signal: Option<Signal>,
+}
+
+impl PtraceTarget {
+    pub fn new(pid: i32, arch: &'static dyn Arch) -> PtraceTarget {
+        PtraceTarget {
+            pid,
+            arch,
+            pending_signal: None,
+        }
+    }
+
+    fn pc(&self) -> Result<u64, String> {
+        let regs = self.read_regs()?;
+        reg::get_register_value(
+            self.arch,
+            &regs,
+            &RegSelector::Name(self.arch.pc_register()),
+        )
+    }
+
+    fn set_pc(&mut self, value: u64) -> Result<(), String> {
+        let mut regs = self.read_regs()?;
+        reg::set_register_value(
+            self.arch,
+            &mut regs,
+            &RegSelector::Name(self.arch.pc_register()),
+            value,
+        )?;
+        self.write_regs(&regs)
+    }
+
+    fn wait_stop(&mut self, si_code_must_user: bool) -> Result<TargetStop, String> {
+        let pid = Pid::from_raw(self.pid);
+        let status = waitpid(pid, None).map_err(|e| format!("failed to wait pid: {}", e))?;
+
+        match status {
+            wait::WaitStatus::Stopped(_, Signal::SIGTRAP) => {
+                let siginfo =
+                    ptrace::getsiginfo(pid).map_err(|e| format!("failed to get siginfo: {}", e))?;
+
+                if si_code_must_user && siginfo.si_code != SI_USER {
+                    Err("could not attach to debugee process: wrong ci code")?;
+                }
+
+                let code = siginfo.si_code;
+                // hit breakpoint
+                if self.arch.breakpoint_trap_codes().contains(&code) {
+                    let pc = self
+                        .pc()
+                        .map_err(|e| format!("failed to get PC register value: {}", e))?;
+                    let addr = pc - self.arch.breakpoint_pc_offset();
+                    self.set_pc(addr)
+                        .map_err(|e| format!("failed to set value to PC register: {}", e))?;
+                    return Ok(TargetStop::Breakpoint(addr));
+                }
+
+                // traceme or signle step
+                if self.arch.step_trap_codes().contains(&code) {
+                    return Ok(TargetStop::Step);
+                }
+
+                Err(format!("Uknown SIGTRAP code: {}", code))
+            }
+            // debugger attached or user interrupted program
+            wait::WaitStatus::Stopped(_, Signal::SIGSTOP | Signal::SIGINT) => {
+                Ok(TargetStop::Interrupted)
+            }
+            wait::WaitStatus::PtraceEvent(_, Signal::SIGTRAP, event)
+                if event == ptrace::Event::PTRACE_EVENT_EXEC as i32 =>
+            {
+                let path = fs::read_link(format!("/proc/{}/exe", self.pid))
+                    .map_err(|e| format!("failed to read executable path: {}", e))?
+                    .to_string_lossy()
+                    .into_owned();
+                // 64-bit program may exec 32-bit one and vice versa
+                self.arch = arch::for_elf_file(&path)?;
+                Ok(TargetStop::Exec(path))
+            }
+            wait::WaitStatus::Stopped(_, signal) => {
+                self.pending_signal = Some(signal);
+                Ok(TargetStop::Signal(signal as i32))
+            }
+            wait::WaitStatus::Signaled(_, signal, _) => Ok(TargetStop::Signaled(signal as i32)),
+            wait::WaitStatus::Exited(_, status) => Ok(TargetStop::Exited(status)),
+            _ => Err(format!("Uknown signal: {:?}", status)),
+        }
+    }
+}
+
+impl Target for PtraceTarget {
+    fn arch(&self) -> &'static dyn Arch {
+        self.arch
+    }
+
+    fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    fn read_regs(&self) -> Result<Vec<u8>, String> {
+        reg::read_regset(self.pid, self.arch)
+    }
+
+    fn write_regs(&mut self, regs: &[u8]) -> Result<(), String> {
+        reg::write_regset(self.pid, &mut regs.to_vec())
+    }
+
+    fn read_fp_regs(&self) -> Result<FpRegs, String> {
+        FpRegs::read(self.pid)
+    }
+
+    fn write_fp_regs(&mut self, regs: &mut FpRegs) -> Result<(), String> {
+        regs.write(self.pid)
+    }
+
+    fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+        // buffer grows with the data read, so a huge length doesn't allocate upfront
+        let mut data = Vec::new();
+        while data.len() < len {
+            let start = data.len();
+            let chunk = READ_CHUNK_LEN.min(len - start);
+            data.resize(start + chunk, 0);
+            let read = memory::read(self.pid, addr + start as u64, &mut data[start..])?;
+            data.truncate(start + read);
+            if read < chunk {
+                break;
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn write_memory(&mut self, addr: u64, data: &[u8]) -> Result<usize, String> {
+        memory::write(self.pid, addr, data)
+    }
+
+    fn memory_regions(&self) -> Result<Vec<MemoryRegion>, String> {
+        linux_maps::read_maps(self.pid)
+    }
+
+    fn auxv(&self) -> Result<Vec<u8>, String> {
+        fs::read(format!("/proc/{}/auxv", self.pid))
+            .map_err(|e| format!("failed to read auxv of process {}: {}", self.pid, e))
+    }
+
+    fn read_proc_file(&self, name: &str) -> Result<Vec<u8>, String> {
+        let path = format!("/proc/{}/{}", self.pid, name);
+        fs::read(&path).map_err(|e| format!("failed to read {}: {}", path, e))
+    }
+
+    // the trap instruction is at most one word long, so a single ptrace word is patched
+    fn insert_breakpoint(&mut self, addr: u64, instruction: &[u8]) -> Result<Vec<u8>, String> {
+        let word = ptrace::read(Pid::from_raw(self.pid), addr as *mut c_void)
+            .map_err(|e| format!("failed to peek instruction: {}", e))?;
+        self.poke(addr, patch_word(word, instruction))?;
+
+        Ok(word_bytes(word, instruction.len()))
+    }
+
+    fn remove_breakpoint(&mut self, addr: u64, replaced: &[u8]) -> Result<(), String> {
+        let word = ptrace::read(Pid::from_raw(self.pid), addr as *mut c_void)
+            .map_err(|e| format!("failed to peek instruction: {}", e))?;
+        self.poke(addr, patch_word(word, replaced))
+    }
+
+    fn wait_attach(&mut self) -> Result<(), String> {
+        self.wait_stop(true)?;
+
+        ptrace::setoptions(Pid::from_raw(self.pid), ptrace::Options::PTRACE_O_TRACEEXEC)
+            .map_err(|e| format!("failed to set ptrace options: {}", e))
+    }
+
+    fn set_pending_signal(&mut self, signal: Option<i32>) -> Result<(), String> {
+        self.pending_signal = signal
+            .map(Signal::try_from)
+            .transpose()
+            .map_err(|e| format!("invalid signal: {}", e))?;
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), String> {
+        ptrace::cont(Pid::from_raw(self.pid), self.pending_signal.take())
+            .map_err(|e| format!("failed to continue program: {}", e))
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        ptrace::step(Pid::from_raw(self.pid), self.pending_signal.take())
+            .map_err(|e| format!("failed to single step program: {}", e))
+    }
+
+    fn wait(&mut self) -> Result<TargetStop, String> {
+        self.wait_stop(false)
+    }
+
+    fn launch(&mut self, launcher: &Launcher) -> Result<(), String> {
+        self.pid = launcher.launch()?;
+        self.pending_signal = None;
+        self.arch = arch::for_elf_file(launcher.program_path())?;
+        Ok(())
+    }
+
+    fn kill(&mut self) -> Result<(), String> {
+        let pid = Pid::from_raw(self.pid);
+        signal::kill(pid, Signal::SIGKILL).map_err(|e| format!("failed to send SIGKILL: {}", e))?;
+        waitpid(pid, None).map_err(|e| format!("failed to wait pid: {}", e))?;
+        self.pending_signal = None;
+        Ok(())
+    }
+
+    fn detach(&mut self) -> Result<(), String> {
+        ptrace::detach(Pid::from_raw(self.pid), self.pending_signal.take())
+            .map_err(|e| format!("failed to detach from process: {}", e))
+    }
+}
+
+impl PtraceTarget {
+    fn poke(&mut self, addr: u64, word: i64) -> Result<(), String> {
+        // SAFETY: addr pointer come outside the program. word isn't pointer, it's a data.
+        unsafe {
+            ptrace::write(
+                Pid::from_raw(self.pid),
+                addr as *mut c_void,
+                word as *mut c_void,
+            )
+            .map_err(|e| format!("failed to poke breakpoint instruction: {}", e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // shell sends SIGSEGV to itself and exits normally if the signal is suppressed
+    fn launch_crashing() -> PtraceTarget {
+        let launcher = Launcher::new("/bin/sh").args(["-c".to_owned(), "kill -SEGV $$".to_owned()]);
+        let mut target = PtraceTarget::new(0, arch::host());
+        target.launch(&launcher).unwrap();
+        target.wait_attach().unwrap();
+        target
+    }
+
+    #[test]
+    fn stop_signal_is_delivered_on_resume() {
+        let mut target = launch_crashing();
+
+        target.resume().unwrap();
+        assert!(matches!(target.wait().unwrap(), TargetStop::Signal(11)));
+        target.resume().unwrap();
+        assert!(matches!(target.wait().unwrap(), TargetStop::Signaled(11)));
+    }
+
+    #[test]
+    fn suppressed_signal_isnt_delivered() {
+        let mut target = launch_crashing();
+
+        target.resume().unwrap();
+        assert!(matches!(target.wait().unwrap(), TargetStop::Signal(11)));
+        target.set_pending_signal(None).unwrap();
+        target.resume().unwrap();
+        assert!(matches!(target.wait().unwrap(), TargetStop::Exited(0)));
+    }
+}
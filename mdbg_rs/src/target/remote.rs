@@ -0,0 +1,798 @@
+use std::{cell::RefCell, collections::HashMap, net::TcpStream};
+
+use super::{Target, TargetStop};
+use crate::{
+    arch::Arch,
+    fpregs::FpRegs,
+    linux_maps::{self, MemoryRegion},
+    reg::{self, RegSelector},
+    rsp::{self, Connection, Packet},
+};
+
+// GDB numbers of signals which stop the program for the debugger, see `rsp::linux_signal`
+const GDB_SIGINT: u8 = 2;
+const GDB_SIGTRAP: u8 = 5;
+const GDB_SIGSTOP: u8 = 17;
+
+const FXSAVE_LEN: usize = 512;
+const FTAG_OFFSET: usize = 4;
+
+// used until the stub tells its own packet size
+const DEFAULT_PACKET_SIZE: usize = 0x400;
+// vFile:pread returns up to this many bytes of a remote file
+const FILE_CHUNK_LEN: usize = 0x1000;
+
+/// Register from the target description, `offset` is its position in the `g` packet.
+struct RemoteRegister {
+    name: String,
+    regnum: usize,
+    offset: usize,
+    size: usize,
+}
+
+/// Breakpoint inserted by the target.
+struct InsertedBreakpoint {
+    len: usize,
+    // inserted with Z0, otherwise the trap was written to memory
+    z0: bool,
+    // trap is in the program memory, Z0 breakpoint gets it when the debugger writes code under it
+    in_memory: bool,
+}
+
+/// Program controlled by a GDB Remote Serial Protocol stub like gdbserver or QEMU gdbstub.
+pub struct RemoteTarget {
+    arch: &'static dyn Arch,
+    pid: i32,
+    // reading methods take &self, but every request is a round trip on the connection
+    connection: RefCell<Connection<TcpStream>>,
+    registers: Vec<RemoteRegister>,
+    packet_size: usize,
+    vcont: bool,
+    // Z0 keeps breakpoints out of memory reads, memory is patched if the stub lacks it
+    z0: bool,
+    breakpoints: HashMap<u64, InsertedBreakpoint>,
+    stepping: bool,
+    // GDB number of the signal the program stopped with, it's passed on when resumed
+    pending_signal: Option<u8>,
+    // `O` packets received while the program ran
+    output: RefCell<Vec<u8>>,
+}
+
+impl RemoteTarget {
+    /// Connects to the stub at `host:port` and reads the registers it describes.
+    pub fn connect(address: &str, arch: &'static dyn Arch) -> Result<RemoteTarget, String> {
+        let stream = TcpStream::connect(address)
+            .map_err(|e| format!("failed to connect to {}: {}", address, e))?;
+        let mut target = RemoteTarget::new(stream, arch);
+
+        // gdbserver describes x86 registers only to clients which ask for it
+        let features = target.request_str("qSupported:swbreak+;xmlRegisters=i386")?;
+        let mut descriptions = false;
+        for feature in features.split(';') {
+            match feature.split_once('=') {
+                Some(("PacketSize", size)) => target.packet_size = rsp::parse_hex(size)? as usize,
+                _ if feature == "QStartNoAckMode+"
+                    && target.request_str("QStartNoAckMode")? == "OK" =>
+                {
+                    target.connection.borrow_mut().set_no_ack();
+                }
+                _ if feature == "qXfer:features:read+" => descriptions = true,
+                _ => (),
+            }
+        }
+        if !descriptions {
+            Err("remote stub doesn't describe its registers")?;
+        }
+
+        let vcont = target.request_str("vCont?")?;
+        target.vcont = vcont.contains(";c") && vcont.contains(";s");
+        target.registers = target.read_target_description()?;
+
+        let stop = target.request_str("?")?;
+        if stop.starts_with('W') || stop.starts_with('X') {
+            Err("remote program isn't running")?;
+        }
+        // multiprocess stubs reply with QCp<pid>.<tid>
+        let thread = target.request_str("qC")?;
+        if let Some(id) = thread.strip_prefix("QC") {
+            let id = id.trim_start_matches('p');
+            target.pid = rsp::parse_hex(id.split('.').next().unwrap_or(id))? as i32;
+        }
+
+        Ok(target)
+    }
+
+    fn new(stream: TcpStream, arch: &'static dyn Arch) -> RemoteTarget {
+        RemoteTarget {
+            arch,
+            pid: 0,
+            connection: RefCell::new(Connection::new(stream)),
+            registers: Vec::new(),
+            packet_size: DEFAULT_PACKET_SIZE,
+            vcont: false,
+            z0: true,
+            breakpoints: HashMap::new(),
+            stepping: false,
+            pending_signal: None,
+            output: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn request(&self, packet: &str) -> Result<Vec<u8>, String> {
+        let mut connection = self.connection.borrow_mut();
+        connection.send(packet.as_bytes())?;
+        loop {
+            match connection.receive()? {
+                Some(Packet::Data(data)) => return Ok(data),
+                Some(Packet::Interrupt) => (),
+                None => Err("remote stub closed the connection")?,
+            }
+        }
+    }
+
+    fn request_str(&self, packet: &str) -> Result<String, String> {
+        Ok(String::from_utf8_lossy(&self.request(packet)?).into_owned())
+    }
+
+    fn request_ok(&self, packet: &str) -> Result<(), String> {
+        match self.request_str(packet)?.as_str() {
+            "OK" => Ok(()),
+            "" => Err(format!("remote stub doesn't support {}", packet)),
+            reply => Err(format!("remote stub replied {} to {}", reply, packet)),
+        }
+    }
+
+    /// Reads the whole object with `qXfer`, like `features` or `auxv`.
+    fn read_xfer(&self, object: &str, annex: &str) -> Result<Vec<u8>, String> {
+        let chunk = self.packet_size / 2;
+        let mut data = Vec::new();
+        loop {
+            let reply = self.request(&format!(
+                "qXfer:{}:read:{}:{:x},{:x}",
+                object,
+                annex,
+                data.len(),
+                chunk
+            ))?;
+            match reply.split_first() {
+                Some((b'm', rest)) => data.extend(rsp::unescape(rest)),
+                Some((b'l', rest)) => {
+                    data.extend(rsp::unescape(rest));
+                    return Ok(data);
+                }
+                _ => Err(format!(
+                    "failed to read {} {}: {}",
+                    object,
+                    annex,
+                    String::from_utf8_lossy(&reply)
+                ))?,
+            }
+        }
+    }
+
+    /// Returns registers of `target.xml` and the files it includes sorted by regnum.
+    fn read_target_description(&self) -> Result<Vec<RemoteRegister>, String> {
+        let mut registers = Vec::new();
+        self.read_features("target.xml", &mut registers)?;
+
+        registers.sort_by_key(|r| r.regnum);
+        let mut offset = 0;
+        for register in &mut registers {
+            register.offset = offset;
+            offset += register.size;
+        }
+
+        Ok(registers)
+    }
+
+    fn read_features(
+        &self,
+        annex: &str,
+        registers: &mut Vec<RemoteRegister>,
+    ) -> Result<(), String> {
+        let xml = self.read_xfer("features", annex)?;
+        let xml = String::from_utf8_lossy(&xml);
+
+        for tag in xml.split('<').skip(1) {
+            let tag = tag.split('>').next().unwrap_or_default();
+            if tag.starts_with("xi:include") {
+                let href = attribute(tag, "href").ok_or("include without href")?;
+                self.read_features(href, registers)?;
+            } else if tag.starts_with("reg ") {
+                let name = attribute(tag, "name").ok_or("register without name")?;
+                let bits = attribute(tag, "bitsize").ok_or("register without bitsize")?;
+                // registers are numbered one after another unless regnum says otherwise
+                let regnum = match attribute(tag, "regnum") {
+                    Some(regnum) => regnum
+                        .parse()
+                        .map_err(|e| format!("failed to parse regnum {}: {}", regnum, e))?,
+                    None => registers.last().map_or(0, |r| r.regnum + 1),
+                };
+                registers.push(RemoteRegister {
+                    name: name.to_owned(),
+                    regnum,
+                    offset: 0,
+                    size: bits
+                        .parse::<usize>()
+                        .map_err(|e| format!("failed to parse bitsize {}: {}", bits, e))?
+                        / 8,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn remote_register(&self, name: &str) -> Option<&RemoteRegister> {
+        // gdb names of registers which the kernel calls differently
+        let name = match name {
+            "fsbase" => "fs_base",
+            "gsbase" => "gs_base",
+            "pstate" => "cpsr",
+            name => name,
+        };
+        self.registers.iter().find(|r| r.name == name)
+    }
+
+    /// Reads the `g` packet, registers the stub can't read are zero.
+    fn read_all_registers(&self) -> Result<Vec<u8>, String> {
+        let reply = self.request_str("g")?;
+        if reply.starts_with('E') {
+            Err(format!("failed to read registers: {}", reply))?;
+        }
+        rsp::decode_hex(&reply.replace('x', "0"))
+    }
+
+    fn pc(&self) -> Result<u64, String> {
+        reg::get_register_value(
+            self.arch,
+            &self.read_regs()?,
+            &RegSelector::Name(self.arch.pc_register()),
+        )
+    }
+
+    fn set_pc(&mut self, value: u64) -> Result<(), String> {
+        let mut regs = self.read_regs()?;
+        reg::set_register_value(
+            self.arch,
+            &mut regs,
+            &RegSelector::Name(self.arch.pc_register()),
+            value,
+        )?;
+        self.write_regs(&regs)
+    }
+
+    /// Reads a file on the machine the stub runs on.
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, String> {
+        let reply = self.request(&format!(
+            "vFile:open:{},0,0",
+            rsp::encode_hex(path.as_bytes())
+        ))?;
+        let (fd, _) = file_reply(&reply).map_err(|e| format!("failed to open {}: {}", path, e))?;
+
+        let mut data = Vec::new();
+        let result = loop {
+            let reply = self.request(&format!(
+                "vFile:pread:{:x},{:x},{:x}",
+                fd,
+                FILE_CHUNK_LEN,
+                data.len()
+            ))?;
+            match file_reply(&reply) {
+                Ok((0, _)) => break Ok(data),
+                Ok((_, chunk)) => data.extend(rsp::unescape(chunk)),
+                Err(e) => break Err(format!("failed to read {}: {}", path, e)),
+            }
+        };
+        self.request(&format!("vFile:close:{:x}", fd))?;
+
+        result
+    }
+}
+
+impl Target for RemoteTarget {
+    fn arch(&self) -> &'static dyn Arch {
+        self.arch
+    }
+
+    fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    fn read_regs(&self) -> Result<Vec<u8>, String> {
+        let all = self.read_all_registers()?;
+        let mut regs = vec![0; self.arch.regset_size()];
+        for info in self.arch.registers() {
+            let remote = match self.remote_register(info.name) {
+                Some(remote) => remote,
+                None => continue,
+            };
+            let len = info.size.min(remote.size);
+            if let Some(value) = all.get(remote.offset..remote.offset + len) {
+                regs[info.offset..info.offset + len].copy_from_slice(value);
+            }
+        }
+
+        Ok(regs)
+    }
+
+    fn write_regs(&mut self, regs: &[u8]) -> Result<(), String> {
+        let mut all = self.read_all_registers()?;
+        for info in self.arch.registers() {
+            let remote = match self.remote_register(info.name) {
+                Some(remote) => remote,
+                None => continue,
+            };
+            let len = info.size.min(remote.size);
+            if let Some(value) = all.get_mut(remote.offset..remote.offset + len) {
+                value.copy_from_slice(&regs[info.offset..info.offset + len]);
+            }
+        }
+
+        self.request_ok(&format!("G{}", rsp::encode_hex(&all)))
+    }
+
+    fn read_fp_regs(&self) -> Result<FpRegs, String> {
+        let all = self.read_all_registers()?;
+        let mut fxsave = vec![0; FXSAVE_LEN];
+        for remote in &self.registers {
+            let value = all
+                .get(remote.offset..remote.offset + remote.size)
+                .ok_or("g packet is too short")?;
+            if remote.name == "ftag" {
+                fxsave[FTAG_OFFSET] = abridged_tag(value);
+            } else if let Some((offset, len)) = fxsave_layout(&remote.name) {
+                let len = len.min(remote.size);
+                fxsave[offset..offset + len].copy_from_slice(&value[..len]);
+            }
+        }
+
+        FpRegs::from_fxsave(fxsave)
+    }
+
+    fn write_fp_regs(&mut self, regs: &mut FpRegs) -> Result<(), String> {
+        let fxsave = regs.fxsave_area();
+        for remote in &self.registers {
+            let mut value = if remote.name == "ftag" {
+                full_tag(fxsave[FTAG_OFFSET]).to_le_bytes().to_vec()
+            } else if let Some((offset, len)) = fxsave_layout(&remote.name) {
+                fxsave[offset..offset + len].to_vec()
+            } else {
+                continue;
+            };
+            value.resize(remote.size, 0);
+            self.request_ok(&format!("P{:x}={}", remote.regnum, rsp::encode_hex(&value)))?;
+        }
+
+        Ok(())
+    }
+
+    fn read_memory(&self, addr: u64, len: usize) -> Result<Vec<u8>, String> {
+        let chunk_len = self.packet_size / 2;
+        let mut data = Vec::new();
+        while data.len() < len {
+            let chunk = chunk_len.min(len - data.len());
+            let reply =
+                self.request_str(&format!("m{:x},{:x}", addr + data.len() as u64, chunk))?;
+            // error reply means the memory can't be accessed
+            if reply.is_empty() || reply.starts_with('E') {
+                break;
+            }
+
+            let bytes = rsp::decode_hex(&reply)?;
+            data.extend_from_slice(&bytes);
+            if bytes.len() < chunk {
+                break;
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn write_memory(&mut self, addr: u64, data: &[u8]) -> Result<usize, String> {
+        // room for the command and the address
+        let chunk_len = self.packet_size.saturating_sub(32) / 2;
+        let mut written = 0;
+        for chunk in data.chunks(chunk_len.max(1)) {
+            let reply = self.request_str(&format!(
+                "M{:x},{:x}:{}",
+                addr + written as u64,
+                chunk.len(),
+                rsp::encode_hex(chunk)
+            ))?;
+            if reply != "OK" {
+                break;
+            }
+            written += chunk.len();
+        }
+
+        let end = addr + written as u64;
+        for (bp_addr, bp) in &mut self.breakpoints {
+            if *bp_addr < end && bp_addr + bp.len as u64 > addr {
+                bp.in_memory = true;
+            }
+        }
+
+        Ok(written)
+    }
+
+    fn memory_regions(&self) -> Result<Vec<MemoryRegion>, String> {
+        let maps = self.read_file(&format!("/proc/{}/maps", self.pid))?;
+        linux_maps::parse_maps(&String::from_utf8_lossy(&maps))
+    }
+
+    fn auxv(&self) -> Result<Vec<u8>, String> {
+        self.read_xfer("auxv", "")
+    }
+
+    // files of the stub host, where the program runs
+    fn read_proc_file(&self, name: &str) -> Result<Vec<u8>, String> {
+        self.read_file(&format!("/proc/{}/{}", self.pid, name))
+    }
+
+    fn executable_load_bias(&self) -> Option<u64> {
+        text_offset(&self.request_str("qOffsets").ok()?)
+    }
+
+    fn take_output(&self) -> Vec<u8> {
+        self.output.take()
+    }
+
+    fn insert_breakpoint(&mut self, addr: u64, instruction: &[u8]) -> Result<Vec<u8>, String> {
+        let replaced = self.read_memory(addr, instruction.len())?;
+        if replaced.len() < instruction.len() {
+            Err(format!("cannot access memory at {:#x}", addr))?;
+        }
+
+        if self.z0 {
+            match self
+                .request_str(&format!("Z0,{:x},{:x}", addr, instruction.len()))?
+                .as_str()
+            {
+                "OK" => {
+                    let breakpoint = InsertedBreakpoint {
+                        len: instruction.len(),
+                        z0: true,
+                        in_memory: false,
+                    };
+                    self.breakpoints.insert(addr, breakpoint);
+                    return Ok(replaced);
+                }
+                "" => self.z0 = false,
+                reply => Err(format!("failed to insert breakpoint: {}", reply))?,
+            }
+        }
+
+        if self.write_memory(addr, instruction)? < instruction.len() {
+            Err(format!("cannot access memory at {:#x}", addr))?;
+        }
+        let breakpoint = InsertedBreakpoint {
+            len: instruction.len(),
+            z0: false,
+            in_memory: true,
+        };
+        self.breakpoints.insert(addr, breakpoint);
+        Ok(replaced)
+    }
+
+    fn remove_breakpoint(&mut self, addr: u64, replaced: &[u8]) -> Result<(), String> {
+        let (z0, in_memory) = self
+            .breakpoints
+            .remove(&addr)
+            .map_or((false, true), |bp| (bp.z0, bp.in_memory));
+        if z0 {
+            self.request_ok(&format!("z0,{:x},{:x}", addr, replaced.len()))?;
+        }
+        // stubs like QEMU gdbstub may not write program code, that's what Z0 is for
+        if in_memory && self.write_memory(addr, replaced)? < replaced.len() {
+            Err(format!("cannot access memory at {:#x}", addr))?;
+        }
+        Ok(())
+    }
+
+    // the stub reports the program stopped as soon as it's connected
+    fn wait_attach(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn set_pending_signal(&mut self, signal: Option<i32>) -> Result<(), String> {
+        self.pending_signal = signal.map(rsp::gdb_signal);
+        Ok(())
+    }
+
+    fn resume(&mut self) -> Result<(), String> {
+        self.stepping = false;
+        let packet = self.resume_packet('c');
+        self.connection.borrow_mut().send(packet.as_bytes())
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        self.stepping = true;
+        let packet = self.resume_packet('s');
+        self.connection.borrow_mut().send(packet.as_bytes())
+    }
+
+    fn wait(&mut self) -> Result<TargetStop, String> {
+        loop {
+            let reply = match self.connection.borrow_mut().receive()? {
+                Some(Packet::Data(data)) => String::from_utf8_lossy(&data).into_owned(),
+                Some(Packet::Interrupt) => continue,
+                None => Err("remote stub closed the connection")?,
+            };
+            // exit status and signal number go first, details like process id follow
+            let code = || rsp::parse_hex(reply.get(1..3).unwrap_or_default());
+
+            match reply.chars().next() {
+                // console output of the program
+                Some('O') => {
+                    let output = rsp::decode_hex(&reply[1..])?;
+                    self.output.borrow_mut().extend(output);
+                }
+                Some('W') => return Ok(TargetStop::Exited(code()? as i32)),
+                Some('X') => {
+                    let signal = code()? as u8;
+                    return Ok(TargetStop::Signaled(
+                        rsp::linux_signal(signal).unwrap_or(signal as i32),
+                    ));
+                }
+                Some('S' | 'T') => return self.classify_stop(code()? as u8, &reply),
+                _ => Err(format!("unexpected stop reply {}", reply))?,
+            }
+        }
+    }
+
+    fn kill(&mut self) -> Result<(), String> {
+        // stub doesn't reply to kill
+        self.connection.borrow_mut().send(b"k")
+    }
+
+    fn detach(&mut self) -> Result<(), String> {
+        self.request_ok("D")
+    }
+}
+
+impl RemoteTarget {
+    fn classify_stop(&mut self, signal: u8, reply: &str) -> Result<TargetStop, String> {
+        match signal {
+            GDB_SIGTRAP if reply.contains("swbreak:") => Ok(TargetStop::Breakpoint(self.pc()?)),
+            GDB_SIGTRAP if self.stepping => Ok(TargetStop::Step),
+            GDB_SIGTRAP => {
+                let pc = self.pc()?;
+                // stubs without swbreak may leave PC after the trap instruction
+                let trap_addr = pc - self.arch.breakpoint_pc_offset();
+                if self.breakpoints.contains_key(&pc) {
+                    Ok(TargetStop::Breakpoint(pc))
+                } else if self.breakpoints.contains_key(&trap_addr) {
+                    self.set_pc(trap_addr)?;
+                    Ok(TargetStop::Breakpoint(trap_addr))
+                } else {
+                    Ok(TargetStop::Interrupted)
+                }
+            }
+            GDB_SIGINT | GDB_SIGSTOP => Ok(TargetStop::Interrupted),
+            signal => {
+                let linux = rsp::linux_signal(signal)
+                    .ok_or(format!("program received unknown signal {}", signal))?;
+                self.pending_signal = Some(signal);
+                Ok(TargetStop::Signal(linux))
+            }
+        }
+    }
+
+    /// Returns `c` or `s` packet which delivers the pending signal, like `vCont;C0b`.
+    fn resume_packet(&mut self, action: char) -> String {
+        let action = match self.pending_signal.take() {
+            Some(signal) => format!("{}{:02x}", action.to_ascii_uppercase(), signal),
+            None => action.to_string(),
+        };
+        if self.vcont {
+            format!("vCont;{}", action)
+        } else {
+            action
+        }
+    }
+}
+
+/// Returns offset and length of the x87 or SSE register in the FXSAVE area.
+fn fxsave_layout(name: &str) -> Option<(usize, usize)> {
+    match name {
+        "fctrl" => return Some((0, 2)),
+        "fstat" => return Some((2, 2)),
+        "fop" => return Some((6, 2)),
+        "fioff" => return Some((8, 4)),
+        "fiseg" => return Some((12, 2)),
+        "fooff" => return Some((16, 4)),
+        "foseg" => return Some((20, 2)),
+        "mxcsr" => return Some((24, 4)),
+        _ => (),
+    }
+
+    let (index, base, len) = match (name.strip_prefix("st"), name.strip_prefix("xmm")) {
+        (Some(index), _) => (index, 32, 10),
+        (_, Some(index)) => (index, 160, 16),
+        _ => return None,
+    };
+    let index: usize = index.parse().ok()?;
+    (index < 16).then_some((base + index * 16, len))
+}
+
+/// Converts full x87 tag word into FXSAVE one with a bit set for every non-empty register.
+fn abridged_tag(value: &[u8]) -> u8 {
+    let tag = u16::from_le_bytes([value[0], *value.get(1).unwrap_or(&0)]);
+    (0..8)
+        .filter(|i| (tag >> (i * 2)) & 0b11 != 0b11)
+        .fold(0, |abridged, i| abridged | 1 << i)
+}
+
+/// Converts FXSAVE tag into full x87 one, non-empty registers are reported as valid.
+fn full_tag(abridged: u8) -> u16 {
+    (0..8)
+        .filter(|i| abridged & 1 << i == 0)
+        .fold(0, |tag, i| tag | 0b11 << (i * 2))
+}
+
+/// Parses `F result[,errno][;attachment]` reply of a host I/O request.
+fn file_reply(reply: &[u8]) -> Result<(u64, &[u8]), String> {
+    let reply = reply
+        .strip_prefix(b"F")
+        .ok_or("remote stub doesn't support file access")?;
+    let (result, attachment) = match reply.iter().position(|&b| b == b';') {
+        Some(end) => (&reply[..end], &reply[end + 1..]),
+        None => (reply, &[][..]),
+    };
+
+    let result = String::from_utf8_lossy(result);
+    if result.starts_with('-') {
+        Err(format!("remote error {}", result))?;
+    }
+    Ok((rsp::parse_hex(&result)?, attachment))
+}
+
+/// Parses `Text=xxx;Data=yyy` or `TextSeg=xxx` reply to `qOffsets`, None if it's unsupported.
+fn text_offset(reply: &str) -> Option<u64> {
+    reply
+        .split(';')
+        .filter_map(|field| field.split_once('='))
+        .find(|(name, _)| matches!(*name, "Text" | "TextSeg"))
+        .and_then(|(_, offset)| rsp::parse_hex(offset).ok())
+}
+
+/// Returns value of the XML attribute in the tag.
+fn attribute<'t>(tag: &'t str, name: &str) -> Option<&'t str> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    #[test]
+    fn parses_file_replies() {
+        assert_eq!(file_reply(b"F5").unwrap(), (5, &b""[..]));
+        assert_eq!(file_reply(b"F3;a}]b").unwrap(), (3, &b"a}]b"[..]));
+        // attachment is binary and may contain `;`
+        assert_eq!(file_reply(b"F2;;x").unwrap(), (2, &b";x"[..]));
+        assert_eq!(file_reply(b"F0;").unwrap(), (0, &b""[..]));
+
+        assert!(file_reply(b"F-1,2").is_err());
+        assert!(file_reply(b"").is_err());
+        assert!(file_reply(b"E01").is_err());
+    }
+
+    #[test]
+    fn reads_xml_attributes() {
+        let tag = r#"reg name="rip" bitsize="64" type="code_ptr" regnum="16"/"#;
+
+        assert_eq!(attribute(tag, "name"), Some("rip"));
+        assert_eq!(attribute(tag, "bitsize"), Some("64"));
+        assert_eq!(attribute(tag, "regnum"), Some("16"));
+        assert_eq!(attribute(tag, "group"), None);
+        assert_eq!(
+            attribute(r#"xi:include href="64bit-core.xml"/"#, "href"),
+            Some("64bit-core.xml")
+        );
+        assert_eq!(attribute(r#"reg name="rip"#, "name"), None);
+    }
+
+    #[test]
+    fn converts_tag_words() {
+        // all empty, all valid and st0 and st7 used
+        assert_eq!(abridged_tag(&[0xff, 0xff]), 0);
+        assert_eq!(abridged_tag(&[0, 0]), 0xff);
+        assert_eq!(abridged_tag(&[0xfc, 0x3f]), 0x81);
+        // st0 holding zero and st1 holding a special value are in use too
+        assert_eq!(abridged_tag(&[0xf9, 0xff]), 0x03);
+
+        assert_eq!(full_tag(0), 0xffff);
+        assert_eq!(full_tag(0xff), 0);
+        assert_eq!(full_tag(0x81), 0x3ffc);
+        for abridged in 0..=255 {
+            assert_eq!(abridged_tag(&full_tag(abridged).to_le_bytes()), abridged);
+        }
+    }
+
+    #[test]
+    fn maps_registers_to_fxsave() {
+        assert_eq!(fxsave_layout("fctrl"), Some((0, 2)));
+        assert_eq!(fxsave_layout("mxcsr"), Some((24, 4)));
+        assert_eq!(fxsave_layout("st0"), Some((32, 10)));
+        assert_eq!(fxsave_layout("st7"), Some((144, 10)));
+        assert_eq!(fxsave_layout("xmm0"), Some((160, 16)));
+        assert_eq!(fxsave_layout("xmm15"), Some((400, 16)));
+
+        assert_eq!(fxsave_layout("xmm16"), None);
+        assert_eq!(fxsave_layout("ymm0"), None);
+        assert_eq!(fxsave_layout("rax"), None);
+        assert_eq!(fxsave_layout("ftag"), None);
+    }
+
+    #[test]
+    fn parses_section_offsets() {
+        assert_eq!(text_offset("Text=1000;Data=1000;Bss=1000"), Some(0x1000));
+        assert_eq!(text_offset("TextSeg=555555554000"), Some(0x555555554000));
+        assert_eq!(text_offset(""), None);
+        assert_eq!(text_offset("E01"), None);
+    }
+
+    /// Starts a stub which supports Z0 and memory writes if they are allowed,
+    /// it returns the packets it got.
+    fn stub(writes: bool) -> (RemoteTarget, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let stub = thread::spawn(move || {
+            let mut connection = Connection::new(listener.accept().unwrap().0);
+            let mut packets = Vec::new();
+            while let Ok(Some(Packet::Data(data))) = connection.receive() {
+                let packet = String::from_utf8_lossy(&data).into_owned();
+                let reply = match packet.chars().next() {
+                    Some('m') => "90",
+                    Some('Z' | 'z') => "OK",
+                    Some('M') if writes => "OK",
+                    _ => "E01",
+                };
+                connection.send(reply.as_bytes()).unwrap();
+                packets.push(packet);
+            }
+            packets
+        });
+
+        (RemoteTarget::new(stream, crate::arch::host()), stub)
+    }
+
+    #[test]
+    fn removes_z0_breakpoint_without_writing_memory() {
+        let (mut target, stub) = stub(false);
+
+        let replaced = target.insert_breakpoint(0x1000, &[0xcc]).unwrap();
+        assert_eq!(replaced, [0x90]);
+        // failed write leaves the trap out of memory
+        assert_eq!(target.write_memory(0x1000, &[0x91]).unwrap(), 0);
+        target.remove_breakpoint(0x1000, &replaced).unwrap();
+
+        drop(target);
+        let packets = stub.join().unwrap();
+        assert_eq!(packets[1..], ["Z0,1000,1", "M1000,1:91", "z0,1000,1"]);
+    }
+
+    #[test]
+    fn restores_code_written_under_z0_breakpoint() {
+        let (mut target, stub) = stub(true);
+
+        let replaced = target.insert_breakpoint(0x1000, &[0xcc]).unwrap();
+        // debugger keeps the trap in the code it writes
+        assert_eq!(target.write_memory(0xfff, &[0x91, 0xcc]).unwrap(), 2);
+        target.remove_breakpoint(0x1000, &[0x92]).unwrap();
+
+        drop(target);
+        let packets = stub.join().unwrap();
+        assert_eq!(
+            packets[1..],
+            ["Z0,1000,1", "Mfff,2:91cc", "z0,1000,1", "M1000,1:92"]
+        );
+        assert_eq!(replaced, [0x90]);
+    }
+}
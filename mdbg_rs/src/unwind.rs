@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use gimli::{
+    BaseAddresses, CfaRule, EhFrame, EndianSlice, RegisterRule, RunTimeEndian, UnwindContext,
+    UnwindSection,
+};
+use object::{Object, ObjectSection};
+
+use crate::{debugger::Debugger, reg::RegSelector, target::Target};
+
+// keeps a corrupted stack from being walked forever
+const MAX_FRAMES: usize = 256;
+
+/// Function call on the stack. `cfa` is the stack pointer value before the call,
+/// it tells frames of recursive calls apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub pc: u64,
+    pub cfa: u64,
+}
+
+/// `.eh_frame` section of an image, it describes how to find the caller of every function.
+pub struct CallFrames<'a> {
+    eh_frame: EhFrame<EndianSlice<'a, RunTimeEndian>>,
+    bases: BaseAddresses,
+}
+
+/// How to find the caller of the function at some address.
+struct UnwindRule {
+    cfa_register: u16,
+    cfa_offset: i64,
+    // offsets from CFA registers of the caller are saved at, None if the register is unchanged
+    return_address: Option<i64>,
+    frame_pointer: Option<i64>,
+    return_address_register: u16,
+}
+
+impl<'a> CallFrames<'a> {
+    pub fn load(object: &object::File<'a>, pointer_size: usize) -> Option<CallFrames<'a>> {
+        let section = object.section_by_name(".eh_frame")?;
+        let endian = if object.is_little_endian() {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+
+        let mut eh_frame = EhFrame::new(section.data().ok()?, endian);
+        eh_frame.set_address_size(pointer_size as u8);
+        // pointers in CIEs and FDEs may be relative to these sections
+        let mut bases = BaseAddresses::default().set_eh_frame(section.address());
+        if let Some(text) = object.section_by_name(".text") {
+            bases = bases.set_text(text.address());
+        }
+        if let Some(got) = object.section_by_name(".got") {
+            bases = bases.set_got(got.address());
+        }
+
+        Some(CallFrames { eh_frame, bases })
+    }
+
+    /// Returns the rule for the file address, None if the function has no call frame info
+    /// or its CFA can't be computed from a register.
+    fn rule(&self, addr: u64, stack_pointer: u16, frame_pointer: u16) -> Option<UnwindRule> {
+        let fde = self
+            .eh_frame
+            .fde_for_address(&self.bases, addr, EhFrame::cie_from_offset)
+            .ok()?;
+        let mut context = UnwindContext::new();
+        let row = fde
+            .unwind_info_for_address(&self.eh_frame, &self.bases, &mut context, addr)
+            .ok()?;
+
+        let (cfa_register, cfa_offset) = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => (register.0, *offset),
+            CfaRule::Expression(_) => return None,
+        };
+        let saved_at = |register| match row.register(gimli::Register(register)) {
+            RegisterRule::Offset(offset) => Some(offset),
+            _ => None,
+        };
+        // the stack pointer of the caller is always the CFA
+        if cfa_register != stack_pointer && cfa_register != frame_pointer {
+            return None;
+        }
+
+        let return_address_register = fde.cie().return_address_register().0;
+        Some(UnwindRule {
+            cfa_register,
+            cfa_offset,
+            return_address: saved_at(return_address_register),
+            frame_pointer: saved_at(frame_pointer),
+            return_address_register,
+        })
+    }
+}
+
+/// Walks the stack of the stopped program from the current function to `_start`, returns
+/// up to `limit` frames. Call frame info is used where it exists, frame pointers are followed otherwise.
+pub fn backtrace<T: Target + ?Sized>(
+    debugger: &Debugger<T>,
+    limit: usize,
+) -> Result<Vec<Frame>, String> {
+    let arch = debugger.arch();
+    let registers = arch.unwind_registers();
+    let (sp_number, sp_name) = registers.stack_pointer;
+    let (fp_number, fp_name) = registers.frame_pointer;
+    let pointer_size = arch.pointer_size();
+
+    let read = |name| debugger.get_register_value(&RegSelector::Name(name));
+    let read_pointer = |addr: u64| -> Option<u64> {
+        let bytes = debugger.read_bytes(addr, pointer_size).ok()?;
+        (bytes.len() == pointer_size).then(|| {
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, &byte| value << 8 | byte as u64)
+        })
+    };
+
+    // values of registers by DWARF number in the frame being unwound
+    let mut values = HashMap::from([(sp_number, read(sp_name)?), (fp_number, read(fp_name)?)]);
+    if let Some((number, name)) = registers.link_register {
+        values.insert(number, read(name)?);
+    }
+
+    let mut frames = Vec::new();
+    let mut pc = debugger.get_pc()?;
+    while frames.len() < limit.min(MAX_FRAMES) {
+        // return address points after the call, which may be the start of the next function
+        let lookup_addr = if frames.is_empty() { pc } else { pc - 1 };
+        let rule = debugger
+            .call_frames(lookup_addr)
+            .and_then(|(frames, bias)| frames.rule(lookup_addr - bias, sp_number, fp_number));
+
+        let (cfa, return_address, frame_pointer) = match rule {
+            Some(rule) => {
+                let base = *values
+                    .get(&rule.cfa_register)
+                    .ok_or("unknown CFA register")?;
+                let cfa = base.wrapping_add(rule.cfa_offset as u64);
+                let return_address = match rule.return_address {
+                    Some(offset) => read_pointer(cfa.wrapping_add(offset as u64)),
+                    None => values.get(&rule.return_address_register).copied(),
+                };
+                let frame_pointer = match rule.frame_pointer {
+                    Some(offset) => read_pointer(cfa.wrapping_add(offset as u64)),
+                    None => values.get(&fp_number).copied(),
+                };
+                (cfa, return_address, frame_pointer)
+            }
+            // frame pointer points at the saved frame pointer of the caller followed by the return address
+            None => {
+                let fp = values.get(&fp_number).copied().unwrap_or(0);
+                if fp == 0 {
+                    frames.push(Frame { pc, cfa: 0 });
+                    break;
+                }
+                let cfa = fp + 2 * pointer_size as u64;
+                (
+                    cfa,
+                    read_pointer(fp + pointer_size as u64),
+                    read_pointer(fp),
+                )
+            }
+        };
+
+        // stack grows down, so callers have higher CFA
+        if frames
+            .last()
+            .is_some_and(|callee: &Frame| cfa <= callee.cfa)
+        {
+            break;
+        }
+        frames.push(Frame { pc, cfa });
+        let return_address = match return_address {
+            Some(addr) if addr != 0 => addr,
+            _ => break,
+        };
+
+        values.clear();
+        values.insert(sp_number, cfa);
+        if let Some(fp) = frame_pointer {
+            values.insert(fp_number, fp);
+        }
+        pc = return_address;
+    }
+
+    Ok(frames)
+}